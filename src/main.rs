@@ -2,18 +2,53 @@
 
 extern crate lazy_static;
 
-use std::fs::File;
-use crate::pluma::ir::types::Type;
-use crate::pluma::token::{NomTrait, GlobalIdentifier, Identifier};
+use crate::pluma::token::{NomTrait, PlumaError};
+use crate::pluma::token::lint::identifier_warnings;
+use crate::pluma::ir::ast::SourceFile;
 use crate::pluma::ir::define::Define;
+use crate::pluma::check::Checker;
+use crate::pluma::runtime::eval::Machine;
+use crate::pluma::source::SourceMap;
 
 mod pluma;
 
+fn report(sources: &SourceMap, err: &PlumaError) {
+    match err.span() {
+        Some(span) => println!("{}: error: {:?}", sources.describe(span), err),
+        None => println!("error: {:?}", err),
+    }
+}
+
 fn main() {
-    let mut f = File::open("./examples/00_helloworld.pluma").unwrap();
+    let mut sources = SourceMap::default();
+    let file = sources.open("./examples/00_helloworld.pluma").unwrap();
     //
-    // let ori = Token::tokenize_str("i64").unwrap();
-    // let a = Type::parse(ori.as_slice(), |_|()).unwrap();
-    let res = Define::parse("fn main(var args []str){}").unwrap();
+    let tokens = match sources.get(file).tokenize() {
+        Ok(tokens) => tokens,
+        Err(err) => return report(&sources, &err),
+    };
+    for warning in identifier_warnings(tokens.as_slice()) {
+        println!("{}: warning: {:?}", sources.describe(warning.span()), warning);
+    }
+    let res = match SourceFile::parse(tokens.as_slice()) {
+        Ok(res) => res,
+        Err(err) => return report(&sources, &err),
+    };
     println!("{:?}", res);
+    if let Err(errors) = Checker::new().check(res.defines()) {
+        for err in errors {
+            println!("{}: error: {:?}", sources.describe(err.span()), err);
+        }
+        return;
+    }
+    let main = res.defines().iter().any(|define| match define {
+        Define::Function(f) => f.receiver().is_none() && f.name().name() == "main",
+        _ => false,
+    });
+    if !main {
+        return println!("error: no `fn main()` to run");
+    }
+    if let Err((panic, span)) = Machine::new(res.defines(), std::io::stdout()).call("main", Vec::new()) {
+        println!("{}: panic: {}", sources.describe(span), panic);
+    }
 }
@@ -0,0 +1,228 @@
+use crate::pluma::check::{Checker, Error, Untyped};
+use crate::pluma::ir::block::Expression;
+use crate::pluma::ir::types::{Primitive, Type};
+use crate::pluma::source::Span;
+
+impl Checker {
+    /// `[1, 2.5, x]` has the type of its first typed item, untyped literals alone are `i32` or `f64`
+    pub(super) fn array(&mut self, items: &[Expression], span: Span) -> Option<Type> {
+        let typed = items.iter().position(|x| Untyped::of(x).is_none());
+        let item = match typed {
+            Some(i) => self.expr(&items[i], true),
+            None if items.is_empty() => return self.error(Error::EmptyArray(span)),
+            None if items.iter().any(|x| matches!(Untyped::of(x), Some(lit) if lit.float)) => {
+                Some(Type::Primitive(Primitive::F64))
+            }
+            None => Some(Type::Primitive(Primitive::I32)),
+        };
+        for (_, x) in items.iter().enumerate().filter(|(i, _)| Some(*i) != typed) {
+            match &item {
+                Some(item) => self.expect(x, item),
+                None => self.expr(x, true),
+            };
+        }
+        Some(Type::Array(Box::new(item?)))
+    }
+
+    /// `[1, 2]` or `[0; n]` where an array of `item` is wanted, even an empty one
+    ///
+    /// For a `[N]T` there have to be `N` items, `n` of a repeat is a literal or a `const` then.
+    pub(super) fn expect_array(&mut self, x: &Expression, item: &Type, length: Option<usize>) {
+        let found = match x {
+            Expression::Array(items, _) => {
+                items.iter().for_each(|x| {
+                    self.expect(x, item);
+                });
+                items.len()
+            }
+            Expression::Repeat(x, n, _) => {
+                self.expect(x, item);
+                self.count(n);
+                match (length, self.length(n)) {
+                    (None, _) => return,
+                    (Some(_), Some(n)) => n,
+                    (Some(_), None) => {
+                        self.error(Error::ConstantLength(n.span()));
+                        return;
+                    }
+                }
+            }
+            _ => return,
+        };
+        match length {
+            Some(length) if length != found => {
+                self.error(Error::ArrayLength(x.span(), length, found));
+            }
+            _ => (),
+        }
+    }
+
+    /// `[x; n]`
+    pub(super) fn fill(&mut self, x: &Expression, n: &Expression) -> Option<Type> {
+        let item = self.expr(x, true);
+        self.count(n);
+        Some(Type::Array(Box::new(item?)))
+    }
+
+    // a length or an index is any integer
+    fn count(&mut self, n: &Expression) {
+        if let Some(lit) = Untyped::of(n) {
+            if !lit.float && !lit.negative {
+                return;
+            }
+        }
+        match self.expr(n, true) {
+            Some(Type::Primitive(p)) if p.is_integer() => (),
+            Some(Type::Never) | None => (),
+            Some(tp) => {
+                self.error(Error::InvalidOperand(n.span(), tp));
+            }
+        }
+    }
+
+    /// `a[i]` is an item, `a[i..j]` an array again
+    pub(super) fn index(&mut self, x: &Expression, index: &Expression) -> Option<Type> {
+        let item = match self.expr(x, true) {
+            Some(Type::Array(item)) | Some(Type::FixedArray(item, _)) => Some(*item),
+            Some(tp) => self.error(Error::NotIndexable(x.span(), tp)),
+            None => None,
+        };
+        match index {
+            Expression::Range(_, _, _) => {
+                self.expr(index, true);
+                Some(Type::Array(Box::new(item?)))
+            }
+            _ => {
+                self.count(index);
+                item
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pluma::check::Error;
+    use crate::pluma::check::tests::{check, errors};
+    use crate::pluma::ir::types::{Primitive, Type};
+
+    #[test]
+    fn arrays() {
+        for src in &[
+            "fn f() -> []f64 { [1, 2.5, 3] }",
+            "fn f(const x i64) -> []i64 { [1, x, 2] }",
+            "fn f() -> []u8 { [] }",
+            "fn f(const n size) -> []u8 { [0; n] }",
+            "fn f() -> [3]i32 { [0; 3] }",
+            "fn f(var a []i32, const i u8) -> i32 {\n    a[i] = 2\n    a[0] + a[1..2][0]\n}",
+            "fn f(var a [2][]u8) -> u8 { a[1][0] }",
+        ] {
+            assert!(check(src).is_ok(), "{} {:?}", src, check(src));
+        }
+    }
+
+    #[test]
+    fn array_errors() {
+        let src = "fn f() { var a = [] }";
+        match errors(src).as_slice() {
+            [Error::EmptyArray(span)] => assert_eq!(Some(span.start), src.find("[]")),
+            errs => panic!("{:?}", errs),
+        }
+        let src = "fn f(const x i32) -> i32 { x[0] }";
+        match errors(src).as_slice() {
+            [Error::NotIndexable(span, Type::Primitive(Primitive::I32))] => assert_eq!(Some(span.start), src.find("x[")),
+            errs => panic!("{:?}", errs),
+        }
+        let src = "fn f() -> [3]i32 { [1, 2] }";
+        match errors(src).as_slice() {
+            [Error::ArrayLength(span, 3, 2)] => assert_eq!(Some(span.start), src.find("[1")),
+            errs => panic!("{:?}", errs),
+        }
+        let src = "fn f(const n i32) -> [3]i32 { [0; n] }";
+        match errors(src).as_slice() {
+            [Error::ConstantLength(span)] => assert_eq!(Some(span.start), src.rfind('n')),
+            errs => panic!("{:?}", errs),
+        }
+        for (src, at) in &[("fn f(const a []i32) -> i32 { a[1.5] }", "1.5"), ("fn f() -> []i32 { [0; true] }", "true")] {
+            match errors(src).as_slice() {
+                [Error::InvalidOperand(span, _)] => assert_eq!(Some(span.start), src.find(at), "{}", src),
+                errs => panic!("{} {:?}", src, errs),
+            }
+        }
+        let src = "fn f() -> []i32 { [1, \"a\"] }";
+        match errors(src).as_slice() {
+            [Error::Mismatched(span, Type::Primitive(Primitive::I32), Type::String)] => assert_eq!(Some(span.start), src.find('"')),
+            errs => panic!("{:?}", errs),
+        }
+    }
+
+    #[test]
+    fn fixed_arrays() {
+        for src in &[
+            "fn sum(const xs []i32) -> i32 { xs[0] }\nfn f(const a [3]i32) -> i32 { sum(a) }",
+            "fn f() -> f64 {\n    var m [3][3]f64\n    m[1][2] = 1.5\n    m[1][2]\n}",
+            "fn f(const a [4]u8) -> []u8 { a[1..3] }",
+        ] {
+            assert!(check(src).is_ok(), "{} {:?}", src, check(src));
+        }
+        // only a call takes a `[N]T` as a `[]T`, and a `[]T` is never a `[N]T`
+        for (src, at) in &[
+            ("fn f(const a [3]i32) -> []i32 { a }", "a }"),
+            ("fn g(const a [3]i32) { }\nfn f(const a []i32) { g(a) }", "a) }"),
+            ("fn g(const a [3]i32) { }\nfn f(const a [2]i32) { g(a) }", "a) }"),
+            ("fn g(const a []i64) { }\nfn f(const a [2]i32) { g(a) }", "a) }"),
+        ] {
+            match errors(src).as_slice() {
+                [Error::Mismatched(span, _, _)] => assert_eq!(Some(span.start), src.rfind(at), "{}", src),
+                errs => panic!("{} {:?}", src, errs),
+            }
+        }
+    }
+
+    #[test]
+    fn const_lengths() {
+        assert!(check("const N size = { 3 }\nfn f() -> [N]i32 { [1, 2, 3] }").is_ok());
+        assert!(check("const N i32 = { 2 }\nconst M i32 = { N * 2 + 1 }\nfn f() { var a [M]u8 = [0; M] }").is_ok());
+        assert!(check("const N i32 = { 2 }\nfn f() { var a [N][N]u8 = [[1, 2], [3, 4]] }").is_ok());
+        let src = "const N i32 = { 2 }\nconst M i32 = { N * 2 + 1 }\nfn f() { var a [M]u8 = [1, 2] }";
+        match errors(src).as_slice() {
+            [Error::ArrayLength(span, 5, 2)] => assert_eq!(Some(span.start), src.find("[1")),
+            errs => panic!("{:?}", errs),
+        }
+    }
+
+    #[test]
+    fn cast_lengths() {
+        // what `cast::Cast` gives, `300` is an `i32` wrapping to `44` as a `u8`
+        assert!(check("const N i32 = { 300 as u8 as i32 }\nfn f() -> [44]i32 { [0; N] }").is_ok());
+        assert!(check("const N size = { -1.5 as size }\nfn f() -> [0]i32 { [0; N] }").is_ok());
+        assert!(check("const N i32 = { 2.9 as i32 + 256i64 as i8 as i32 }\nfn f(var a [N]i32) -> [2]i32 { a }").is_ok());
+        let src = "const N i8 = { 200 as i8 }\nfn f(var a [N]i32) { }";
+        match errors(src).as_slice() {
+            [Error::NotArrayLength(n)] => assert_eq!(Some(n.span().start), src.rfind("N]")),
+            errs => panic!("{:?}", errs),
+        }
+    }
+
+    #[test]
+    fn not_array_length() {
+        for src in &[
+            "var N i32 = { 3 }\nfn f(var a [N]i32) { }",
+            "const N i32 = { N + 1 }\nfn f(var a [N]i32) { }",
+            "const N i32 = { -1 }\nfn f(var a [N]i32) { }",
+            "const N f64 = { 2.0 }\nfn f(var a [N]i32) { }",
+            "fn f(var a [N]i32) { }",
+        ] {
+            match errors(src).as_slice() {
+                [Error::NotArrayLength(n)] => assert_eq!(Some(n.span().start), src.rfind("N]")),
+                errs => panic!("{} {:?}", src, errs),
+            }
+        }
+        // a local of the name is not the `const`
+        let src = "const N i32 = { 2 }\nfn f(var N i32) { var a [2]i32 = [0; N] }";
+        match errors(src).as_slice() {
+            [Error::ConstantLength(span)] => assert_eq!(Some(span.start), src.rfind('N')),
+            errs => panic!("{:?}", errs),
+        }
+    }
+}
@@ -0,0 +1,213 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+use crate::pluma::check::{Checker, names};
+use crate::pluma::ir::block::{Binary, Block, Expression, Unary};
+use crate::pluma::ir::cast::{Cast, Scalar};
+use crate::pluma::ir::commons::Value;
+use crate::pluma::ir::define::{Define, Static};
+use crate::pluma::ir::literal::Literal;
+use crate::pluma::ir::types::{Primitive, Type};
+use crate::pluma::token::GlobalIdentifier;
+use crate::pluma::token::literal::Numeric;
+
+/// value worked out before the program runs, an unsuffixed literal has no type yet
+///
+/// An untyped integer is kept in the bits of an `i64`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(super) struct Constant {
+    value: Scalar,
+    tp: Option<Primitive>,
+}
+
+impl Constant {
+    // the same value as a `p`, `None` when it is no `p` at all
+    fn adopt(self, p: Primitive) -> Option<Constant> {
+        let value = match (self.tp, self.value) {
+            (Some(tp), value) if tp == p => value,
+            (Some(_), _) => return None,
+            (None, Scalar::Integer(bits)) if p.is_integer() => Scalar::wrap(p, i128::from(bits as i64)),
+            (None, Scalar::Integer(bits)) if p.is_float() => Scalar::Float(bits as i64 as f64),
+            (None, Scalar::Float(x)) if p.is_float() => Scalar::Float(x),
+            (None, _) => return None,
+        };
+        Some(Constant { value: rounded(p, value), tp: Some(p) })
+    }
+
+    // still untyped
+    fn float(self) -> Option<Constant> {
+        Some(Constant { value: Scalar::Float(self.integer()? as f64), tp: None })
+    }
+
+    fn integer(&self) -> Option<i128> {
+        self.value.integer(self.tp.unwrap_or(Primitive::I64))
+    }
+}
+
+// a `f32` keeps what fits in it
+fn rounded(p: Primitive, value: Scalar) -> Scalar {
+    match value {
+        Scalar::Float(x) if p == Primitive::F32 => Scalar::Float(f64::from(x as f32)),
+        value => value,
+    }
+}
+
+// `{ x }` with nothing before its value
+fn only(b: &Block) -> Option<&Expression> {
+    match b.statements() {
+        [_] => b.value(),
+        _ => None,
+    }
+}
+
+// literals, other constants, arithmetic and casts of them, wrapping as the operators do when the program runs
+fn fold(x: &Expression, constant: &mut dyn FnMut(Vec<String>) -> Option<Constant>) -> Option<Constant> {
+    match x {
+        Expression::Literal(l) => match &l.node {
+            Literal::Numeric(Numeric::Integer(n, p)) => match p {
+                Some(p) => Some(Constant { value: Scalar::wrap(*p, i128::from(*n)), tp: Some(*p) }),
+                None => Some(Constant { value: Scalar::Integer(*n), tp: None }),
+            },
+            Literal::Numeric(Numeric::Float(x, p)) => Some(Constant { value: rounded(p.unwrap_or(Primitive::F64), Scalar::Float(*x)), tp: *p }),
+            _ => None,
+        },
+        Expression::Identifier(x) => constant(vec![x.name().to_string()]),
+        Expression::Path(x) => constant(names(x)),
+        Expression::Block(b) => fold(only(b)?, constant),
+        Expression::Unary(Unary::Neg, x, _) => {
+            let x = fold(x, constant)?;
+            let value = match x.value {
+                Scalar::Integer(_) => Scalar::wrap(x.tp.unwrap_or(Primitive::I64), x.integer()?.wrapping_neg()),
+                Scalar::Float(value) => Scalar::Float(-value),
+            };
+            Some(Constant { value, ..x })
+        }
+        // an unsuffixed literal is cast as the `i32` or `f64` the checker takes it for
+        Expression::Cast(x, tp, _) => {
+            let x = fold(x, constant)?;
+            let from = match (x.tp, x.value) {
+                (Some(p), _) => p,
+                (None, Scalar::Integer(_)) => Primitive::I32,
+                (None, Scalar::Float(_)) => Primitive::F64,
+            };
+            let to = match &tp.node {
+                Type::Primitive(p) => *p,
+                _ => return None,
+            };
+            Some(Constant { value: Cast::new(from, to)?.apply(x.adopt(from)?.value), tp: Some(to) })
+        }
+        Expression::Binary(op, a, b, _) => {
+            let (a, b) = (fold(a, constant)?, fold(b, constant)?);
+            let (a, b) = match (a.tp, b.tp) {
+                (Some(p), _) | (None, Some(p)) => (a.adopt(p)?, b.adopt(p)?),
+                // `1 + 2.5` is a float
+                (None, None) => match (a.value, b.value) {
+                    (Scalar::Integer(_), Scalar::Float(_)) => (a.float()?, b),
+                    (Scalar::Float(_), Scalar::Integer(_)) => (a, b.float()?),
+                    _ => (a, b),
+                },
+            };
+            let tp = a.tp.or(b.tp);
+            let value = match (a.value, b.value) {
+                (Scalar::Integer(_), Scalar::Integer(_)) => {
+                    let (x, y) = (a.integer()?, b.integer()?);
+                    let value = match op {
+                        Binary::Add => x.wrapping_add(y),
+                        Binary::Sub => x.wrapping_sub(y),
+                        Binary::Mul => x.wrapping_mul(y),
+                        Binary::Div => x.checked_div(y)?,
+                        Binary::Mod => x.checked_rem(y)?,
+                        Binary::Pow => x.wrapping_pow(u32::try_from(y).ok()?),
+                        _ => return None,
+                    };
+                    Scalar::wrap(tp.unwrap_or(Primitive::I64), value)
+                }
+                (Scalar::Float(x), Scalar::Float(y)) => {
+                    let value = match op {
+                        Binary::Add => x + y,
+                        Binary::Sub => x - y,
+                        Binary::Mul => x * y,
+                        Binary::Div => x / y,
+                        Binary::Mod => x % y,
+                        Binary::Pow => x.powf(y),
+                        _ => return None,
+                    };
+                    rounded(tp.unwrap_or(Primitive::F64), Scalar::Float(value))
+                }
+                _ => return None,
+            };
+            Some(Constant { value, tp })
+        }
+        _ => None,
+    }
+}
+
+// `const` statics by name, each worked out once
+struct Folding<'d> {
+    statics: HashMap<Vec<String>, &'d Static>,
+    values: HashMap<Vec<String>, Option<Constant>>,
+}
+
+impl<'d> Folding<'d> {
+    fn constant(&mut self, path: Vec<String>) -> Option<Constant> {
+        if let Some(value) = self.values.get(&path) {
+            return *value;
+        }
+        let s = *self.statics.get(&path)?;
+        // `const N i32 = { N + 1 }` has no value
+        self.values.insert(path.clone(), None);
+        let value = match &s.declare().node {
+            Type::Primitive(p) => fold(only(s.define())?, &mut |path| self.constant(path)).and_then(|x| x.adopt(*p)),
+            _ => None,
+        };
+        self.values.insert(path, value);
+        value
+    }
+}
+
+impl Checker {
+    /// values of the `const` statics made of literals, other constants, arithmetic and casts
+    pub(super) fn constants(&mut self, defines: &[Define]) {
+        let mut statics = HashMap::new();
+        for define in defines {
+            match define {
+                Define::Static(s) if *s.value() == Value::Constant => {
+                    statics.entry(vec![s.name().name().to_string()]).or_insert(s);
+                }
+                _ => (),
+            }
+        }
+        let paths = statics.keys().cloned().collect::<Vec<_>>();
+        let mut folding = Folding { statics, values: HashMap::new() };
+        for path in paths {
+            if let Some(value) = folding.constant(path.clone()) {
+                self.constants.insert(path, value);
+            }
+        }
+    }
+
+    // a local of the name hides the `const`
+    fn constant(&self, path: Vec<String>) -> Option<Constant> {
+        match path.as_slice() {
+            [name] if self.scopes.iter().any(|scope| scope.contains_key(name)) => None,
+            _ => self.constants.get(&path).copied(),
+        }
+    }
+
+    /// `n` of `[x; n]` when it is known before the program runs
+    pub(super) fn length(&self, n: &Expression) -> Option<usize> {
+        let n = fold(n, &mut |path| self.constant(path))?;
+        match n.value {
+            Scalar::Integer(_) if !matches!(n.tp, Some(p) if !p.is_integer()) => {
+                let n = n.integer()?;
+                if n >= 0 { Some(n as usize) } else { None }
+            }
+            _ => None,
+        }
+    }
+
+    /// `N` of `[N]T`
+    pub(super) fn const_length(&self, n: &GlobalIdentifier) -> Option<usize> {
+        self.length(&Expression::Path(n.clone()))
+    }
+}
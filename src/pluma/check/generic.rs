@@ -0,0 +1,297 @@
+use std::collections::HashMap;
+
+use crate::pluma::check::{Checker, Error, Untyped, names};
+use crate::pluma::ir::block::Expression;
+use crate::pluma::ir::types::{self, Generic, Parameter, Type};
+use crate::pluma::source::{Span, Spanned};
+
+/// `tp` with every `Param` found in `bindings` replaced
+pub fn subst(tp: &Type, bindings: &HashMap<String, Type>) -> Type {
+    match tp {
+        Type::Param(p) => bindings.get(p.name()).cloned().unwrap_or_else(|| tp.clone()),
+        Type::Named(path, args) => Type::Named(path.clone(), args.iter().map(|x| subst(x, bindings)).collect()),
+        Type::Function(f) => Type::Function(Box::new(subst_function(f, bindings))),
+        Type::Array(item) => Type::Array(Box::new(subst(item, bindings))),
+        Type::FixedArray(item, n) => Type::FixedArray(Box::new(subst(item, bindings)), *n),
+        Type::ConstArray(item, n) => Type::ConstArray(Box::new(subst(item, bindings)), n.clone()),
+        Type::Range(item) => Type::Range(Box::new(subst(item, bindings))),
+        Type::Void | Type::Primitive(_) | Type::String | Type::Never => tp.clone(),
+    }
+}
+
+pub fn subst_function(f: &types::Function, bindings: &HashMap<String, Type>) -> types::Function {
+    types::Function {
+        params: f.params.iter()
+            .map(|param| Parameter::new(param.value().clone(), param.declare().map(|tp| subst(tp, bindings))))
+            .collect(),
+        ret: f.ret.map(|tp| subst(tp, bindings)),
+    }
+}
+
+pub fn has_params(tp: &Type) -> bool {
+    match tp {
+        Type::Param(_) => true,
+        Type::Named(_, args) => args.iter().any(has_params),
+        Type::Function(f) => f.params.iter().any(|param| has_params(param.declare())) || has_params(&f.ret),
+        Type::Array(item) | Type::FixedArray(item, _) | Type::ConstArray(item, _) | Type::Range(item) => has_params(item),
+        Type::Void | Type::Primitive(_) | Type::String | Type::Never => false,
+    }
+}
+
+/// match `found` against `pattern`, binding its params on the way
+pub fn unify(pattern: &Type, found: &Type, bindings: &mut HashMap<String, Type>) -> bool {
+    match (pattern, found) {
+        (Type::Param(p), found) => match bindings.get(p.name()) {
+            Some(bound) => bound == found,
+            None => {
+                bindings.insert(p.name().to_string(), found.clone());
+                true
+            }
+        },
+        (Type::Named(a, xs), Type::Named(b, ys)) => {
+            a == b && xs.len() == ys.len() && xs.iter().zip(ys).all(|(x, y)| unify(x, y, bindings))
+        }
+        (Type::Function(a), Type::Function(b)) => {
+            a.params.len() == b.params.len()
+                && a.params.iter().zip(&b.params).all(|(x, y)| {
+                    x.value() == y.value() && unify(x.declare(), y.declare(), bindings)
+                })
+                && unify(&a.ret, &b.ret, bindings)
+        }
+        (Type::Array(a), Type::Array(b)) | (Type::Range(a), Type::Range(b)) => unify(a, b, bindings),
+        (Type::FixedArray(a, n), Type::FixedArray(b, m)) => n == m && unify(a, b, bindings),
+        (a, b) => a == b,
+    }
+}
+
+/// params of `pattern` a value of it binds when the value has to be a `want`, none if it never is one
+pub fn seed(pattern: &Type, want: Option<&Type>) -> HashMap<String, Type> {
+    let mut bindings = HashMap::new();
+    match want {
+        Some(want) if unify(pattern, want, &mut bindings) => bindings,
+        _ => HashMap::new(),
+    }
+}
+
+impl Checker {
+    /// `T` written in a generic define is read as `Named`, this turns it into `Param`
+    pub(super) fn norm(&self, tp: &Type) -> Type {
+        match tp {
+            Type::Named(path, args) => match path.path() {
+                [name] if args.is_empty() && self.params.iter().any(|g| g.name.name() == name.name()) => {
+                    Type::Param(name.clone())
+                }
+                _ => Type::Named(path.clone(), args.iter().map(|x| self.norm(x)).collect()),
+            },
+            Type::Function(f) => Type::Function(Box::new(self.norm_function(f))),
+            Type::Array(item) => Type::Array(Box::new(self.norm(item))),
+            Type::FixedArray(item, n) => Type::FixedArray(Box::new(self.norm(item)), *n),
+            Type::ConstArray(item, n) => match self.const_length(n) {
+                Some(length) => Type::FixedArray(Box::new(self.norm(item)), length),
+                None => Type::ConstArray(Box::new(self.norm(item)), n.clone()),
+            },
+            Type::Range(item) => Type::Range(Box::new(self.norm(item))),
+            Type::Void | Type::Primitive(_) | Type::String | Type::Param(_) | Type::Never => tp.clone(),
+        }
+    }
+
+    pub(super) fn norm_function(&self, f: &types::Function) -> types::Function {
+        types::Function {
+            params: f.params.iter()
+                .map(|param| Parameter::new(param.value().clone(), param.declare().map(|tp| self.norm(tp))))
+                .collect(),
+            ret: f.ret.map(|tp| self.norm(tp)),
+        }
+    }
+
+    // `Box<T>` of `fn (ref b Box<T>) get() -> T` brings `T` with the bound `Box` gives it
+    pub(super) fn receiver_generics(&self, receiver: &Type) -> Option<Vec<Generic>> {
+        let (path, args) = match receiver {
+            Type::Named(path, args) => (path, args),
+            _ => return None,
+        };
+        let structure = self.structs.get(&names(path))?;
+        if structure.generics.len() != args.len() {
+            return None;
+        }
+        args.iter().zip(&structure.generics)
+            .map(|(arg, generic)| match arg {
+                Type::Named(name, args) if args.is_empty() && name.path().len() == 1 => Some(Generic {
+                    name: name.path()[0].clone(),
+                    bound: generic.bound.clone(),
+                }),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // generic function `f` names, with its params and type
+    pub(super) fn template(&self, f: &Expression) -> Option<(Vec<Generic>, Type)> {
+        let key = match f {
+            Expression::Identifier(x) if self.scopes.iter().all(|scope| !scope.contains_key(x.name())) => {
+                vec![x.name().to_string()]
+            }
+            Expression::Path(x) => names(x),
+            _ => return None,
+        };
+        let generics = self.templates.get(&key)?;
+        let tp = self.globals.get(&key)?.tp.clone()?;
+        Some((generics.clone(), tp))
+    }
+
+    /// `<i32, f64>` given to the params `generics`
+    pub(super) fn explicit(&mut self, generics: &[Generic], args: &[Type], span: Span) -> Option<HashMap<String, Type>> {
+        if generics.len() != args.len() {
+            self.error(Error::GenericCount(span, generics.len(), args.len()));
+            return None;
+        }
+        let args = args.iter().map(|x| self.norm(x)).collect::<Vec<_>>();
+        args.iter().for_each(|x| self.resolve(x));
+        let bindings = generics.iter()
+            .zip(args)
+            .map(|(g, x)| (g.name.name().to_string(), x))
+            .collect();
+        self.bounds(generics, &bindings, span);
+        Some(bindings)
+    }
+
+    /// params of `generics` taken from the types of the expressions given where `pairs` want them
+    ///
+    /// An expression used to bind is checked here already, the flags tell which ones.
+    /// Arguments of a call pass a `[N]T` for a `[]T`, `call` says whether these are.
+    /// The params in `bindings` are known before, from the type the value has to be.
+    pub(super) fn bind(&mut self, generics: &[Generic], mut bindings: HashMap<String, Type>, pairs: &[(Type, &Expression)], call: bool, span: Span) -> Option<(HashMap<String, Type>, Vec<bool>)> {
+        let mut checked = vec![false; pairs.len()];
+        let mut failed = false;
+        // typed expressions go first, an untyped literal then takes the type they bound
+        let mut order = (0..pairs.len()).collect::<Vec<_>>();
+        order.sort_by_key(|i| Untyped::of(pairs[*i].1).is_some());
+        for i in order {
+            let (pattern, x) = &pairs[i];
+            let want = subst(pattern, &bindings);
+            if !has_params(&want) {
+                continue;
+            }
+            checked[i] = true;
+            if let Some(found) = self.expr(x, true) {
+                let found = match (&want, found) {
+                    (Type::Array(_), Type::FixedArray(item, _)) if call => Type::Array(item),
+                    (_, found) => found,
+                };
+                if !unify(&want, &found, &mut bindings) {
+                    self.error(Error::Mismatched(x.span(), want, found));
+                    failed = true;
+                }
+            }
+        }
+        // a param left open by a mismatch is not worth another error
+        if let Some(g) = generics.iter().find(|g| !bindings.contains_key(g.name.name())) {
+            if !failed {
+                self.error(Error::CannotInfer(span, g.name.clone()));
+            }
+            return None;
+        }
+        self.bounds(generics, &bindings, span);
+        Some((bindings, checked))
+    }
+
+    fn bounds(&mut self, generics: &[Generic], bindings: &HashMap<String, Type>, span: Span) {
+        for g in generics {
+            if let (Some(bound), Some(tp)) = (&g.bound, bindings.get(g.name.name())) {
+                if let Err(missing) = self.implements(tp, bound) {
+                    let interface = Type::Named(bound.clone(), Vec::new());
+                    self.error(Error::NotImplemented(span, tp.clone(), interface, missing));
+                }
+            }
+        }
+    }
+
+    /// `max<i32>`
+    pub(super) fn instance(&mut self, f: &Expression, args: &[Spanned<Type>], span: Span) -> Option<Type> {
+        match self.template(f) {
+            Some((generics, tp)) => {
+                let args = args.iter().map(|x| x.node.clone()).collect::<Vec<_>>();
+                let bindings = self.explicit(&generics, &args, span)?;
+                Some(subst(&tp, &bindings))
+            }
+            None => {
+                self.expr(f, true)?;
+                self.error(Error::GenericCount(span, 0, args.len()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pluma::check::Error;
+    use crate::pluma::check::tests::{check, errors};
+    use crate::pluma::ir::types::{Primitive, Type};
+
+    const MAX: &str = "fn max<T Comparable>(a T, b T) -> T { if a > b { a } else { b } }\n";
+    const BOX: &str = "struct Box<T> { v T }\nfn (ref b Box<T>) get() -> T { b.v }\n";
+
+    #[test]
+    fn inferred() {
+        for body in &[
+            "fn f(a f64) -> f64 { max(a, 2) }",
+            "fn f() -> i32 { max(1, 2) }",
+            "fn f() { var c i64 = max(1, 2) }",
+            "fn f() -> u8 { max(1, 2) }",
+            "fn f() { var c = max<i64>(1, 2) }",
+            "fn f() { var b Box<i64> = Box{v: 1} }",
+            "fn f() -> f64 { Box{v: 2.5}.get() }",
+            "fn f() -> i32 { Box<i32>{v: 1}.get() }",
+        ] {
+            let src = format!("{}{}{}", MAX, BOX, body);
+            assert!(check(&src).is_ok(), "{} {:?}", body, check(&src));
+        }
+    }
+
+    #[test]
+    fn rejected() {
+        let src = format!("{}fn f(a i32, b i64) -> i32 {{ max(a, b) }}", MAX);
+        match errors(&src).as_slice() {
+            [Error::Mismatched(span, Type::Primitive(Primitive::I32), Type::Primitive(Primitive::I64))] => {
+                assert_eq!(Some(span.start), src.rfind('b'))
+            }
+            errs => panic!("{:?}", errs),
+        }
+        // the wanted type binds first, the argument is then the wrong one
+        let src = format!("{}fn f(a i32) {{ var c i64 = max(a, 2) }}", MAX);
+        match errors(&src).as_slice() {
+            [Error::Mismatched(_, Type::Primitive(Primitive::I64), Type::Primitive(Primitive::I32))] => (),
+            errs => panic!("{:?}", errs),
+        }
+        let src = format!("{}fn f(a bool) -> bool {{ max(a, a) }}", MAX);
+        match errors(&src).as_slice() {
+            [Error::NotImplemented(_, Type::Primitive(Primitive::Bool), _, _)] => (),
+            errs => panic!("{:?}", errs),
+        }
+        let src = format!("{}fn f() {{ var c = max<i32, i32>(1, 2) }}", MAX);
+        match errors(&src).as_slice() {
+            [Error::GenericCount(_, 1, 2)] => (),
+            errs => panic!("{:?}", errs),
+        }
+        let src = "fn none<T>() -> i32 { 0 }\nfn f() -> i32 { none() }";
+        match errors(src).as_slice() {
+            [Error::CannotInfer(_, t)] => assert_eq!(t.name(), "T"),
+            errs => panic!("{:?}", errs),
+        }
+        let src = format!("{}fn f() {{ var m = max }}", MAX);
+        match errors(&src).as_slice() {
+            [Error::CannotInfer(_, t)] => assert_eq!(t.name(), "T"),
+            errs => panic!("{:?}", errs),
+        }
+        let src = "struct P { }\nfn (ref p P) get<T>() { }";
+        match errors(src).as_slice() {
+            [Error::GenericMethod(name)] => assert_eq!(name.name(), "get"),
+            errs => panic!("{:?}", errs),
+        }
+        let src = "fn f<T Missing>(a T) { }";
+        match errors(src).as_slice() {
+            [Error::UndefinedType(bound)] => assert_eq!(bound.path()[0].name(), "Missing"),
+            errs => panic!("{:?}", errs),
+        }
+    }
+}
@@ -0,0 +1,1661 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::pluma::ir::ast::SourceFile;
+use crate::pluma::ir::block::{Binary, Block, Declare, Expression, Loop, Statement, Unary};
+use crate::pluma::ir::cast::Cast;
+use crate::pluma::ir::commons::Value;
+use crate::pluma::ir::define::Define;
+use crate::pluma::ir::literal::Literal;
+use crate::pluma::ir::types::{self, Generic, Primitive, Type};
+use crate::pluma::source::{Span, Spanned};
+use crate::pluma::token::{GlobalIdentifier, Identifier, parse_str};
+use crate::pluma::token::literal::{self, Numeric};
+
+mod array;
+mod constant;
+mod generic;
+mod pattern;
+
+// `None` type is a name whose declaration already failed to check, using it reports nothing more
+#[derive(Debug, Clone)]
+struct Local {
+    value: Value,
+    tp: Option<Type>,
+}
+
+/// Type checker over the defines of a file
+///
+/// Errors are collected instead of stopping at the first one,
+/// an expression which failed has no type so that the same mistake is reported once.
+#[derive(Debug, Default)]
+pub struct Checker {
+    globals: HashMap<Vec<String>, Local>,
+    structs: HashMap<Vec<String>, Structure>,
+    // methods of structs by the receiver type, and of interfaces
+    methods: HashMap<Vec<String>, Vec<(Identifier, types::Function)>>,
+    interfaces: HashMap<Vec<String>, Vec<(Identifier, types::Function)>>,
+    // params of generic functions, their types in `globals` use them
+    templates: HashMap<Vec<String>, Vec<Generic>>,
+    // params of the function or struct being checked
+    params: Vec<Generic>,
+    scopes: Vec<HashMap<String, Local>>,
+    loops: Vec<Breaks>,
+    // return type of the function being checked
+    returns: Option<Type>,
+    // `const` statics known before the program runs
+    constants: HashMap<Vec<String>, constant::Constant>,
+    errors: Vec<Error>,
+}
+
+/// what the checker finds wrong, every one of them at a place in the source
+#[derive(Debug)]
+pub enum Error {
+    Undefined(Identifier),
+    UndefinedPath(GlobalIdentifier),
+    /// expected, found
+    Mismatched(Span, Type, Type),
+    /// `if` branches of a used value, then and else
+    BranchMismatch(Span, Type, Type),
+    NotCallable(Span, Type),
+    /// expected, found
+    ArgumentCount(Span, usize, usize),
+    InvalidOperand(Span, Type),
+    NoField(Identifier, Type),
+    UndefinedType(GlobalIdentifier),
+    DuplicateField(Identifier),
+    /// a second struct, interface, function or static of the name, at the second one
+    DuplicateDefine(Identifier),
+    /// struct literal leaving these fields out
+    MissingFields(Span, Vec<Identifier>),
+    /// receiver of a method has to be a struct
+    InvalidReceiver(Identifier, Type),
+    /// the type, the interface and what it lacks
+    NotImplemented(Span, Type, Type, Vec<MethodError>),
+    AssignConstant(Span),
+    NotAssignable(Span),
+    VoidValue(Span),
+    NotIterable(Span, Type),
+    /// `break` or `continue` with no loop around
+    OutsideLoop(Span),
+    /// `break` with a value out of a loop that may also end by itself
+    BreakValue(Span),
+    /// `return` in a static initializer
+    OutsideFunction(Span),
+    /// a path through a function with a return type ends without a value
+    MissingReturn(Span),
+    /// type arguments given to a generic function or struct, expected, found
+    GenericCount(Span, usize, usize),
+    /// param of a generic function the arguments leave open
+    CannotInfer(Span, Identifier),
+    /// methods take the params of their receiver only
+    GenericMethod(Identifier),
+    /// `is` a value of the first type is never the second
+    ImpossibleTest(Span, Type, Type),
+    /// `is { }` arms leaving these types out, none listed when only `_` would do
+    NotExhaustive(Span, Vec<Type>),
+    /// `as` from the first type to the second means nothing
+    InvalidCast(Span, Type, Type),
+    /// `[]` with no type around it to take
+    EmptyArray(Span),
+    /// items of a literal for `[N]T`, expected, found
+    ArrayLength(Span, usize, usize),
+    /// `n` of `[x; n]` for a `[N]T` is not known before the program runs
+    ConstantLength(Span),
+    /// `N` of `[N]T` is no integer `const` the checker can work out
+    NotArrayLength(GlobalIdentifier),
+    NotIndexable(Span, Type),
+    /// literal the type has no room for, like `300` for a `u8`, `128i8` or `1e39` for a `f32`
+    LiteralOutOfRange(Span, Type),
+}
+
+impl Error {
+    pub fn span(&self) -> Span {
+        match self {
+            Error::Undefined(x)
+            | Error::NoField(x, _)
+            | Error::DuplicateField(x)
+            | Error::DuplicateDefine(x)
+            | Error::InvalidReceiver(x, _)
+            | Error::GenericMethod(x) => x.span(),
+            Error::UndefinedPath(x)
+            | Error::UndefinedType(x)
+            | Error::NotArrayLength(x) => x.span(),
+            Error::Mismatched(span, _, _)
+            | Error::BranchMismatch(span, _, _)
+            | Error::NotCallable(span, _)
+            | Error::ArgumentCount(span, _, _)
+            | Error::InvalidOperand(span, _)
+            | Error::MissingFields(span, _)
+            | Error::NotImplemented(span, _, _, _)
+            | Error::AssignConstant(span)
+            | Error::NotAssignable(span)
+            | Error::VoidValue(span)
+            | Error::NotIterable(span, _)
+            | Error::OutsideLoop(span)
+            | Error::BreakValue(span)
+            | Error::OutsideFunction(span)
+            | Error::MissingReturn(span)
+            | Error::GenericCount(span, _, _)
+            | Error::CannotInfer(span, _)
+            | Error::ImpossibleTest(span, _, _)
+            | Error::NotExhaustive(span, _)
+            | Error::InvalidCast(span, _, _)
+            | Error::EmptyArray(span)
+            | Error::ArrayLength(span, _, _)
+            | Error::ConstantLength(span)
+            | Error::NotIndexable(span, _)
+            | Error::LiteralOutOfRange(span, _) => *span,
+        }
+    }
+}
+
+/// why a type does not implement an interface
+#[derive(Debug)]
+pub enum MethodError {
+    /// method of the interface
+    Missing(Identifier),
+    /// method of the type, then the signature the interface wants and the one it has
+    Mismatched(Identifier, Box<types::Function>, Box<types::Function>),
+}
+
+// fields and methods of a generic struct use its params, `Box<i32>` gets them replaced
+#[derive(Debug, Clone)]
+struct Structure {
+    path: GlobalIdentifier,
+    generics: Vec<Generic>,
+    fields: Vec<(Identifier, Type)>,
+}
+
+const COMPARABLE: &str = "Comparable";
+const CLOSER: &str = "Closer";
+
+// interfaces every file sees, in Pluma itself
+const PRELUDE: &str = "interface Closer {
+    fn close()
+}";
+
+fn names(path: &GlobalIdentifier) -> Vec<String> {
+    path.path().iter().map(|x| x.name().to_string()).collect()
+}
+
+fn bindings(generics: &[Generic], args: &[Type]) -> HashMap<String, Type> {
+    generics.iter()
+        .zip(args)
+        .map(|(g, x)| (g.name.name().to_string(), x.clone()))
+        .collect()
+}
+
+// a loop being checked, `value` is the type of the first `break` in it
+#[derive(Debug)]
+struct Breaks {
+    forever: bool,
+    value: Option<Type>,
+}
+
+// unsuffixed numeric literal, it is whatever number type the other side wants
+//
+// `value` is kept for a lone integer literal, `300` fits no `u8`,
+// and `real` for a lone float literal, `1e39` fits no `f32`.
+#[derive(Debug, Copy, Clone)]
+struct Untyped {
+    float: bool,
+    negative: bool,
+    value: Option<u64>,
+    real: Option<f64>,
+}
+
+impl Untyped {
+    fn of(x: &Expression) -> Option<Untyped> {
+        match x {
+            Expression::Literal(l) => match &l.node {
+                Literal::Numeric(Numeric::Integer(value, None)) => {
+                    Some(Untyped { float: false, negative: false, value: Some(*value), real: None })
+                }
+                Literal::Numeric(Numeric::Float(real, None)) => {
+                    Some(Untyped { float: true, negative: false, value: None, real: Some(*real) })
+                }
+                _ => None,
+            },
+            // `- -1` is still no unsigned, but its value is not checked
+            Expression::Unary(Unary::Neg, x, _) => Untyped::of(x).map(|x| {
+                let value = if x.negative { None } else { x.value };
+                Untyped { negative: true, value, ..x }
+            }),
+            Expression::Binary(Binary::Add | Binary::Sub | Binary::Mul | Binary::Div | Binary::Mod | Binary::Pow, a, b, _) => {
+                let (a, b) = (Untyped::of(a)?, Untyped::of(b)?);
+                Some(Untyped { float: a.float || b.float, negative: a.negative || b.negative, value: None, real: None })
+            }
+            _ => None,
+        }
+    }
+    // one of the values kept to check against the range of a type
+    fn known(&self) -> bool {
+        self.value.is_some() || self.real.is_some()
+    }
+    // a number of `tp` whatever its value
+    fn numeric(&self, tp: &Type) -> bool {
+        match tp {
+            Type::Primitive(p) => p.is_float() || p.is_integer() && !self.float,
+            _ => false,
+        }
+    }
+    fn fits(&self, tp: &Type) -> bool {
+        match tp {
+            Type::Primitive(Primitive::F32) => !matches!(self.real, Some(real) if real > f64::from(f32::MAX)),
+            Type::Primitive(p) if p.is_float() => true,
+            Type::Primitive(p) if p.is_integer() => !self.float && match self.value {
+                Some(value) => literal::fits(value, self.negative, *p),
+                None => p.is_signed() || !self.negative,
+            },
+            _ => false,
+        }
+    }
+}
+
+// the expression a block evaluates to, if any
+fn tail(x: &Expression) -> &Expression {
+    match x {
+        Expression::Block(b) => match b.value() {
+            Some(x) => tail(x),
+            None => x,
+        },
+        _ => x,
+    }
+}
+
+// `if` with no `else` or a loop which ends by itself, the paths through it may only `return`
+fn valueless(x: &Expression) -> bool {
+    match x {
+        Expression::If(_, _, None, _) => true,
+        Expression::If(_, _, Some(other), _) => valueless(tail(other)),
+        Expression::For(Loop::Forever, _, _) => false,
+        Expression::For(_, _, _) => true,
+        _ => false,
+    }
+}
+
+// variable an assignment target lives in, `p` of `p.x.y`
+fn root(x: &Expression) -> Option<&Identifier> {
+    match x {
+        Expression::Identifier(x) => Some(x),
+        Expression::Path(x) => x.path().first(),
+        Expression::Field(x, _, _) | Expression::Index(x, _, _) => root(x),
+        _ => None,
+    }
+}
+
+impl Checker {
+    pub fn new() -> Self {
+        let mut checker = Checker::default();
+        // until packages can be imported `io` is built in
+        let print = parse_str::<Type>("fn(const str)").expect("prelude does not parse");
+        checker.global(&["io", "print"], print.clone());
+        checker.global(&["io", "println"], print);
+        // bound of the params `<` and friends work on, it has no methods to write
+        checker.interfaces.insert(vec![COMPARABLE.to_string()], Vec::new());
+        // `len(a)` of any array
+        checker.params = vec![parse_str::<Generic>("T").expect("prelude does not parse")];
+        let len = checker.norm(&parse_str::<Type>("fn(const []T) -> size").expect("prelude does not parse"));
+        checker.global(&["len"], len);
+        checker.templates.insert(vec!["len".to_string()], checker.params.split_off(0));
+        for define in parse_str::<SourceFile>(PRELUDE).expect("prelude does not parse").defines() {
+            if let Define::Interface(x) = define {
+                let methods = x.methods().iter()
+                    .map(|method| (method.name().clone(), method.declare().clone()))
+                    .collect();
+                checker.interfaces.insert(vec![x.name().name().to_string()], methods);
+            }
+        }
+        checker
+    }
+
+    fn global(&mut self, path: &[&str], tp: Type) {
+        let path = path.iter().map(|x| x.to_string()).collect();
+        self.globals.insert(path, Local { value: Value::Constant, tp: Some(tp) });
+    }
+
+    /// every define is visible to every other one, the order in the file does not matter
+    pub fn check(mut self, defines: &[Define]) -> Result<(), Vec<Error>> {
+        // types and values are named apart, `struct P` and `fn P` may both be there
+        let (mut types, mut values) = (HashSet::new(), HashSet::new());
+        // lengths of array types may name them
+        self.constants(defines);
+        // types first, a method receiver needs the params of its struct
+        for define in defines {
+            match define {
+                Define::Struct(s) if !self.unique(&mut types, s.name()) => (),
+                Define::Struct(s) => {
+                    self.params = s.generics().to_vec();
+                    let fields = s.fields().iter()
+                        .map(|field| (field.name().clone(), self.norm(&field.declare().node)))
+                        .collect();
+                    let structure = Structure { path: s.name().clone().into(), generics: s.generics().to_vec(), fields };
+                    self.structs.insert(vec![s.name().name().to_string()], structure);
+                    self.params.clear();
+                }
+                Define::Interface(x) if !self.unique(&mut types, x.name()) => (),
+                Define::Interface(x) => {
+                    let methods = x.methods().iter()
+                        .map(|method| (method.name().clone(), self.norm_function(method.declare())))
+                        .collect();
+                    self.interfaces.insert(vec![x.name().name().to_string()], methods);
+                }
+                Define::Function(_) | Define::Static(_) => (),
+            }
+        }
+        for define in defines {
+            match define {
+                Define::Function(f) => match f.receiver() {
+                    Some((_, receiver)) => self.method(f.name(), f.generics(), receiver.declare(), f.declare()),
+                    None if !self.unique(&mut values, f.name()) => (),
+                    None => {
+                        self.params = f.generics().to_vec();
+                        let declare = Type::Function(Box::new(self.norm_function(f.declare())));
+                        self.global(&[f.name().name()], declare);
+                        if !f.generics().is_empty() {
+                            self.templates.insert(vec![f.name().name().to_string()], f.generics().to_vec());
+                        }
+                        self.params.clear();
+                    }
+                },
+                Define::Static(s) if !self.unique(&mut values, s.name()) => (),
+                Define::Static(s) => {
+                    let local = Local { value: s.value().clone(), tp: Some(self.norm(s.declare())) };
+                    self.globals.insert(vec![s.name().name().to_string()], local);
+                }
+                Define::Struct(_) | Define::Interface(_) => (),
+            }
+        }
+        for define in defines {
+            self.define(define);
+        }
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(self.errors)
+        }
+    }
+
+    // the first define of a name counts, the ones after it are reported
+    fn unique(&mut self, seen: &mut HashSet<String>, name: &Identifier) -> bool {
+        if seen.insert(name.name().to_string()) {
+            true
+        } else {
+            self.error(Error::DuplicateDefine(name.clone()));
+            false
+        }
+    }
+
+    // methods are kept in the params of their struct, `fn (b Box<U>) get() -> U` as `fn() -> T` of `Box<T>`
+    fn method(&mut self, name: &Identifier, generics: &[Generic], receiver: &Type, declare: &types::Function) {
+        if !generics.is_empty() {
+            self.error(Error::GenericMethod(name.clone()));
+            return;
+        }
+        let path = match receiver {
+            Type::Named(path, _) => path,
+            tp => {
+                self.error(Error::InvalidReceiver(name.clone(), tp.clone()));
+                return;
+            }
+        };
+        let declare = match (self.receiver_generics(receiver), self.structs.get(&names(path))) {
+            (Some(params), Some(structure)) => {
+                let bindings = params.iter()
+                    .zip(&structure.generics)
+                    .map(|(param, generic)| (param.name.name().to_string(), Type::Param(generic.name.clone())))
+                    .collect();
+                self.params = params;
+                let declare = generic::subst_function(&self.norm_function(declare), &bindings);
+                self.params.clear();
+                declare
+            }
+            // reported when the method is checked
+            _ => declare.clone(),
+        };
+        self.methods.entry(names(path)).or_default().push((name.clone(), declare));
+    }
+
+    fn define(&mut self, define: &Define) {
+        match define {
+            Define::Function(f) => {
+                let receiver = f.receiver().map(|(_, receiver)| receiver.declare());
+                let mut params = receiver.and_then(|x| self.receiver_generics(x)).unwrap_or_default();
+                params.extend(f.generics().iter().cloned());
+                self.params = params;
+                self.generics(f.generics());
+                let declare = self.norm_function(f.declare());
+                let mut scope = f.params().iter()
+                    .zip(declare.params.iter())
+                    .map(|(name, param)| {
+                        (name.name().to_string(), Local { value: param.value().clone(), tp: Some(param.declare().node.clone()) })
+                    })
+                    .collect::<HashMap<_, _>>();
+                if let Some((name, receiver)) = f.receiver() {
+                    if let Type::Named(path, _) = &receiver.declare().node {
+                        let key = names(path);
+                        let field = match self.structs.get(&key) {
+                            // `Box<i32>` is not a receiver, every param of the struct is named instead
+                            Some(_) if self.receiver_generics(receiver.declare()).is_none() => None,
+                            Some(structure) => Some(structure.fields.iter().any(|(x, _)| x.name() == f.name().name())),
+                            None => None,
+                        };
+                        // the first method of a name is fine, the ones after it are reported
+                        let again = self.methods.get(&key)
+                            .and_then(|methods| methods.iter().find(|(x, _)| x.name() == f.name().name()))
+                            .map(|(x, _)| x.span() != f.name().span()) == Some(true);
+                        match field {
+                            None => {
+                                self.error(Error::InvalidReceiver(f.name().clone(), receiver.declare().node.clone()));
+                            }
+                            Some(true) => {
+                                self.error(Error::DuplicateField(f.name().clone()));
+                            }
+                            Some(false) if again => {
+                                self.error(Error::DuplicateField(f.name().clone()));
+                            }
+                            Some(false) => (),
+                        }
+                    }
+                    let local = Local { value: receiver.value().clone(), tp: Some(self.norm(receiver.declare())) };
+                    scope.insert(name.name().to_string(), local);
+                }
+                let ret = &declare.ret.node;
+                self.resolve(&Type::Function(Box::new(declare.clone())));
+                self.scopes.push(scope);
+                self.returns = Some(ret.clone());
+                match (ret, f.define().value()) {
+                    (Type::Void, _) => {
+                        self.block(f.define(), false);
+                    }
+                    (ret, Some(x)) if !valueless(tail(x)) => {
+                        self.block_expect(f.define(), ret);
+                    }
+                    // every path has to end in a `return` then
+                    (_, _) => {
+                        if self.block(f.define(), false) != Some(Type::Never) {
+                            self.error(Error::MissingReturn(f.define().span()));
+                        }
+                    }
+                }
+                self.returns = None;
+                self.scopes.pop();
+                self.params.clear();
+            }
+            Define::Static(s) => {
+                let tp = self.norm(s.declare());
+                self.resolve(&tp);
+                self.block_expect(s.define(), &tp);
+            }
+            Define::Struct(s) => {
+                self.params = s.generics().to_vec();
+                self.generics(s.generics());
+                let mut seen = Vec::new();
+                for field in s.fields() {
+                    if seen.contains(&field.name().name()) {
+                        self.error(Error::DuplicateField(field.name().clone()));
+                    }
+                    seen.push(field.name().name());
+                    let tp = self.norm(&field.declare().node);
+                    self.resolve(&tp);
+                }
+                self.params.clear();
+            }
+            Define::Interface(x) => {
+                let mut seen = Vec::new();
+                for method in x.methods() {
+                    if seen.contains(&method.name().name()) {
+                        self.error(Error::DuplicateField(method.name().clone()));
+                    }
+                    seen.push(method.name().name());
+                    self.resolve(&Type::Function(Box::new(self.norm_function(method.declare()))));
+                }
+            }
+        }
+    }
+
+    // a param has the methods of its bound, `Box<i32>` those of `Box<T>` with `i32` for `T`
+    fn methods_of(&self, tp: &Type) -> Vec<(Identifier, types::Function)> {
+        match tp {
+            Type::Named(path, args) => {
+                let key = names(path);
+                if let Some(methods) = self.interfaces.get(&key) {
+                    return methods.clone();
+                }
+                let methods = self.methods.get(&key).cloned().unwrap_or_default();
+                match self.structs.get(&key) {
+                    Some(structure) => {
+                        let bindings = bindings(&structure.generics, args);
+                        methods.into_iter()
+                            .map(|(name, f)| (name, generic::subst_function(&f, &bindings)))
+                            .collect()
+                    }
+                    None => methods,
+                }
+            }
+            Type::Param(p) => match self.bound(p) {
+                Some(bound) => self.methods_of(&Type::Named(bound, Vec::new())),
+                None => Vec::new(),
+            },
+            _ => Vec::new(),
+        }
+    }
+
+    fn bound(&self, param: &Identifier) -> Option<GlobalIdentifier> {
+        self.params.iter().rev()
+            .find(|g| g.name.name() == param.name())
+            .and_then(|g| g.bound.clone())
+    }
+
+    // what `<` and the like work on
+    fn ordered(&self, tp: &Type) -> bool {
+        match tp {
+            Type::Primitive(p) => *p != Primitive::Bool,
+            Type::String => true,
+            Type::Param(p) => matches!(self.bound(p), Some(bound) if names(&bound) == [COMPARABLE]),
+            _ => false,
+        }
+    }
+
+    // structural, every method of the interface with the same signature
+    fn implements(&self, tp: &Type, interface: &GlobalIdentifier) -> Result<(), Vec<MethodError>> {
+        if names(interface) == [COMPARABLE] {
+            return if self.ordered(tp) { Ok(()) } else { Err(Vec::new()) };
+        }
+        let has = self.methods_of(tp);
+        let missing = self.methods_of(&Type::Named(interface.clone(), Vec::new())).into_iter()
+            .filter_map(|(name, want)| match has.iter().find(|(x, _)| x.name() == name.name()) {
+                None => Some(MethodError::Missing(name)),
+                Some((x, found)) if *found != want => Some(MethodError::Mismatched(x.clone(), Box::new(want), Box::new(found.clone()))),
+                Some(_) => None,
+            })
+            .collect::<Vec<_>>();
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(missing)
+        }
+    }
+
+    // bounds are interfaces
+    fn generics(&mut self, generics: &[Generic]) {
+        for bound in generics.iter().filter_map(|g| g.bound.as_ref()) {
+            if !self.interfaces.contains_key(&names(bound)) {
+                self.error(Error::UndefinedType(bound.clone()));
+            }
+        }
+    }
+
+    // every named type in `tp` has to be defined, with as many arguments as it has params
+    fn resolve(&mut self, tp: &Type) {
+        match tp {
+            Type::Named(path, args) => {
+                let key = names(path);
+                let generics = match (self.structs.get(&key), self.interfaces.contains_key(&key)) {
+                    (Some(structure), _) => structure.generics.clone(),
+                    (None, true) => Vec::new(),
+                    (None, false) => {
+                        self.error(Error::UndefinedType(path.clone()));
+                        return;
+                    }
+                };
+                self.explicit(&generics, args, path.span());
+            }
+            Type::Function(f) => {
+                f.params.iter().for_each(|param| self.resolve(param.declare()));
+                self.resolve(&f.ret);
+            }
+            Type::Array(item) | Type::FixedArray(item, _) | Type::Range(item) => self.resolve(item),
+            Type::ConstArray(item, n) => {
+                self.error(Error::NotArrayLength(n.clone()));
+                self.resolve(item);
+            }
+            Type::Void | Type::Primitive(_) | Type::String | Type::Param(_) | Type::Never => (),
+        }
+    }
+
+    fn error(&mut self, err: Error) -> Option<Type> {
+        self.errors.push(err);
+        None
+    }
+
+    fn lookup(&self, name: &str) -> Option<&Local> {
+        self.scopes.iter().rev()
+            .find_map(|scope| scope.get(name))
+            .or_else(|| self.globals.get(&vec![name.to_string()]))
+    }
+
+    fn declare(&mut self, name: &Identifier, value: Value, tp: Option<Type>) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.name().to_string(), Local { value, tp });
+        }
+    }
+
+    // a statement of type `Never` makes the whole block `Never`, the rest of it is never reached
+    fn block(&mut self, b: &Block, used: bool) -> Option<Type> {
+        self.scopes.push(HashMap::new());
+        let (stmts, value) = match b.value() {
+            Some(x) if used => (&b.statements()[..b.statements().len() - 1], Some(x)),
+            _ => (b.statements(), None),
+        };
+        let never = self.statements(stmts);
+        let tp = match value {
+            Some(x) => self.expr(x, true),
+            None => Some(Type::Void),
+        };
+        self.scopes.pop();
+        if never {
+            Some(Type::Never)
+        } else {
+            tp
+        }
+    }
+
+    // like `block` but the value is checked against `tp`, so untyped literals in it take that type
+    fn block_expect(&mut self, b: &Block, tp: &Type) -> Option<Type> {
+        self.scopes.push(HashMap::new());
+        let (stmts, value) = match b.value() {
+            Some(x) => (&b.statements()[..b.statements().len() - 1], Some(x)),
+            None => (b.statements(), None),
+        };
+        let never = self.statements(stmts);
+        let checked = value.map(|x| self.expect(x, tp));
+        self.scopes.pop();
+        match checked {
+            _ if never => Some(tp.clone()),
+            Some(checked) => checked,
+            None if *tp == Type::Void => Some(Type::Void),
+            None => self.error(Error::Mismatched(b.span(), tp.clone(), Type::Void)),
+        }
+    }
+
+    // whether any of them never ends
+    fn statements(&mut self, stmts: &[Statement]) -> bool {
+        let mut never = false;
+        for stmt in stmts {
+            never |= self.statement(stmt);
+        }
+        never
+    }
+
+    fn statement(&mut self, stmt: &Statement) -> bool {
+        match stmt {
+            Statement::Declare(d) => self.local(d),
+            Statement::Assign(target, op, value, span) => self.assign(target, *op, value, *span),
+            Statement::Expression(x) => return self.expr(x, false) == Some(Type::Never),
+        }
+        false
+    }
+
+    fn local(&mut self, d: &Declare) {
+        let declare = d.declare().map(|tp| self.norm(tp));
+        if let Some(tp) = &declare {
+            self.resolve(tp);
+        }
+        let tp = match (declare, d.define()) {
+            (Some(tp), Some(x)) => {
+                self.expect(x, &tp);
+                Some(tp)
+            }
+            (Some(tp), None) => Some(tp),
+            (None, Some(x)) => match self.expr(x, true) {
+                Some(Type::Void) => self.error(Error::VoidValue(x.span())),
+                tp => tp,
+            },
+            // rejected by the parser already
+            (None, None) => None,
+        };
+        self.declare(d.name(), d.value().clone(), tp);
+    }
+
+    fn assign(&mut self, target: &Expression, op: Option<Binary>, value: &Expression, span: Span) {
+        let tp = match target {
+            Expression::Identifier(ident) => match self.lookup(ident.name()).cloned() {
+                Some(local) => {
+                    if local.value == Value::Constant {
+                        self.error(Error::AssignConstant(span));
+                    }
+                    local.tp
+                }
+                None => self.error(Error::Undefined(ident.clone())),
+            },
+            // a field is as assignable as the variable holding it
+            Expression::Path(_) | Expression::Field(_, _, _) | Expression::Index(_, _, _) => {
+                let constant = root(target)
+                    .and_then(|x| self.lookup(x.name()))
+                    .map(|x| x.value == Value::Constant) == Some(true);
+                if constant {
+                    self.error(Error::AssignConstant(span));
+                }
+                self.expr(target, true)
+            }
+            _ => self.error(Error::NotAssignable(target.span())),
+        };
+        match tp {
+            Some(tp) => {
+                if let Some(op) = op {
+                    self.operator(op, tp.clone(), span);
+                }
+                self.expect(value, &tp);
+            }
+            None => {
+                self.expr(value, true);
+            }
+        }
+    }
+
+    fn condition(&mut self, cond: &Expression) {
+        self.expect(cond, &Type::Primitive(Primitive::Bool));
+    }
+
+    /// check `x` where a value of `tp` is needed
+    pub fn expect(&mut self, x: &Expression, tp: &Type) -> Option<Type> {
+        match x {
+            Expression::Block(b) => return self.block_expect(b, tp),
+            Expression::If(cond, then, Some(other), _) => {
+                self.condition(cond);
+                let then = self.narrowed(cond, |c| c.block_expect(then, tp));
+                let other = self.expect(other, tp);
+                return then.and(other);
+            }
+            Expression::Match(x, arms, span) => return self.matching(x, arms, Some(tp), true, *span),
+            Expression::With(name, x, body, _) => {
+                self.resource(name, x);
+                let tp = self.block_expect(body, tp);
+                self.scopes.pop();
+                return tp;
+            }
+            // a generic call or struct takes its params from `tp` first, `max(1, 2)` for a `i64` is one
+            Expression::Call(f, args, span) => {
+                let found = self.call(f, args, Some(tp), *span)?;
+                return self.fits(x.span(), found, tp);
+            }
+            Expression::Struct(path, args, values, span) => {
+                let found = self.structure(path, args, values, Some(tp), *span)?;
+                return self.fits(x.span(), found, tp);
+            }
+            Expression::Array(_, _) | Expression::Repeat(_, _, _) => match tp {
+                Type::Array(item) => {
+                    self.expect_array(x, item, None);
+                    return Some(tp.clone());
+                }
+                Type::FixedArray(item, n) => {
+                    self.expect_array(x, item, Some(*n));
+                    return Some(tp.clone());
+                }
+                _ => (),
+            },
+            _ => (),
+        }
+        match Untyped::of(x) {
+            Some(lit) if lit.fits(tp) => return Some(tp.clone()),
+            Some(lit) if lit.numeric(tp) && lit.known() => {
+                return self.error(Error::LiteralOutOfRange(x.span(), tp.clone()));
+            }
+            _ => (),
+        }
+        let found = self.expr(x, true)?;
+        self.fits(x.span(), found, tp)
+    }
+
+    // a value of `found` where one of `tp` is needed
+    fn fits(&mut self, span: Span, found: Type, tp: &Type) -> Option<Type> {
+        match (found, tp) {
+            (found, tp) if found == *tp || found == Type::Never => Some(tp.clone()),
+            (found, Type::Named(interface, _)) if self.interfaces.contains_key(&names(interface)) => {
+                match self.implements(&found, interface) {
+                    Ok(()) => Some(tp.clone()),
+                    Err(missing) => self.error(Error::NotImplemented(span, found, tp.clone(), missing)),
+                }
+            }
+            (found, tp) => self.error(Error::Mismatched(span, tp.clone(), found)),
+        }
+    }
+
+    // like `expect`, but a call takes a `[N]T` where it wants a `[]T`
+    fn argument(&mut self, x: &Expression, tp: &Type) -> Option<Type> {
+        match (tp, x) {
+            (_, Expression::Array(_, _)) | (_, Expression::Repeat(_, _, _)) => self.expect(x, tp),
+            (Type::Array(item), _) => match self.expr(x, true)? {
+                Type::FixedArray(found, _) if found == *item => Some(tp.clone()),
+                found => self.fits(x.span(), found, tp),
+            },
+            _ => self.expect(x, tp),
+        }
+    }
+
+    /// type of `x`, `used` is whether its value goes anywhere
+    pub fn expr(&mut self, x: &Expression, used: bool) -> Option<Type> {
+        match x {
+            Expression::Literal(l) => self.literal(l, false, l.span),
+            // a generic function is a value only once its params are known
+            Expression::Identifier(_) | Expression::Path(_) if self.template(x).is_some() => {
+                let generics = self.template(x).map(|(generics, _)| generics).unwrap_or_default();
+                self.error(Error::CannotInfer(x.span(), generics[0].name.clone()))
+            }
+            Expression::Identifier(ident) => match self.lookup(ident.name()) {
+                Some(local) => local.tp.clone(),
+                None => self.error(Error::Undefined(ident.clone())),
+            },
+            Expression::Path(path) => self.path(path),
+            Expression::Instance(f, args, span) => self.instance(f, args, *span),
+            Expression::Is(x, pattern, _) => self.test(x, pattern),
+            Expression::Match(x, arms, span) => self.matching(x, arms, None, used, *span),
+            Expression::Array(items, span) => self.array(items, *span),
+            Expression::Repeat(x, n, _) => self.fill(x, n),
+            Expression::Index(x, index, _) => self.index(x, index),
+            Expression::Cast(x, tp, span) => {
+                let tp = self.norm(tp);
+                self.resolve(&tp);
+                match (self.expr(x, true)?, tp) {
+                    (found, tp) if found == tp => Some(tp),
+                    (Type::Primitive(from), Type::Primitive(to)) if Cast::new(from, to).is_some() => Some(Type::Primitive(to)),
+                    (found, tp) => self.error(Error::InvalidCast(*span, found, tp)),
+                }
+            }
+            Expression::With(name, x, body, _) => {
+                self.resource(name, x);
+                let tp = self.block(body, used);
+                self.scopes.pop();
+                tp
+            }
+            Expression::Field(x, field, _) => {
+                let tp = self.expr(x, true)?;
+                self.field(tp, field)
+            }
+            Expression::Call(f, args, span) => self.call(f, args, None, *span),
+            Expression::Unary(op, x, span) => {
+                let tp = match (op, &**x) {
+                    (Unary::Neg, Expression::Literal(l)) => self.literal(l, true, *span)?,
+                    _ => self.expr(x, true)?,
+                };
+                let valid = match (op, &tp) {
+                    (Unary::Neg, Type::Primitive(p)) => p.is_float() || p.is_signed(),
+                    (Unary::Not, Type::Primitive(p)) => *p == Primitive::Bool || p.is_integer(),
+                    _ => false,
+                };
+                if valid {
+                    Some(tp)
+                } else {
+                    self.error(Error::InvalidOperand(*span, tp))
+                }
+            }
+            // the right side sees what the left one tested
+            Expression::Binary(Binary::And, a, b, _) => {
+                self.condition(a);
+                self.narrowed(a, |c| c.condition(b));
+                Some(Type::Primitive(Primitive::Bool))
+            }
+            Expression::Binary(op, a, b, span) => {
+                let tp = self.operands(a, b)?;
+                self.operator(*op, tp, *span)
+            }
+            Expression::Block(b) => self.block(b, used),
+            Expression::If(cond, then, other, span) => {
+                self.condition(cond);
+                match other {
+                    Some(other) if used => {
+                        let (a, b) = (self.narrowed(cond, |c| c.block(then, true)), self.expr(other, true));
+                        let (a, b) = (a?, b?);
+                        let then = Expression::Block(then.clone());
+                        if a == b || a == Type::Never || matches!(Untyped::of(tail(&then)), Some(lit) if lit.fits(&b)) {
+                            Some(b)
+                        } else if b == Type::Never || matches!(Untyped::of(tail(other)), Some(lit) if lit.fits(&a)) {
+                            Some(a)
+                        } else {
+                            self.error(Error::BranchMismatch(*span, a, b))
+                        }
+                    }
+                    Some(other) => {
+                        let (a, b) = (self.narrowed(cond, |c| c.block(then, false)), self.expr(other, false));
+                        match (a, b) {
+                            (Some(Type::Never), Some(Type::Never)) => Some(Type::Never),
+                            _ => Some(Type::Void),
+                        }
+                    }
+                    // nothing to give when the condition is false
+                    None => {
+                        self.narrowed(cond, |c| c.block(then, false));
+                        Some(Type::Void)
+                    }
+                }
+            }
+            Expression::For(kind, body, _) => self.repeat(kind, body),
+            Expression::Break(value, span) => self.jump(value.as_deref(), *span),
+            Expression::Continue(span) => {
+                if self.loops.is_empty() {
+                    return self.error(Error::OutsideLoop(*span));
+                }
+                Some(Type::Never)
+            }
+            Expression::Return(value, span) => {
+                let ret = match self.returns.clone() {
+                    Some(ret) => ret,
+                    None => {
+                        value.as_ref().map(|x| self.expr(x, true));
+                        return self.error(Error::OutsideFunction(*span));
+                    }
+                };
+                match value {
+                    Some(x) => {
+                        self.expect(x, &ret);
+                    }
+                    None if ret != Type::Void => {
+                        self.error(Error::Mismatched(*span, ret, Type::Void));
+                    }
+                    None => (),
+                }
+                Some(Type::Never)
+            }
+            Expression::Struct(path, args, values, span) => self.structure(path, args, values, None, *span),
+            Expression::Range(start, end, span) => match self.operands(start, end)? {
+                Type::Primitive(p) if p.is_integer() => Some(Type::Range(Box::new(Type::Primitive(p)))),
+                tp => self.error(Error::InvalidOperand(*span, tp)),
+            },
+        }
+    }
+
+    // `with name = x`, in a scope of its own the caller pops after the body
+    //
+    // `name` is constant, the value to close at the end is the one opened.
+    fn resource(&mut self, name: &Identifier, x: &Expression) {
+        let tp = match self.expr(x, true) {
+            Some(Type::Void) => self.error(Error::VoidValue(x.span())),
+            Some(tp) => {
+                let closer = parse_str::<GlobalIdentifier>(CLOSER).expect("prelude names parse");
+                if let Err(missing) = self.implements(&tp, &closer) {
+                    self.error(Error::NotImplemented(x.span(), tp.clone(), Type::Named(closer, Vec::new()), missing));
+                }
+                Some(tp)
+            }
+            None => None,
+        };
+        self.scopes.push(HashMap::new());
+        self.declare(name, Value::Constant, tp);
+    }
+
+    // only `for { }` gives the value of its breaks, the others may end with none
+    fn repeat(&mut self, kind: &Loop, body: &Block) -> Option<Type> {
+        self.scopes.push(HashMap::new());
+        match kind {
+            Loop::Forever => (),
+            Loop::While(cond) => self.condition(cond),
+            Loop::In(x, xs) => {
+                let item = match self.expr(xs, true) {
+                    Some(Type::Array(item)) | Some(Type::FixedArray(item, _)) | Some(Type::Range(item)) => Some(*item),
+                    Some(tp) => self.error(Error::NotIterable(xs.span(), tp)),
+                    None => None,
+                };
+                self.declare(x, Value::Constant, item);
+            }
+        }
+        self.loops.push(Breaks { forever: kind.is_forever(), value: None });
+        self.block(body, false);
+        let breaks = self.loops.pop();
+        self.scopes.pop();
+        match (kind, breaks.and_then(|x| x.value)) {
+            (Loop::Forever, Some(tp)) => Some(tp),
+            (Loop::Forever, None) => Some(Type::Never),
+            _ => Some(Type::Void),
+        }
+    }
+
+    // every `break` of a loop gives the same type, a bare `break` gives `Void`
+    fn jump(&mut self, value: Option<&Expression>, span: Span) -> Option<Type> {
+        let (forever, first) = match self.loops.last() {
+            Some(breaks) => (breaks.forever, breaks.value.clone()),
+            None => {
+                value.map(|x| self.expr(x, true));
+                return self.error(Error::OutsideLoop(span));
+            }
+        };
+        if value.is_some() && !forever {
+            self.error(Error::BreakValue(span));
+        }
+        let tp = match (value, first) {
+            (Some(x), Some(tp)) => self.expect(x, &tp),
+            (Some(x), None) => self.expr(x, true),
+            (None, Some(tp)) if tp != Type::Void => self.error(Error::Mismatched(span, tp, Type::Void)),
+            (None, _) => Some(Type::Void),
+        };
+        if let Some(breaks) = self.loops.last_mut() {
+            if breaks.value.is_none() {
+                breaks.value = tp;
+            }
+        }
+        Some(Type::Never)
+    }
+
+    // `io.println` is a global, `p.x` is a field of the local `p`
+    fn path(&mut self, path: &GlobalIdentifier) -> Option<Type> {
+        if let Some(global) = self.globals.get(&names(path)) {
+            return global.tp.clone();
+        }
+        let (first, fields) = path.path().split_first()?;
+        if self.lookup(first.name()).is_none() {
+            return self.error(Error::UndefinedPath(path.clone()));
+        }
+        let mut tp = self.expr(&Expression::Identifier(first.clone()), true)?;
+        for field in fields {
+            tp = self.field(tp, field)?;
+        }
+        Some(tp)
+    }
+
+    fn field(&mut self, tp: Type, field: &Identifier) -> Option<Type> {
+        // fields first, then methods which are functions without their receiver
+        let found = match &tp {
+            Type::Named(path, args) => self.structs.get(&names(path))
+                .and_then(|structure| {
+                    let (_, found) = structure.fields.iter().find(|(name, _)| name.name() == field.name())?;
+                    Some(generic::subst(found, &bindings(&structure.generics, args)))
+                }),
+            _ => None,
+        };
+        let found = found.or_else(|| {
+            self.methods_of(&tp).into_iter()
+                .find(|(name, _)| name.name() == field.name())
+                .map(|(_, f)| Type::Function(Box::new(f)))
+        });
+        match found {
+            Some(found) => Some(found),
+            None => self.error(Error::NoField(field.clone(), tp)),
+        }
+    }
+
+    // every field exactly once, in any order, the params of a generic struct come from its fields if not given
+    fn structure(&mut self, path: &GlobalIdentifier, args: &[Spanned<Type>], values: &[(Identifier, Expression)], want: Option<&Type>, span: Span) -> Option<Type> {
+        let structure = match self.structs.get(&names(path)) {
+            Some(structure) => structure.clone(),
+            None => {
+                values.iter().for_each(|(_, x)| {
+                    self.expr(x, true);
+                });
+                return self.error(Error::UndefinedType(path.clone()));
+            }
+        };
+        let fields = structure.fields;
+        // the first value of each known field
+        let pairs = values.iter()
+            .enumerate()
+            .filter(|(i, (name, _))| values[..*i].iter().all(|(x, _)| x.name() != name.name()))
+            .filter_map(|(i, (name, x))| {
+                let (_, field) = fields.iter().find(|(field, _)| field.name() == name.name())?;
+                Some((i, (field.clone(), x)))
+            })
+            .collect::<Vec<_>>();
+        let (bindings, checked) = if args.is_empty() && !structure.generics.is_empty() {
+            let (indices, pairs): (Vec<_>, Vec<_>) = pairs.into_iter().unzip();
+            let params = structure.generics.iter().map(|g| Type::Param(g.name.clone())).collect();
+            let seed = generic::seed(&Type::Named(path.clone(), params), want);
+            let (bindings, checked) = self.bind(&structure.generics, seed, &pairs, false, span)?;
+            let checked = indices.into_iter().zip(checked).filter(|(_, x)| *x).map(|(i, _)| i).collect();
+            (bindings, checked)
+        } else {
+            let args = args.iter().map(|x| x.node.clone()).collect::<Vec<_>>();
+            (self.explicit(&structure.generics, &args, span)?, Vec::new())
+        };
+        let args = structure.generics.iter()
+            .map(|g| bindings.get(g.name.name()).cloned().unwrap_or_default())
+            .collect();
+        let tp = Type::Named(path.clone(), args);
+        let mut seen = Vec::new();
+        for (i, (name, x)) in values.iter().enumerate() {
+            match fields.iter().find(|(field, _)| field.name() == name.name()) {
+                Some(_) if seen.contains(&name.name()) => {
+                    self.error(Error::DuplicateField(name.clone()));
+                }
+                Some(_) if checked.contains(&i) => (),
+                Some((_, field)) => {
+                    self.expect(x, &generic::subst(field, &bindings));
+                }
+                None => {
+                    self.error(Error::NoField(name.clone(), tp.clone()));
+                }
+            }
+            seen.push(name.name());
+        }
+        let missing = fields.into_iter()
+            .map(|(name, _)| name)
+            .filter(|name| !seen.contains(&name.name()))
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            return self.error(Error::MissingFields(span, missing));
+        }
+        Some(tp)
+    }
+
+    fn call(&mut self, f: &Expression, args: &[Expression], want: Option<&Type>, span: Span) -> Option<Type> {
+        if let Some((generics, Type::Function(declare))) = self.template(f) {
+            return self.infer(&generics, &declare, args, want, span);
+        }
+        let declare = match self.expr(f, true) {
+            Some(Type::Function(declare)) => declare,
+            tp => {
+                if let Some(tp) = tp {
+                    self.error(Error::NotCallable(f.span(), tp));
+                }
+                args.iter().for_each(|arg| {
+                    self.expr(arg, true);
+                });
+                return None;
+            }
+        };
+        if declare.params.len() != args.len() {
+            self.error(Error::ArgumentCount(span, declare.params.len(), args.len()));
+        }
+        for (param, arg) in declare.params.iter().zip(args) {
+            self.argument(arg, param.declare());
+        }
+        Some(declare.ret.node.clone())
+    }
+
+    // `max(1.5, b)` of a generic `max` has its params bound by the arguments
+    fn infer(&mut self, generics: &[Generic], declare: &types::Function, args: &[Expression], want: Option<&Type>, span: Span) -> Option<Type> {
+        if declare.params.len() != args.len() {
+            self.error(Error::ArgumentCount(span, declare.params.len(), args.len()));
+        }
+        args.iter().skip(declare.params.len()).for_each(|arg| {
+            self.expr(arg, true);
+        });
+        let pairs = declare.params.iter()
+            .zip(args)
+            .map(|(param, arg)| (param.declare().node.clone(), arg))
+            .collect::<Vec<_>>();
+        let seed = generic::seed(&declare.ret, want);
+        let (bindings, checked) = self.bind(generics, seed, &pairs, true, span)?;
+        for ((param, arg), checked) in pairs.iter().zip(checked) {
+            if !checked {
+                self.argument(arg, &generic::subst(param, &bindings));
+            }
+        }
+        Some(generic::subst(&declare.ret, &bindings))
+    }
+
+    // both sides of a binary have one type, an untyped literal takes the type of the other side
+    fn operands(&mut self, a: &Expression, b: &Expression) -> Option<Type> {
+        match (Untyped::of(a), Untyped::of(b)) {
+            (Some(_), None) => {
+                let tp = self.expr(b, true)?;
+                self.expect(a, &tp)
+            }
+            (None, Some(_)) => {
+                let tp = self.expr(a, true)?;
+                self.expect(b, &tp)
+            }
+            _ => {
+                let (ta, tb) = (self.expr(a, true), self.expr(b, true));
+                let (ta, tb) = (ta?, tb?);
+                if ta == tb {
+                    Some(ta)
+                } else {
+                    self.error(Error::Mismatched(b.span(), ta, tb))
+                }
+            }
+        }
+    }
+
+    // `128i8` is out of range where `-128i8` is not, so a negated literal is checked with its `-`
+    fn literal(&mut self, l: &Literal, negative: bool, span: Span) -> Option<Type> {
+        let tp = l.literal_type();
+        match (l, &tp) {
+            (Literal::Numeric(Numeric::Integer(value, _)), Type::Primitive(p)) if !literal::fits(*value, negative, *p) => {
+                self.error(Error::LiteralOutOfRange(span, tp))
+            }
+            _ => Some(tp),
+        }
+    }
+
+    // result of `op` over two operands of `tp`, `None` result is `tp` itself
+    fn operator(&mut self, op: Binary, tp: Type, span: Span) -> Option<Type> {
+        let bool = Type::Primitive(Primitive::Bool);
+        let ordered = self.ordered(&tp);
+        let (valid, result) = match (&tp, op) {
+            (_, Binary::Or) | (_, Binary::And) => (tp == bool, Some(bool.clone())),
+            (Type::Void, _) | (Type::Function(_), _) => (false, None),
+            (_, Binary::Equal) | (_, Binary::NotEqual) => (true, Some(bool.clone())),
+            (_, Binary::Less) | (_, Binary::LessEqual) |
+            (_, Binary::Greater) | (_, Binary::GreaterEqual) => (ordered, Some(bool.clone())),
+            (Type::String, Binary::Add) => (true, None),
+            (Type::Primitive(p), Binary::BitOr) | (Type::Primitive(p), Binary::BitAnd) => (p.is_integer() || *p == Primitive::Bool, None),
+            (Type::Primitive(p), Binary::ShiftLeft) | (Type::Primitive(p), Binary::ShiftRight) => (p.is_integer(), None),
+            (Type::Primitive(p), _) => (p.is_integer() || p.is_float(), None),
+            _ => (false, None),
+        };
+        if valid {
+            Some(result.unwrap_or(tp))
+        } else {
+            self.error(Error::InvalidOperand(span, tp))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    pub(super) fn check(src: &str) -> Result<(), Vec<Error>> {
+        let file = parse_str::<SourceFile>(src).expect(src);
+        Checker::new().check(file.defines())
+    }
+
+    pub(super) fn errors(src: &str) -> Vec<Error> {
+        check(src).expect_err(src)
+    }
+
+    #[test]
+    fn duplicate_defines() {
+        for src in &[
+            "struct P { x i32 }\nstruct P { y i32 }",
+            "interface I { fn a() }\ninterface I { fn b() }",
+            "struct P { x i32 }\ninterface P { fn a() }",
+            "fn main() { }\nfn main() { }",
+            "const N i32 = { 1 }\nvar N i32 = { 2 }",
+            "fn N() { }\nconst N i32 = { 1 }",
+        ] {
+            match errors(src).as_slice() {
+                // at the second one
+                [Error::DuplicateDefine(name)] => assert_eq!(Some(name.span().start), src.rfind(name.name())),
+                errs => panic!("{} {:?}", src, errs),
+            }
+        }
+        // the first one stays, the second is not used in its place
+        match errors("struct P { x i32 }\nstruct P { y i32 }\nfn f() -> i32 { P{x: 1}.x }").as_slice() {
+            [Error::DuplicateDefine(_)] => (),
+            errs => panic!("{:?}", errs),
+        }
+        // a struct of the first `I` does not have to implement the second
+        match errors("interface I { fn a() }\ninterface I { fn b() }\nstruct S { }\nfn (ref s S) a() { }\nfn f(var i I) { }\nfn g() { f(S{}) }").as_slice() {
+            [Error::DuplicateDefine(name)] => assert_eq!(name.name(), "I"),
+            errs => panic!("{:?}", errs),
+        }
+        assert!(check("struct P { x i32 }\nfn P() { }").is_ok());
+        assert!(check("struct P { x i32 }\nfn (ref p P) x2() -> i32 { p.x }\nfn x2() -> i32 { 2 }").is_ok());
+    }
+
+    #[test]
+    fn returns() {
+        for src in &[
+            "fn f() -> i32 { return 1 }",
+            "fn f(c bool) -> i32 { if c { return 1 }\n2 }",
+            "fn f(c bool) -> i32 { if c { return 1 } else { return 2 } }",
+            "fn f(c bool) -> i32 { if c { 1 } else { 2 } }",
+            "fn f() -> i32 { for { return 1 } }",
+            "fn f() -> i32 { for i in 0..3 { return i }\nreturn 0 }",
+            "fn f() { return }",
+        ] {
+            assert!(check(src).is_ok(), "{} {:?}", src, check(src));
+        }
+        match errors("fn f() -> i32 { true }").as_slice() {
+            [Error::Mismatched(_, Type::Primitive(Primitive::I32), Type::Primitive(Primitive::Bool))] => (),
+            errs => panic!("{:?}", errs),
+        }
+        match errors("fn f() { return 1 }").as_slice() {
+            [Error::Mismatched(_, Type::Void, Type::Primitive(Primitive::I32))] => (),
+            errs => panic!("{:?}", errs),
+        }
+        match errors("const N i32 = { return 1 }").as_slice() {
+            [Error::OutsideFunction(_)] => (),
+            errs => panic!("{:?}", errs),
+        }
+    }
+
+    #[test]
+    fn missing_return() {
+        for src in &[
+            "fn f() -> i32 { }",
+            "fn f(c bool) -> i32 { if c { return 1 } }",
+            "fn f() -> i32 { for i in 0..3 { return i } }",
+            "fn f(c bool) -> i32 { if c { return 1 } else if !c { return 2 } }",
+            "fn f(c bool) -> i32 { for c { return 1 } }",
+        ] {
+            match errors(src).as_slice() {
+                [Error::MissingReturn(span)] => assert_eq!(Some(span.start), src.find(" {").map(|x| x + 1), "{}", src),
+                errs => panic!("{} {:?}", src, errs),
+            }
+        }
+    }
+
+    #[test]
+    fn calls() {
+        for src in &[
+            "fn f(const a i32, var b str) -> i32 { a }\nfn g() -> i32 { f(1, \"b\",) + 1 }",
+            "fn g() {\n    io.println(\"a\")\n    io.print(\"b\")\n}",
+            "fn f() -> fn(const i32) -> i32 { g }\nfn g(const a i32) -> i32 { a }\nfn h() -> i32 { f()(1) }",
+        ] {
+            assert!(check(src).is_ok(), "{} {:?}", src, check(src));
+        }
+        let src = "fn f(const a i32) { }\nfn g() { f(1, 2) }";
+        match errors(src).as_slice() {
+            [Error::ArgumentCount(span, 1, 2)] => assert_eq!(Some(span.start), src.find("f(1")),
+            errs => panic!("{:?}", errs),
+        }
+        let src = "fn f(const a i32) { }\nfn g() { f(\"a\") }";
+        match errors(src).as_slice() {
+            [Error::Mismatched(span, Type::Primitive(Primitive::I32), Type::String)] => assert_eq!(Some(span.start), src.find("\"a")),
+            errs => panic!("{:?}", errs),
+        }
+        let src = "fn g() {\n    var a = 1\n    a()\n}";
+        match errors(src).as_slice() {
+            [Error::NotCallable(span, Type::Primitive(Primitive::I32))] => assert_eq!(Some(span.start), src.find("a()")),
+            errs => panic!("{:?}", errs),
+        }
+        match errors("fn g() { h(1) }").as_slice() {
+            [Error::Undefined(name)] => assert_eq!(name.name(), "h"),
+            errs => panic!("{:?}", errs),
+        }
+        match errors("fn g() { io.printx(\"a\") }").as_slice() {
+            [Error::UndefinedPath(path)] => assert_eq!(path.path().len(), 2),
+            errs => panic!("{:?}", errs),
+        }
+    }
+
+    #[test]
+    fn declarations() {
+        for src in &[
+            "fn f() -> i64 {\n    var a i64 = 1\n    const b = a + 2\n    ref c = a\n    c += b\n    a\n}",
+            "fn f() -> str {\n    var a = 1\n    var a = \"shadowed\"\n    a\n}",
+            "fn f() {\n    var a [2]i32\n    a[0] = 1\n}",
+        ] {
+            assert!(check(src).is_ok(), "{} {:?}", src, check(src));
+        }
+        let src = "fn f() {\n    const a = 1\n    a = 2\n}";
+        match errors(src).as_slice() {
+            [Error::AssignConstant(span)] => assert_eq!(Some(span.start), src.find("a = 2")),
+            errs => panic!("{:?}", errs),
+        }
+        let src = "fn g() -> i32 { 1 }\nfn f() { g() = 2 }";
+        match errors(src).as_slice() {
+            [Error::NotAssignable(span)] => assert_eq!(Some(span.start), src.find("g() =")),
+            errs => panic!("{:?}", errs),
+        }
+        let src = "fn f() { var a str = 1 }";
+        match errors(src).as_slice() {
+            [Error::Mismatched(span, Type::String, Type::Primitive(Primitive::I32))] => assert_eq!(Some(span.start), src.find('1')),
+            errs => panic!("{:?}", errs),
+        }
+        let src = "fn g() { }\nfn f() { var a = g() }";
+        match errors(src).as_slice() {
+            [Error::VoidValue(span)] => assert_eq!(Some(span.start), src.rfind("g()")),
+            errs => panic!("{:?}", errs),
+        }
+        // a name is there from its declaration on
+        let src = "fn f() {\n    b = 1\n    var b = 2\n}";
+        match errors(src).as_slice() {
+            [Error::Undefined(name)] => assert_eq!(Some(name.span().start), src.find('b')),
+            errs => panic!("{:?}", errs),
+        }
+    }
+
+    #[test]
+    fn branches() {
+        for src in &[
+            "fn f(c bool) -> i64 {\n    var x = if c { 1 } else { 2i64 }\n    x\n}",
+            "fn f(c bool) {\n    if c { 1 } else { \"a\" }\n}",
+            "fn f(c bool) -> i32 { if c { 1 } else if !c { 2 } else { return 3 } }",
+        ] {
+            assert!(check(src).is_ok(), "{} {:?}", src, check(src));
+        }
+        let src = "fn f(c bool) {\n    var x = if c { 1 } else { \"a\" }\n}";
+        match errors(src).as_slice() {
+            [Error::BranchMismatch(span, Type::Primitive(Primitive::I32), Type::String)] => assert_eq!(Some(span.start), src.find("if c")),
+            errs => panic!("{:?}", errs),
+        }
+        let src = "fn f(c bool) -> str { if c { \"a\" } else { 1 } }";
+        match errors(src).as_slice() {
+            [Error::Mismatched(span, Type::String, Type::Primitive(Primitive::I32))] => assert_eq!(Some(span.start), src.find('1')),
+            errs => panic!("{:?}", errs),
+        }
+        let src = "fn f() { if 1 { } }";
+        match errors(src).as_slice() {
+            [Error::Mismatched(span, Type::Primitive(Primitive::Bool), Type::Primitive(Primitive::I32))] => assert_eq!(Some(span.start), src.find('1')),
+            errs => panic!("{:?}", errs),
+        }
+        match errors("fn f(c bool) { var x i32 = if c { 1 } }").as_slice() {
+            [Error::Mismatched(_, Type::Primitive(Primitive::I32), Type::Void)] => (),
+            errs => panic!("{:?}", errs),
+        }
+    }
+
+    #[test]
+    fn loops() {
+        for src in &[
+            "fn f() -> i64 {\n    var n i64 = 0\n    for i in 0..10i64 {\n        if i == 5 {\n            continue\n        }\n        n += i\n    }\n    n\n}",
+            "fn f() -> str {\n    for x in [\"a\", \"b\"] {\n        return x\n    }\n    \"\"\n}",
+            "fn f(const xs [3]u8) -> u8 {\n    for x in xs {\n        return x\n    }\n    0\n}",
+            "fn f() -> i32 {\n    var n = 1\n    for n < 100 {\n        n *= 2\n    }\n    n\n}",
+            "fn f() -> i64 { for { break 1i64 } }",
+            "fn f(c bool) -> i32 { for { if c { break 1 }\n break 2 } }",
+        ] {
+            assert!(check(src).is_ok(), "{} {:?}", src, check(src));
+        }
+        let src = "fn f() { break }";
+        match errors(src).as_slice() {
+            [Error::OutsideLoop(span)] => assert_eq!(Some(span.start), src.find("break")),
+            errs => panic!("{:?}", errs),
+        }
+        match errors("fn f() { continue }").as_slice() {
+            [Error::OutsideLoop(_)] => (),
+            errs => panic!("{:?}", errs),
+        }
+        let src = "fn f(c bool) { for c { break 1 } }";
+        match errors(src).as_slice() {
+            [Error::BreakValue(span)] => assert_eq!(Some(span.start), src.find("break")),
+            errs => panic!("{:?}", errs),
+        }
+        let src = "fn f() { for x in 3 { } }";
+        match errors(src).as_slice() {
+            [Error::NotIterable(span, Type::Primitive(Primitive::I32))] => assert_eq!(Some(span.start), src.find('3')),
+            errs => panic!("{:?}", errs),
+        }
+        // every `break` of a `for { }` gives the type of the first
+        let src = "fn f(c bool) -> i32 { for { if c { break 1 }\n break \"a\" } }";
+        match errors(src).as_slice() {
+            [Error::Mismatched(span, Type::Primitive(Primitive::I32), Type::String)] => assert_eq!(Some(span.start), src.find("\"a")),
+            errs => panic!("{:?}", errs),
+        }
+        match errors("fn f(c bool) -> i32 { for { if c { break 1 }\n break } }").as_slice() {
+            [Error::Mismatched(_, Type::Primitive(Primitive::I32), Type::Void)] => (),
+            errs => panic!("{:?}", errs),
+        }
+    }
+
+    #[test]
+    fn structs() {
+        let point = "struct P { x f64, y f64 }\n";
+        for src in &[
+            "fn f() -> f64 {\n    var p = P{y: 2.0, x: 1}\n    p.x = 3.0\n    p.x + p.y\n}",
+            "struct L { a P, b P }\nfn f(const l L) -> f64 { l.a.x - l.b.y }",
+            "struct E { }\nfn f() -> E { E{} }",
+        ] {
+            let src = format!("{}{}", point, src);
+            assert!(check(&src).is_ok(), "{} {:?}", src, check(&src));
+        }
+        let src = format!("{}fn f() -> P {{ P{{x: 1.0}} }}", point);
+        match errors(&src).as_slice() {
+            [Error::MissingFields(span, fields)] => {
+                assert_eq!(Some(span.start), src.rfind("P{"));
+                assert_eq!(fields.iter().map(|x| x.name()).collect::<Vec<_>>(), vec!["y"]);
+            }
+            errs => panic!("{:?}", errs),
+        }
+        let src = format!("{}fn f() -> P {{ P{{x: 1.0, y: 2.0, x: 3.0}} }}", point);
+        match errors(&src).as_slice() {
+            [Error::DuplicateField(name)] => assert_eq!(Some(name.span().start), src.rfind("x:")),
+            errs => panic!("{:?}", errs),
+        }
+        let src = format!("{}fn f() -> P {{ P{{x: 1.0, y: 2.0, z: 3.0}} }}", point);
+        match errors(&src).as_slice() {
+            [Error::NoField(name, Type::Named(_, _))] => assert_eq!(name.name(), "z"),
+            errs => panic!("{:?}", errs),
+        }
+        let src = format!("{}fn f(const p P) -> f64 {{ p.z }}", point);
+        match errors(&src).as_slice() {
+            [Error::NoField(name, _)] => assert_eq!(Some(name.span().start), src.rfind('z')),
+            errs => panic!("{:?}", errs),
+        }
+        let src = format!("{}fn f() -> P {{ P{{x: 1.0, y: \"a\"}} }}", point);
+        match errors(&src).as_slice() {
+            [Error::Mismatched(span, Type::Primitive(Primitive::F64), Type::String)] => assert_eq!(Some(span.start), src.find("\"a")),
+            errs => panic!("{:?}", errs),
+        }
+        match errors("struct P { x f64, x i32 }").as_slice() {
+            [Error::DuplicateField(name)] => assert_eq!(name.span().start, 18),
+            errs => panic!("{:?}", errs),
+        }
+        for src in &["fn f(const q Q) { }", "fn f() { Q{x: 1} }", "struct L { a Q }"] {
+            match errors(src).as_slice() {
+                [Error::UndefinedType(path)] => assert_eq!(Some(path.span().start), src.find('Q'), "{}", src),
+                errs => panic!("{} {:?}", src, errs),
+            }
+        }
+    }
+
+    #[test]
+    fn methods() {
+        let point = "struct P { x f64, y f64 }\nfn (const p P) sum() -> f64 { p.x + p.y }\n";
+        for src in &[
+            "fn (ref p P) scale(const by f64) {\n    p.x *= by\n    p.y *= by\n}\nfn f() -> f64 {\n    var p = P{x: 1.0, y: 2.0}\n    p.scale(2.0)\n    p.sum()\n}",
+            "struct Box<T> { v T }\nfn (const b Box<U>) get() -> U { b.v }\nfn f() -> i32 { Box<i32>{v: 1}.get() }",
+        ] {
+            let src = format!("{}{}", point, src);
+            assert!(check(&src).is_ok(), "{} {:?}", src, check(&src));
+        }
+        let src = format!("{}fn (const p P) x() -> f64 {{ 0.0 }}", point);
+        match errors(&src).as_slice() {
+            [Error::DuplicateField(name)] => assert_eq!(Some(name.span().start), src.find("x()")),
+            errs => panic!("{:?}", errs),
+        }
+        let src = format!("{}fn (const p P) sum() -> f64 {{ 0.0 }}", point);
+        match errors(&src).as_slice() {
+            [Error::DuplicateField(name)] => assert_eq!(Some(name.span().start), src.rfind("sum")),
+            errs => panic!("{:?}", errs),
+        }
+        let src = "fn (const x i32) double() -> i32 { x * 2 }";
+        match errors(src).as_slice() {
+            [Error::InvalidReceiver(name, Type::Primitive(Primitive::I32))] => assert_eq!(name.name(), "double"),
+            errs => panic!("{:?}", errs),
+        }
+        let src = format!("{}fn f(const p P) -> f64 {{ p.area() }}", point);
+        match errors(&src).as_slice() {
+            [Error::NoField(name, _)] => assert_eq!(Some(name.span().start), src.find("area")),
+            errs => panic!("{:?}", errs),
+        }
+    }
+
+    #[test]
+    fn interfaces() {
+        let shape = "interface Shape { fn area() -> f64 }\nstruct Sq { side f64 }\nfn total(const s Shape) -> f64 { s.area() }\n";
+        let src = format!("{}fn (const s Sq) area() -> f64 {{ s.side * s.side }}\nfn f() -> f64 {{ total(Sq{{side: 2.0}}) }}", shape);
+        assert!(check(&src).is_ok(), "{} {:?}", src, check(&src));
+        let src = format!("{}fn f() -> f64 {{ total(Sq{{side: 2.0}}) }}", shape);
+        match errors(&src).as_slice() {
+            [Error::NotImplemented(span, Type::Named(_, _), Type::Named(_, _), missing)] => {
+                assert_eq!(Some(span.start), src.rfind("Sq{"));
+                match missing.as_slice() {
+                    [MethodError::Missing(name)] => assert_eq!(name.name(), "area"),
+                    x => panic!("{:?}", x),
+                }
+            }
+            errs => panic!("{:?}", errs),
+        }
+        let src = format!("{}fn (const s Sq) area() -> f32 {{ 0.0 }}\nfn f() -> f64 {{ total(Sq{{side: 2.0}}) }}", shape);
+        match errors(&src).as_slice() {
+            [Error::NotImplemented(_, _, _, missing)] => match missing.as_slice() {
+                [MethodError::Mismatched(name, want, found)] => {
+                    assert_eq!(Some(name.span().start), src.find("area() -> f32"));
+                    assert_eq!((&want.ret.node, &found.ret.node), (&Type::Primitive(Primitive::F64), &Type::Primitive(Primitive::F32)));
+                }
+                x => panic!("{:?}", x),
+            },
+            errs => panic!("{:?}", errs),
+        }
+        let src = "interface Shape { fn area() -> f64 }\nfn f() { var s Shape = 1 }";
+        match errors(src).as_slice() {
+            [Error::NotImplemented(span, Type::Primitive(Primitive::I32), _, _)] => assert_eq!(Some(span.start), src.rfind('1')),
+            errs => panic!("{:?}", errs),
+        }
+    }
+
+    #[test]
+    fn with_blocks() {
+        let file = "struct File { name str }\nfn open(const name str) -> File { File{name: name} }\nfn (const f File) close() { }\n";
+        for body in &[
+            "with f = open(\"a\") { f.name }",
+            "with f = open(\"a\") {\n        with g = open(f.name) { return g.name }\n    }",
+            "for {\n        with f = open(\"a\") { break }\n    }\n    \"\"",
+        ] {
+            let src = format!("{}fn f() -> str {{\n    {}\n}}", file, body);
+            assert!(check(&src).is_ok(), "{} {:?}", src, check(&src));
+        }
+        let src = format!("{}fn f() {{\n    with h = open(\"a\") {{ }}\n    h.close()\n}}", file);
+        match errors(&src).as_slice() {
+            [Error::UndefinedPath(path)] => assert_eq!(Some(path.span().start), src.rfind("h.close")),
+            errs => panic!("{:?}", errs),
+        }
+        let src = format!("{}fn f() {{ with f = open(\"a\") {{ f = open(\"b\") }} }}", file);
+        match errors(&src).as_slice() {
+            [Error::AssignConstant(span)] => assert_eq!(Some(span.start), src.find("f = open(\"b")),
+            errs => panic!("{:?}", errs),
+        }
+        let src = "fn f() { with x = 1 { } }";
+        match errors(src).as_slice() {
+            [Error::NotImplemented(span, Type::Primitive(Primitive::I32), Type::Named(closer, _), missing)] => {
+                assert_eq!(Some(span.start), src.find('1'));
+                assert_eq!(closer.path()[0].name(), "Closer");
+                assert!(matches!(missing.as_slice(), [MethodError::Missing(name)] if name.name() == "close"));
+            }
+            errs => panic!("{:?}", errs),
+        }
+        let src = "fn g() { }\nfn f() { with x = g() { } }";
+        match errors(src).as_slice() {
+            [Error::VoidValue(span)] => assert_eq!(Some(span.start), src.rfind("g()")),
+            errs => panic!("{:?}", errs),
+        }
+    }
+
+    #[test]
+    fn casts() {
+        for src in &[
+            "fn f(const x f64) -> u8 { x as u8 }",
+            "fn f(const x i64) -> f32 { x as f32 + 1.0 }",
+            "fn f(const x i32) -> i32 { x as i32 }",
+            "fn f(const x size) -> ptr { x as ptr }",
+            "fn f() -> i64 { 1 as i64 << 40 }",
+        ] {
+            assert!(check(src).is_ok(), "{} {:?}", src, check(src));
+        }
+        for (src, from, to) in &[
+            ("fn f(const x bool) -> i32 { x as i32 }", Type::Primitive(Primitive::Bool), Type::Primitive(Primitive::I32)),
+            ("fn f(const x i32) -> bool { x as bool }", Type::Primitive(Primitive::I32), Type::Primitive(Primitive::Bool)),
+            ("fn f(const x f64) -> ptr { x as ptr }", Type::Primitive(Primitive::F64), Type::Primitive(Primitive::Ptr)),
+            ("fn f(const x str) -> i32 { x as i32 }", Type::String, Type::Primitive(Primitive::I32)),
+        ] {
+            match errors(src).as_slice() {
+                [Error::InvalidCast(span, found, tp)] => {
+                    assert_eq!((span.start, span.end), (src.find("x as").unwrap(), src.rfind(" }").unwrap()), "{}", src);
+                    assert_eq!((found, tp), (from, to), "{}", src);
+                }
+                errs => panic!("{} {:?}", src, errs),
+            }
+        }
+        let src = "fn f() -> i32 { x as i32 }";
+        match errors(src).as_slice() {
+            [Error::Undefined(name)] => assert_eq!(name.name(), "x"),
+            errs => panic!("{:?}", errs),
+        }
+    }
+
+    #[test]
+    fn untyped_float_range() {
+        for src in &["fn f() { var x f32 = 1e39 }", "fn f() { var x f32 = -1e39 }"] {
+            match errors(src).as_slice() {
+                [Error::LiteralOutOfRange(span, Type::Primitive(Primitive::F32))] => {
+                    assert_eq!(Some(span.start), src.find("= ").map(|x| x + 2))
+                }
+                errs => panic!("{} {:?}", src, errs),
+            }
+        }
+        assert!(check("fn f() { var x f32 = 3.4e38\nvar y f32 = -1e-50\nvar z f64 = 1e39 }").is_ok());
+    }
+}
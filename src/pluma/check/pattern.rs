@@ -0,0 +1,299 @@
+use std::collections::HashMap;
+
+use crate::pluma::check::{Checker, Error, Local, Untyped, bindings, generic, names, tail, COMPARABLE};
+use crate::pluma::ir::block::{Arm, Binary, Expression};
+use crate::pluma::ir::commons::Value;
+use crate::pluma::ir::pattern::Pattern;
+use crate::pluma::ir::types::{Primitive, Type};
+use crate::pluma::source::Span;
+
+impl Checker {
+    fn interface(&self, tp: &Type) -> bool {
+        match tp {
+            Type::Named(path, _) => self.interfaces.contains_key(&names(path)),
+            _ => false,
+        }
+    }
+
+    // a value of `found` may be a `tp`, it is one already or `tp` implements the interface it is
+    fn admits(&self, found: &Type, tp: &Type) -> bool {
+        match found {
+            _ if found == tp => true,
+            Type::Named(interface, _) if self.interface(found) && !self.interface(tp) => {
+                self.implements(tp, interface).is_ok()
+            }
+            _ => false,
+        }
+    }
+
+    /// type a value of `found` has where it matches, `None` for `_`
+    ///
+    /// `Box{v}` tested on a `Box<i32>` is that `Box<i32>`.
+    pub(super) fn pattern_type(&self, found: Option<&Type>, pattern: &Pattern) -> Option<Type> {
+        match pattern {
+            Pattern::Any(_) => None,
+            Pattern::Type(tp) => Some(self.norm(tp)),
+            Pattern::Struct(path, _, _) => match found {
+                Some(Type::Named(x, args)) if x == path => Some(Type::Named(path.clone(), args.clone())),
+                _ => Some(Type::Named(path.clone(), Vec::new())),
+            },
+        }
+    }
+
+    // reports a pattern which can never match a value of `found`
+    fn pattern(&mut self, found: &Type, pattern: &Pattern) {
+        let tp = match self.pattern_type(Some(found), pattern) {
+            Some(tp) => tp,
+            None => return,
+        };
+        if let Type::Named(path, _) = &tp {
+            let key = names(path);
+            if !self.structs.contains_key(&key) && !self.interfaces.contains_key(&key) {
+                self.error(Error::UndefinedType(path.clone()));
+                return;
+            }
+        }
+        self.resolve(&tp);
+        if let Pattern::Struct(_, fields, _) = pattern {
+            let mut seen = Vec::new();
+            for (field, _) in fields {
+                if seen.contains(&field.name()) {
+                    self.error(Error::DuplicateField(field.clone()));
+                } else if self.field_type(&tp, field.name()).is_none() {
+                    self.error(Error::NoField(field.clone(), tp.clone()));
+                }
+                seen.push(field.name());
+            }
+        }
+        if !self.admits(found, &tp) {
+            self.error(Error::ImpossibleTest(pattern.span(), found.clone(), tp));
+        }
+    }
+
+    fn field_type(&self, tp: &Type, field: &str) -> Option<Type> {
+        match tp {
+            Type::Named(path, args) => {
+                let structure = self.structs.get(&names(path))?;
+                let (_, found) = structure.fields.iter().find(|(name, _)| name.name() == field)?;
+                Some(generic::subst(found, &bindings(&structure.generics, args)))
+            }
+            _ => None,
+        }
+    }
+
+    // names a match brings in, `x` itself narrowed to the type of the pattern and the fields taken out
+    fn captures(&self, x: &Expression, found: Option<&Type>, pattern: &Pattern) -> HashMap<String, Local> {
+        let mut scope = HashMap::new();
+        let tp = self.pattern_type(found, pattern);
+        if let (Expression::Identifier(x), Some(tp)) = (x, &tp) {
+            if let Some(local) = self.lookup(x.name()) {
+                scope.insert(x.name().to_string(), Local { value: local.value.clone(), tp: Some(tp.clone()) });
+            }
+        }
+        if let (Pattern::Struct(_, fields, _), Some(tp)) = (pattern, &tp) {
+            for (field, bind) in fields.iter().filter(|(_, bind)| bind.name() != "_") {
+                let local = Local { value: Value::Constant, tp: self.field_type(tp, field.name()) };
+                scope.insert(bind.name().to_string(), local);
+            }
+        }
+        scope
+    }
+
+    /// what holds where `cond` is true, the tests in it joined by `&&`
+    pub(super) fn narrowing(&self, cond: &Expression) -> HashMap<String, Local> {
+        match cond {
+            Expression::Is(x, pattern, _) => {
+                let found = match &**x {
+                    Expression::Identifier(x) => self.lookup(x.name()).and_then(|local| local.tp.clone()),
+                    _ => None,
+                };
+                self.captures(x, found.as_ref(), pattern)
+            }
+            Expression::Binary(Binary::And, a, b, _) => {
+                let mut scope = self.narrowing(a);
+                scope.extend(self.narrowing(b));
+                scope
+            }
+            _ => HashMap::new(),
+        }
+    }
+
+    /// `f` checked where `cond` was true
+    pub(super) fn narrowed<T>(&mut self, cond: &Expression, f: impl FnOnce(&mut Self) -> T) -> T {
+        let scope = self.narrowing(cond);
+        self.scopes.push(scope);
+        let result = f(self);
+        self.scopes.pop();
+        result
+    }
+
+    /// `x is i32`
+    pub(super) fn test(&mut self, x: &Expression, pattern: &Pattern) -> Option<Type> {
+        let found = self.expr(x, true)?;
+        self.pattern(&found, pattern);
+        Some(Type::Primitive(Primitive::Bool))
+    }
+
+    /// `x is { ... }`, arms give one type like the branches of an `if`
+    pub(super) fn matching(&mut self, x: &Expression, arms: &[Arm], expect: Option<&Type>, used: bool, span: Span) -> Option<Type> {
+        let found = self.expr(x, true);
+        if let Some(found) = &found {
+            arms.iter().for_each(|arm| self.pattern(found, arm.pattern()));
+            self.exhaustive(found, arms, span);
+        }
+        // typed arms go first, an untyped literal arm then takes their type
+        let mut order = (0..arms.len()).collect::<Vec<_>>();
+        order.sort_by_key(|i| Untyped::of(tail(arms[*i].value())).is_some());
+        let mut tp = expect.cloned();
+        let mut never = true;
+        for i in order {
+            let arm = &arms[i];
+            let scope = self.captures(x, found.as_ref(), arm.pattern());
+            self.scopes.push(scope);
+            let checked = match &tp {
+                Some(tp) if used => self.expect(arm.value(), tp),
+                _ => self.expr(arm.value(), used),
+            };
+            self.scopes.pop();
+            match checked {
+                Some(Type::Never) => (),
+                Some(checked) => {
+                    never = false;
+                    if used && tp.is_none() {
+                        tp = Some(checked);
+                    }
+                }
+                None => never = false,
+            }
+        }
+        match tp {
+            _ if never => Some(Type::Never),
+            Some(tp) if used => Some(tp),
+            _ => Some(Type::Void),
+        }
+    }
+
+    // `_` covers everything, an interface is covered by arms for every struct implementing it
+    fn exhaustive(&mut self, found: &Type, arms: &[Arm], span: Span) {
+        let covered = arms.iter()
+            .map(|arm| self.pattern_type(Some(found), arm.pattern()))
+            .collect::<Option<Vec<_>>>();
+        let covered = match covered {
+            Some(covered) => covered,
+            None => return,
+        };
+        if covered.contains(found) {
+            return;
+        }
+        let missing = match found {
+            Type::Named(interface, _) if self.interface(found) && names(interface) != [COMPARABLE] => {
+                let mut missing = self.structs.iter()
+                    .filter(|(_, structure)| structure.generics.is_empty())
+                    .filter(|(key, _)| {
+                        covered.iter().all(|tp| match tp {
+                            Type::Named(path, _) => names(path) != **key,
+                            _ => true,
+                        })
+                    })
+                    .filter(|(_, structure)| {
+                        let tp = Type::Named(structure.path.clone(), Vec::new());
+                        self.implements(&tp, interface).is_ok()
+                    })
+                    .map(|(_, structure)| structure.path.clone())
+                    .collect::<Vec<_>>();
+                if missing.is_empty() {
+                    return;
+                }
+                missing.sort_by_key(names);
+                missing.into_iter().map(|path| Type::Named(path, Vec::new())).collect()
+            }
+            // no end of values to list, only `_` covers them
+            _ => Vec::new(),
+        };
+        self.error(Error::NotExhaustive(span, missing));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pluma::check::Error;
+    use crate::pluma::check::tests::{check, errors};
+    use crate::pluma::ir::types::{Primitive, Type};
+
+    const SHAPES: &str = "struct Circle { r f64 }\nstruct Square { s f64 }\ninterface Shape { fn area() -> f64 }\n\
+        fn (const c Circle) area() -> f64 { c.r * c.r * 3.0 }\nfn (const s Square) area() -> f64 { s.s * s.s }\n";
+
+    fn shapes(body: &str) -> String {
+        format!("{}fn f(const s Shape) -> f64 {{\n    {}\n}}", SHAPES, body)
+    }
+
+    #[test]
+    fn narrowing() {
+        for body in &[
+            "if s is Circle { s.r } else { 0.0 }",
+            "if s is Circle{r} && r > 1.0 { r } else { 0.0 }",
+            "if s is Square{s: side} { side * 2.0 } else { s.area() }",
+            "s is { Circle{r} => r, Square{s} => s }",
+            "s is { Circle{r: x} => x, _ => 0 }",
+        ] {
+            let src = shapes(body);
+            assert!(check(&src).is_ok(), "{} {:?}", src, check(&src));
+        }
+        // `s` is a `Shape` again after the branch
+        let src = shapes("if s is Circle { 0.0 } else { 0.0 }\n    s.r");
+        match errors(&src).as_slice() {
+            [Error::NoField(name, Type::Named(_, _))] => assert_eq!(Some(name.span().start), src.rfind('r')),
+            errs => panic!("{:?}", errs),
+        }
+        let src = shapes("s is { Circle{r} => r, Square{s} => r }");
+        match errors(&src).as_slice() {
+            [Error::Undefined(name)] => assert_eq!(Some(name.span().start), src.rfind('r')),
+            errs => panic!("{:?}", errs),
+        }
+    }
+
+    #[test]
+    fn impossible_tests() {
+        let src = "struct Circle { r f64 }\nfn f(const x i32) -> bool { x is Circle }";
+        match errors(src).as_slice() {
+            [Error::ImpossibleTest(span, Type::Primitive(Primitive::I32), Type::Named(_, _))] => {
+                assert_eq!(Some(span.start), src.rfind("Circle"))
+            }
+            errs => panic!("{:?}", errs),
+        }
+        let src = format!("{}struct Line {{ len f64 }}\nfn f(const s Shape) -> bool {{ s is Line }}", SHAPES);
+        match errors(&src).as_slice() {
+            [Error::ImpossibleTest(span, _, _)] => assert_eq!(Some(span.start), src.rfind("Line")),
+            errs => panic!("{:?}", errs),
+        }
+        let src = shapes("if s is Circle{d} { d } else { 0.0 }");
+        match errors(&src).as_slice() {
+            [Error::NoField(name, _)] => assert_eq!(Some(name.span().start), src.rfind("d}")),
+            errs => panic!("{:?}", errs),
+        }
+        let src = shapes("if s is Oval { 1.0 } else { 0.0 }");
+        match errors(&src).as_slice() {
+            [Error::UndefinedType(path)] => assert_eq!(Some(path.span().start), src.find("Oval")),
+            errs => panic!("{:?}", errs),
+        }
+    }
+
+    #[test]
+    fn exhaustive() {
+        let src = shapes("s is { Circle{r} => r }");
+        match errors(&src).as_slice() {
+            [Error::NotExhaustive(span, missing)] => {
+                assert_eq!(Some(span.start), src.rfind("s is"));
+                assert!(matches!(missing.as_slice(), [Type::Named(path, _)] if path.path()[0].name() == "Square"));
+            }
+            errs => panic!("{:?}", errs),
+        }
+        let src = "fn f(const x i32) -> i32 { x is { i32 => 1 } }";
+        assert!(check(src).is_ok(), "{:?}", check(src));
+        let src = "fn f(const x i64) -> i32 { x is { i32 => 1 } }";
+        match errors(src).as_slice() {
+            [Error::ImpossibleTest(_, _, _), Error::NotExhaustive(_, missing)] => assert!(missing.is_empty()),
+            errs => panic!("{:?}", errs),
+        }
+    }
+}
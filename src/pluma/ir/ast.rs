@@ -0,0 +1,149 @@
+use nom::IResult;
+use nom::combinator::{map, opt};
+use nom::multi::{many0, separated_list};
+use nom::sequence::{pair, preceded, terminated};
+
+use crate::pluma::ir::define::Define;
+use crate::pluma::source::Span;
+use crate::pluma::token::{GlobalIdentifier, Identifier, Keyword, NomTrait, PlumaError, Tokens, sep, spanned};
+
+/// a whole file, the `package` header first, then its imports, then the defines
+///
+/// ```text
+/// package main
+///
+/// import io
+/// import geo.shapes as shapes
+///
+/// fn main() { }
+/// ```
+#[derive(Debug, Clone)]
+pub struct SourceFile {
+    package: Option<Identifier>,
+    imports: Vec<Import>,
+    defines: Vec<Define>,
+    span: Span,
+}
+
+/// `import a.b.c`, or `import a.b.c as x` to use it by another name
+#[derive(Debug, Clone)]
+pub struct Import {
+    path: GlobalIdentifier,
+    alias: Option<Identifier>,
+    span: Span,
+}
+
+impl SourceFile {
+    pub fn package(&self) -> Option<&Identifier> {
+        self.package.as_ref()
+    }
+    pub fn imports(&self) -> &[Import] {
+        self.imports.as_slice()
+    }
+    pub fn defines(&self) -> &[Define] {
+        self.defines.as_slice()
+    }
+    pub fn span(&self) -> Span {
+        self.span
+    }
+}
+
+impl Import {
+    pub fn path(&self) -> &GlobalIdentifier {
+        &self.path
+    }
+    pub fn alias(&self) -> Option<&Identifier> {
+        self.alias.as_ref()
+    }
+    /// name the file uses it by, `c` of `import a.b.c`
+    pub fn name(&self) -> &Identifier {
+        match &self.alias {
+            Some(alias) => alias,
+            None => self.path.path().last().expect("import of an empty path"),
+        }
+    }
+    pub fn span(&self) -> Span {
+        self.span
+    }
+}
+
+impl<'t> NomTrait<'t> for SourceFile {
+    fn nom(i: Tokens<'t>) -> IResult<Tokens<'t>, Self, PlumaError> {
+        map(
+            spanned(preceded(
+                opt(sep),
+                pair(
+                    pair(
+                        opt(terminated(preceded(Keyword::Package.matcher(), Identifier::nom), sep)),
+                        many0(terminated(Import::nom, sep)),
+                    ),
+                    terminated(separated_list(sep, Define::nom), opt(sep)),
+                ),
+            )),
+            |(((package, imports), defines), span)| SourceFile { package, imports, defines, span },
+        )(i)
+    }
+}
+
+impl<'t> NomTrait<'t> for Import {
+    fn nom(i: Tokens<'t>) -> IResult<Tokens<'t>, Self, PlumaError> {
+        map(
+            spanned(preceded(
+                Keyword::Import.matcher(),
+                pair(
+                    GlobalIdentifier::nom,
+                    opt(preceded(Keyword::As.matcher(), Identifier::nom)),
+                ),
+            )),
+            |((path, alias), span)| Import { path, alias, span },
+        )(i)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pluma::token::parse_str;
+
+    fn names(x: &GlobalIdentifier) -> Vec<&str> {
+        x.path().iter().map(|x| x.name()).collect()
+    }
+
+    #[test]
+    fn whole_file() {
+        let src = "\n// hello\npackage main\n\nimport io\nimport geo.shapes as sh\n\nfn main() { }\n\nstruct P { }\n";
+        let file = parse_str::<SourceFile>(src).unwrap();
+        assert_eq!(file.package().map(|x| x.name()), Some("main"));
+        let imports = file.imports().iter()
+            .map(|x| (names(x.path()), x.alias().map(|x| x.name()), x.name().name()))
+            .collect::<Vec<_>>();
+        assert_eq!(imports, vec![(vec!["io"], None, "io"), (vec!["geo", "shapes"], Some("sh"), "sh")]);
+        assert_eq!(Some(file.imports()[1].span().start), src.find("import geo"));
+        assert_eq!(file.imports()[1].span().end, src.find("sh\n").unwrap() + 2);
+        assert_eq!(file.defines().len(), 2);
+    }
+
+    #[test]
+    fn optional_parts() {
+        let file = parse_str::<SourceFile>("import io\nfn main() { }").unwrap();
+        assert!(file.package().is_none());
+        assert_eq!(file.imports().len(), 1);
+        let file = parse_str::<SourceFile>("package empty\n").unwrap();
+        assert!(file.imports().is_empty() && file.defines().is_empty());
+        assert!(parse_str::<SourceFile>("").unwrap().defines().is_empty());
+    }
+
+    #[test]
+    fn out_of_order() {
+        for (src, at) in &[
+            ("fn main() { }\nimport io", "import"),
+            ("import io\npackage main", "package"),
+            ("import io as\n", "import"),
+        ] {
+            match parse_str::<SourceFile>(src) {
+                Err(PlumaError::Left(tk)) => assert_eq!(Some(tk.span.start), src.find(at), "{}", src),
+                x => panic!("{} {:?}", src, x.map(|_| ())),
+            }
+        }
+    }
+}
@@ -1,40 +1,1014 @@
-use crate::pluma::token::{NomTrait, PlumaError, Control, ws, sep};
+use crate::pluma::token::{NomTrait, PlumaError, Control, Keyword, Identifier, GlobalIdentifier, newline, sep, Tokens, commit, spanned, comma_list};
+use crate::pluma::source::{Span, Spanned};
+use crate::pluma::ir::literal::Literal;
+use crate::pluma::ir::pattern::Pattern;
+use crate::pluma::ir::commons::Value;
+use crate::pluma::ir::types::{self, Type};
 use nom::IResult;
-use nom::sequence::{delimited, pair};
-use nom::combinator::{opt, map};
-use nom::multi::separated_list;
+use nom::branch::alt;
+use nom::sequence::{delimited, pair, preceded, terminated};
+use nom::combinator::{opt, map, peek};
+use nom::multi::{many1, separated_list};
 
 #[derive(Debug, Clone)]
 pub struct Block {
-    exprs : Vec<Expression>,
+    stmts : Vec<Statement>,
+    // last statement is an expression with no `;` after it
+    value : bool,
+    span : Span,
 }
 
 #[derive(Debug, Clone)]
-pub enum Expression {}
+pub enum Statement {
+    Declare(Declare),
+    /// `a = b`, or `a += b` with the operator of a compound assignment
+    Assign(Expression, Option<Binary>, Expression, Span),
+    Expression(Expression),
+}
 
+/// `var x i32 = 1`, `const name = "a"`, `ref r = other`
+///
+/// Either the type or the initializer may be left out, not both.
+#[derive(Debug, Clone)]
+pub struct Declare {
+    value: Value,
+    name: Identifier,
+    declare: Option<Spanned<Type>>,
+    define: Option<Expression>,
+    span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub enum Expression {
+    Literal(Spanned<Literal>),
+    Identifier(Identifier),
+    /// `io.println`, a package member or a field chain, resolved later
+    Path(GlobalIdentifier),
+    Field(Box<Expression>, Identifier, Span),
+    Call(Box<Expression>, Vec<Expression>, Span),
+    Unary(Unary, Box<Expression>, Span),
+    Binary(Binary, Box<Expression>, Box<Expression>, Span),
+    Block(Block),
+    /// `if cond { } else { }`, the else branch is either a `Block` or another `If`
+    If(Box<Expression>, Block, Option<Box<Expression>>, Span),
+    For(Loop, Block, Span),
+    Break(Option<Box<Expression>>, Span),
+    Continue(Span),
+    Return(Option<Box<Expression>>, Span),
+    /// `a..b`, end exclusive
+    Range(Box<Expression>, Box<Expression>, Span),
+    /// `Point{x: 1.0, y: 2.0}` or `Box<i32>{v: 1}`
+    Struct(GlobalIdentifier, Vec<Spanned<Type>>, Vec<(Identifier, Expression)>, Span),
+    /// `max<i32>`, a generic function given its type arguments
+    Instance(Box<Expression>, Vec<Spanned<Type>>, Span),
+    /// `shape is Circle{r}`, a `bool` which also narrows `shape` and binds `r` where it holds
+    Is(Box<Expression>, Pattern, Span),
+    /// `shape is { Circle{r} => r * r, _ => 0.0 }`, the first arm matching is taken
+    Match(Box<Expression>, Vec<Arm>, Span),
+    /// `[1, 2, 3]`
+    Array(Vec<Expression>, Span),
+    /// `[0; n]`, `n` copies of the value
+    Repeat(Box<Expression>, Box<Expression>, Span),
+    /// `a[i]`, or `a[i..j]` which is the slice of `a` from `i` to `j`
+    ///
+    /// Out of bounds panics with `runtime::panic::Panic::Index` or `Slice`.
+    Index(Box<Expression>, Box<Expression>, Span),
+    /// `x as u8`, see `cast::Cast` for what it does to the value
+    Cast(Box<Expression>, Spanned<Type>, Span),
+    /// `with f = open("x") { }` over a `Closer`, `f.close()` runs however the block is left,
+    /// by its end, a `return`, a `break` or `continue` out of it or a panic
+    With(Identifier, Box<Expression>, Block, Span),
+}
+
+#[derive(Debug, Clone)]
+pub struct Arm {
+    pattern: Pattern,
+    value: Expression,
+    span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub enum Loop {
+    /// `for { }`, ends only by `break`
+    Forever,
+    /// `for cond { }`
+    While(Box<Expression>),
+    /// `for x in xs { }` over an array or a range
+    In(Identifier, Box<Expression>),
+}
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone, Hash)]
+pub enum Unary {
+    Neg,
+    Not,
+}
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone, Hash)]
+pub enum Binary {
+    Or,
+    And,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    BitOr,
+    BitAnd,
+    ShiftLeft,
+    ShiftRight,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Pow,
+}
+
+impl Block {
+    pub fn statements(&self) -> &[Statement] {
+        self.stmts.as_slice()
+    }
+    /// `{ a; b }` and `{ a\n b\n }` are `b`, `{ a; b; }` has no value
+    pub fn value(&self) -> Option<&Expression> {
+        match self.stmts.last() {
+            Some(Statement::Expression(x)) if self.value => Some(x),
+            _ => None,
+        }
+    }
+    pub fn span(&self) -> Span {
+        self.span
+    }
+}
 
 impl<'t> NomTrait<'t> for Block {
-    fn nom(i: &'t str) -> IResult<&'t str, Self, PlumaError<'t>> {
+    fn nom(i: Tokens<'t>) -> IResult<Tokens<'t>, Self, PlumaError> {
         map(
-            delimited(
+            spanned(delimited(
                 pair(Control::BlockStart.matcher(), opt(sep)),
-                separated_list(
-                    sep,
-                    Expression::nom,
+                pair(
+                    separated_list(
+                        sep,
+                        Statement::nom,
+                    ),
+                    opt(terminator),
                 ),
-                pair(opt(sep), Control::BlockEnd.matcher()),
-            ),
-            |x|{
+                // whatever stopped the statements is what is wrong in the block
+                commit(Control::BlockEnd.matcher()),
+            )),
+            |((x, semicolon), span)|{
                 Block{
-                    exprs : x,
+                    stmts : x,
+                    value : semicolon != Some(true),
+                    span,
                 }
             }
         )(i)
     }
 }
 
+// separators after the last statement, whether one of them is `;`
+fn terminator(i: Tokens) -> IResult<Tokens, bool, PlumaError> {
+    map(
+        many1(alt((
+            map(newline, |_| false),
+            map(Control::Semicolon.matcher(), |_| true),
+        ))),
+        |x| x.contains(&true),
+    )(i)
+}
+
+impl Statement {
+    pub fn span(&self) -> Span {
+        match self {
+            Statement::Declare(x) => x.span,
+            Statement::Assign(_, _, _, span) => *span,
+            Statement::Expression(x) => x.span(),
+        }
+    }
+
+    // `=` is plain, `+=` `-=` `*=` `/=` `%=` carry their operator
+    fn assign_operator(i: Tokens) -> IResult<Tokens, Option<Binary>, PlumaError> {
+        let (left, ctl) = Control::any_matcher()(i)?;
+        match ctl {
+            Control::Equal => Ok((left, None)),
+            Control::PlusEqual => Ok((left, Some(Binary::Add))),
+            Control::MinusEqual => Ok((left, Some(Binary::Sub))),
+            Control::MulEqual => Ok((left, Some(Binary::Mul))),
+            Control::SlashEqual => Ok((left, Some(Binary::Div))),
+            Control::ModEqual => Ok((left, Some(Binary::Mod))),
+            _ => Err(nom::Err::Error(PlumaError::NotOperator)),
+        }
+    }
+}
+
+impl<'t> NomTrait<'t> for Statement {
+    fn nom(i: Tokens<'t>) -> IResult<Tokens<'t>, Self, PlumaError> {
+        alt((
+            map(Declare::nom, Statement::Declare),
+            // once `=` is there the right side has to follow, its error is the one to report
+            |i| {
+                let (i, target) = Expression::nom(i)?;
+                match Self::assign_operator(i) {
+                    Ok((i, op)) => {
+                        let (i, value) = commit(Expression::nom)(i)?;
+                        let span = target.span().join(value.span());
+                        Ok((i, Statement::Assign(target, op, value, span)))
+                    }
+                    Err(_) => Ok((i, Statement::Expression(target))),
+                }
+            },
+        ))(i)
+    }
+}
+
+impl Declare {
+    pub fn value(&self) -> &Value {
+        &self.value
+    }
+    pub fn name(&self) -> &Identifier {
+        &self.name
+    }
+    pub fn declare(&self) -> Option<&Spanned<Type>> {
+        self.declare.as_ref()
+    }
+    pub fn define(&self) -> Option<&Expression> {
+        self.define.as_ref()
+    }
+    pub fn span(&self) -> Span {
+        self.span
+    }
+}
+
+impl<'t> NomTrait<'t> for Declare {
+    fn nom(i: Tokens<'t>) -> IResult<Tokens<'t>, Self, PlumaError> {
+        let (left, (((value, name), (declare, define)), span)) = spanned(pair(
+            pair(Value::nom, commit(Identifier::nom)),
+            pair(
+                opt(Spanned::<Type>::nom),
+                opt(preceded(Control::Equal.matcher(), commit(Expression::nom))),
+            ),
+        ))(i)?;
+        if declare.is_none() && define.is_none() {
+            return Err(nom::Err::Failure(PlumaError::UntypedDeclare(name)));
+        }
+        Ok((left, Declare { value, name, declare, define, span }))
+    }
+}
+
+impl Arm {
+    pub fn pattern(&self) -> &Pattern {
+        &self.pattern
+    }
+    pub fn value(&self) -> &Expression {
+        &self.value
+    }
+    pub fn span(&self) -> Span {
+        self.span
+    }
+}
+
+impl<'t> NomTrait<'t> for Arm {
+    fn nom(i: Tokens<'t>) -> IResult<Tokens<'t>, Self, PlumaError> {
+        map(
+            spanned(pair(Pattern::nom, preceded(Control::FatArrow.matcher(), Expression::nom))),
+            |((pattern, value), span)| Arm { pattern, value, span },
+        )(i)
+    }
+}
+
+impl Loop {
+    pub fn is_forever(&self) -> bool {
+        matches!(self, Loop::Forever)
+    }
+}
+
+impl Binary {
+    /// Lowest binds loosest, every level is left associative but `^`
+    ///
+    /// | level | operators |
+    /// |---|---|
+    /// | 1 | `\|\|` `or` |
+    /// | 2 | `&&` `and` |
+    /// | 3 | `==` `!=` `<` `<=` `>` `>=` |
+    /// | 4 | `\|` |
+    /// | 5 | `&` |
+    /// | 6 | `<<` `>>` |
+    /// | 7 | `+` `-` |
+    /// | 8 | `*` `/` `%` |
+    /// | 9 | unary `-` `!` |
+    /// | 10 | `^`, right associative |
+    pub fn precedence(&self) -> u8 {
+        match self {
+            Binary::Or => 1,
+            Binary::And => 2,
+            Binary::Equal | Binary::NotEqual => 3,
+            Binary::Less | Binary::LessEqual | Binary::Greater | Binary::GreaterEqual => 3,
+            Binary::BitOr => 4,
+            Binary::BitAnd => 5,
+            Binary::ShiftLeft | Binary::ShiftRight => 6,
+            Binary::Add | Binary::Sub => 7,
+            Binary::Mul | Binary::Div | Binary::Mod => 8,
+            Binary::Pow => 10,
+        }
+    }
+    pub fn from_control(ctl: Control) -> Option<Binary> {
+        match ctl {
+            Control::OrOr => Some(Binary::Or),
+            Control::AndAnd => Some(Binary::And),
+            Control::EqualEqual => Some(Binary::Equal),
+            Control::NotEqual => Some(Binary::NotEqual),
+            Control::LESS => Some(Binary::Less),
+            Control::LessEqual => Some(Binary::LessEqual),
+            Control::GREATER => Some(Binary::Greater),
+            Control::GreaterEqual => Some(Binary::GreaterEqual),
+            Control::Or => Some(Binary::BitOr),
+            Control::And => Some(Binary::BitAnd),
+            Control::ShiftLeft => Some(Binary::ShiftLeft),
+            Control::ShiftRight => Some(Binary::ShiftRight),
+            Control::Plus => Some(Binary::Add),
+            Control::Minus => Some(Binary::Sub),
+            Control::Mul => Some(Binary::Mul),
+            Control::Slash => Some(Binary::Div),
+            Control::Mod => Some(Binary::Mod),
+            Control::Pow => Some(Binary::Pow),
+            _ => None,
+        }
+    }
+}
+
+impl<'t> NomTrait<'t> for Binary {
+    fn nom(i: Tokens<'t>) -> IResult<Tokens<'t>, Self, PlumaError> {
+        alt((
+            map(Keyword::Or.matcher(), |_| Binary::Or),
+            map(Keyword::And.matcher(), |_| Binary::And),
+            |i| {
+                let (left, ctl) = Control::any_matcher()(i)?;
+                match Binary::from_control(ctl) {
+                    Some(op) => Ok((left, op)),
+                    None => Err(nom::Err::Error(PlumaError::NotOperator)),
+                }
+            },
+        ))(i)
+    }
+}
+
+impl<'t> NomTrait<'t> for Unary {
+    fn nom(i: Tokens<'t>) -> IResult<Tokens<'t>, Self, PlumaError> {
+        alt((
+            map(Control::Minus.matcher(), |_| Unary::Neg),
+            map(Control::Not.matcher(), |_| Unary::Not),
+        ))(i)
+    }
+}
+
+impl Expression {
+    pub fn span(&self) -> Span {
+        match self {
+            Expression::Literal(x) => x.span,
+            Expression::Identifier(x) => x.span(),
+            Expression::Path(x) => x.span(),
+            Expression::Field(_, _, span) => *span,
+            Expression::Call(_, _, span) => *span,
+            Expression::Unary(_, _, span) => *span,
+            Expression::Binary(_, _, _, span) => *span,
+            Expression::Block(x) => x.span(),
+            Expression::If(_, _, _, span) => *span,
+            Expression::For(_, _, span) => *span,
+            Expression::Break(_, span) => *span,
+            Expression::Continue(span) => *span,
+            Expression::Return(_, span) => *span,
+            Expression::Range(_, _, span) => *span,
+            Expression::Struct(_, _, _, span) => *span,
+            Expression::Instance(_, _, span) => *span,
+            Expression::Is(_, _, span) => *span,
+            Expression::Match(_, _, span) => *span,
+            Expression::With(_, _, _, span) => *span,
+            Expression::Cast(_, _, span) => *span,
+            Expression::Array(_, span) => *span,
+            Expression::Repeat(_, _, span) => *span,
+            Expression::Index(_, _, span) => *span,
+        }
+    }
+
+    // `{` right after `for` is the body, not a block condition, and `for x in` goes before `for cond`
+    fn repeat(i: Tokens) -> IResult<Tokens, Expression, PlumaError> {
+        map(
+            spanned(preceded(
+                Keyword::For.matcher(),
+                pair(
+                    alt((
+                        map(peek(Control::BlockStart.matcher()), |_| Loop::Forever),
+                        map(
+                            pair(Identifier::nom, preceded(Keyword::In.matcher(), Expression::condition)),
+                            |(x, xs)| Loop::In(x, Box::new(xs)),
+                        ),
+                        map(Expression::condition, |x| Loop::While(Box::new(x))),
+                    )),
+                    Block::nom,
+                ),
+            )),
+            |((kind, body), span)| Expression::For(kind, body, span),
+        )(i)
+    }
+
+    fn jump(i: Tokens) -> IResult<Tokens, Expression, PlumaError> {
+        alt((
+            map(
+                spanned(preceded(Keyword::Break.matcher(), opt(Expression::nom))),
+                |(x, span)| Expression::Break(x.map(Box::new), span),
+            ),
+            map(
+                spanned(Keyword::Continue.matcher()),
+                |(_, span)| Expression::Continue(span),
+            ),
+            map(
+                spanned(preceded(Keyword::Return.matcher(), opt(Expression::nom))),
+                |(x, span)| Expression::Return(x.map(Box::new), span),
+            ),
+        ))(i)
+    }
+
+    fn resource(i: Tokens) -> IResult<Tokens, Expression, PlumaError> {
+        map(
+            spanned(preceded(
+                Keyword::With.matcher(),
+                pair(
+                    pair(Identifier::nom, preceded(Control::Equal.matcher(), Expression::condition)),
+                    Block::nom,
+                ),
+            )),
+            |(((name, x), body), span)| Expression::With(name, Box::new(x), body, span),
+        )(i)
+    }
+
+    // `else if` chains nest, `if a {} else if b {} else {}` is `If(a, {}, If(b, {}, {}))`
+    fn branch(i: Tokens) -> IResult<Tokens, Expression, PlumaError> {
+        map(
+            spanned(pair(
+                preceded(Keyword::If.matcher(), pair(Expression::condition, Block::nom)),
+                opt(preceded(
+                    Keyword::Else.matcher(),
+                    alt((
+                        Self::branch,
+                        map(Block::nom, Expression::Block),
+                    )),
+                )),
+            )),
+            |(((cond, then), other), span)| {
+                Expression::If(Box::new(cond), then, other.map(Box::new), span)
+            },
+        )(i)
+    }
+
+    // `structs` is off in `if` and `for` heads, where the `{` after a name opens the body
+    fn primary(i: Tokens, structs: bool) -> IResult<Tokens, Expression, PlumaError> {
+        alt((
+            map(Spanned::<Literal>::nom, Expression::Literal),
+            |i| if structs { Self::structure(i) } else { Err(nom::Err::Error(PlumaError::NotExpression)) },
+            map(GlobalIdentifier::nom, |x| {
+                match x.path() {
+                    [ident] => Expression::Identifier(ident.clone()),
+                    _ => Expression::Path(x),
+                }
+            }),
+            delimited(Control::GroupStart.matcher(), Expression::nom, Control::GroupEnd.matcher()),
+            Self::branch,
+            Self::repeat,
+            Self::jump,
+            Self::resource,
+            Self::array,
+            map(Block::nom, Expression::Block),
+        ))(i).map_err(|err| match err {
+            nom::Err::Error(_) => nom::Err::Error(PlumaError::NotExpression),
+            err => err,
+        })
+    }
+
+    /// `Point{x: 1.0, y: 2.0}`
+    fn structure(i: Tokens) -> IResult<Tokens, Expression, PlumaError> {
+        map(
+            spanned(pair(
+                pair(GlobalIdentifier::nom, opt(types::arguments)),
+                delimited(
+                    Control::BlockStart.matcher(),
+                    comma_list(pair(Identifier::nom, preceded(Control::Meta.matcher(), Expression::nom))),
+                    pair(opt(newline), Control::BlockEnd.matcher()),
+                ),
+            )),
+            |(((name, args), fields), span)| Expression::Struct(name, args.unwrap_or_default(), fields, span),
+        )(i)
+    }
+
+    // `[0; n]` before `[1, 2]`, both are the same up to `;`
+    fn array(i: Tokens) -> IResult<Tokens, Expression, PlumaError> {
+        alt((
+            map(
+                spanned(delimited(
+                    Control::ArrayStart.matcher(),
+                    pair(Expression::nom, preceded(Control::Semicolon.matcher(), Expression::nom)),
+                    Control::ArrayEnd.matcher(),
+                )),
+                |((x, n), span)| Expression::Repeat(Box::new(x), Box::new(n), span),
+            ),
+            map(
+                spanned(delimited(
+                    Control::ArrayStart.matcher(),
+                    comma_list(Expression::nom),
+                    Control::ArrayEnd.matcher(),
+                )),
+                |(xs, span)| Expression::Array(xs, span),
+            ),
+        ))(i)
+    }
+
+    /// `(1, 2,)` arguments of a call, a trailing comma is allowed
+    pub fn arguments(i: Tokens) -> IResult<Tokens, Vec<Expression>, PlumaError> {
+        delimited(
+            Control::GroupStart.matcher(),
+            comma_list(Expression::nom),
+            Control::GroupEnd.matcher(),
+        )(i)
+    }
+
+    // `<`types`>` right before `(`, otherwise `<` compares
+    fn instance(i: Tokens) -> IResult<Tokens, Vec<Spanned<Type>>, PlumaError> {
+        terminated(types::arguments, peek(Control::GroupStart.matcher()))(i)
+    }
+
+    // calls, indexing and field access chain to the left, `a.b().c(1)` is `((a.b)()).c(1)`
+    fn postfix(i: Tokens, structs: bool) -> IResult<Tokens, Expression, PlumaError> {
+        let (mut i, mut x) = Self::primary(i, structs)?;
+        if let Expression::Identifier(_) | Expression::Path(_) = x {
+            if let Ok((left, (args, span))) = spanned(Self::instance)(i) {
+                let span = x.span().join(span);
+                x = Expression::Instance(Box::new(x), args, span);
+                i = left;
+            }
+        }
+        loop {
+            if let Ok((left, (args, span))) = spanned(Self::arguments)(i) {
+                let span = x.span().join(span);
+                x = Expression::Call(Box::new(x), args, span);
+                i = left;
+            } else if let Ok((left, (index, span))) = spanned(delimited(
+                Control::ArrayStart.matcher(),
+                Expression::nom,
+                Control::ArrayEnd.matcher(),
+            ))(i) {
+                let span = x.span().join(span);
+                x = Expression::Index(Box::new(x), Box::new(index), span);
+                i = left;
+            } else if let Ok((left, field)) = preceded(Control::Dot.matcher(), Identifier::nom)(i) {
+                let span = x.span().join(field.span());
+                x = Expression::Field(Box::new(x), field, span);
+                i = left;
+            } else {
+                return Ok((i, x));
+            }
+        }
+    }
+
+    // `^` binds tighter than unary minus, `-2^2` is `-(2^2)`
+    fn power(i: Tokens, structs: bool) -> IResult<Tokens, Expression, PlumaError> {
+        let (i, base) = Self::postfix(i, structs)?;
+        match Control::Pow.matcher()(i) {
+            Ok((i, _)) => {
+                let (i, exp) = Self::unary(i, structs)?;
+                let span = base.span().join(exp.span());
+                Ok((i, Expression::Binary(Binary::Pow, Box::new(base), Box::new(exp), span)))
+            }
+            Err(_) => Ok((i, base)),
+        }
+    }
+
+    fn unary(i: Tokens, structs: bool) -> IResult<Tokens, Expression, PlumaError> {
+        alt((
+            map(
+                spanned(pair(Unary::nom, |i| Self::unary(i, structs))),
+                |((op, x), span)| Expression::Unary(op, Box::new(x), span),
+            ),
+            |i| Self::power(i, structs),
+        ))(i)
+    }
+
+    // `as` binds tighter than any binary operator but looser than unary ones, `-x as u8 * 2` is `((-x) as u8) * 2`
+    fn cast(i: Tokens, structs: bool) -> IResult<Tokens, Expression, PlumaError> {
+        let (mut i, mut x) = Self::unary(i, structs)?;
+        while let Ok((left, tp)) = preceded(Keyword::As.matcher(), Spanned::<Type>::nom)(i) {
+            let span = x.span().join(tp.span);
+            x = Expression::Cast(Box::new(x), tp, span);
+            i = left;
+        }
+        Ok((i, x))
+    }
+
+    // precedence climbing over `Binary::precedence`, `Pow` never shows up here
+    //
+    // `is` binds like a comparison, `a + b is i32 && c` is `((a + b) is i32) && c`
+    fn climb(i: Tokens, min: u8, structs: bool) -> IResult<Tokens, Expression, PlumaError> {
+        let (mut i, mut lhs) = Self::cast(i, structs)?;
+        loop {
+            if min <= Binary::Equal.precedence() {
+                if let Ok((left, _)) = Keyword::Is.matcher()(i) {
+                    let (left, x) = Self::test(left, lhs, structs)?;
+                    lhs = x;
+                    i = left;
+                    continue;
+                }
+            }
+            match Binary::nom(i) {
+                Ok((left, op)) if op.precedence() >= min => {
+                    let (left, rhs) = Self::climb(left, op.precedence() + 1, structs)?;
+                    let span = lhs.span().join(rhs.span());
+                    lhs = Expression::Binary(op, Box::new(lhs), Box::new(rhs), span);
+                    i = left;
+                }
+                _ => return Ok((i, lhs)),
+            }
+        }
+    }
+
+    // what follows `is`, the arms in `{ }` or a single pattern
+    fn test(i: Tokens, x: Expression, structs: bool) -> IResult<Tokens, Expression, PlumaError> {
+        if structs {
+            let arms = delimited(
+                pair(Control::BlockStart.matcher(), opt(sep)),
+                comma_list(terminated(Arm::nom, opt(newline))),
+                pair(opt(sep), Control::BlockEnd.matcher()),
+            );
+            if let Ok((left, (arms, span))) = spanned(arms)(i) {
+                let span = x.span().join(span);
+                return Ok((left, Expression::Match(Box::new(x), arms, span)));
+            }
+        }
+        let (left, pattern) = Pattern::nom_with(i, structs)?;
+        let span = x.span().join(pattern.span());
+        Ok((left, Expression::Is(Box::new(x), pattern, span)))
+    }
+
+    // `..` binds loosest of all, `0..n + 1` ends at `n + 1`
+    fn range(i: Tokens, structs: bool) -> IResult<Tokens, Expression, PlumaError> {
+        let (i, start) = Self::climb(i, 1, structs)?;
+        match preceded(Control::DotDot.matcher(), |i| Self::climb(i, 1, structs))(i) {
+            Ok((i, end)) => {
+                let span = start.span().join(end.span());
+                Ok((i, Expression::Range(Box::new(start), Box::new(end), span)))
+            }
+            Err(_) => Ok((i, start)),
+        }
+    }
+
+    /// expression right before a block, a struct literal there has to be in parentheses
+    pub fn condition(i: Tokens) -> IResult<Tokens, Expression, PlumaError> {
+        Self::range(i, false)
+    }
+}
+
 impl<'t> NomTrait<'t> for Expression {
-    fn nom(i: &'t str) -> IResult<&'t str, Self, PlumaError<'t>> {
-        Err(nom::Err::Error(PlumaError::Unknown))
+    fn nom(i: Tokens<'t>) -> IResult<Tokens<'t>, Self, PlumaError> {
+        Self::range(i, true)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pluma::token::parse_str;
+    use crate::pluma::token::literal::Numeric;
+
+    // the tree as `(Op lhs rhs)`, enough to see how operators grouped
+    fn tree(x: &Expression) -> String {
+        match x {
+            Expression::Literal(lit) => match &lit.node {
+                Literal::Numeric(Numeric::Integer(value, None)) => value.to_string(),
+                lit => format!("{:?}", lit),
+            },
+            Expression::Identifier(ident) => ident.name().to_string(),
+            Expression::Path(path) => path.path().iter().map(|x| x.name()).collect::<Vec<_>>().join("."),
+            Expression::Field(x, field, _) => format!("(Field {} {})", tree(x), field.name()),
+            Expression::Unary(op, x, _) => format!("({:?} {})", op, tree(x)),
+            Expression::Binary(op, lhs, rhs, _) => format!("({:?} {} {})", op, tree(lhs), tree(rhs)),
+            Expression::Cast(x, _, _) => format!("(As {})", tree(x)),
+            Expression::Range(start, end, _) => format!("(Range {} {})", tree(start), tree(end)),
+            Expression::Is(x, _, _) => format!("(Is {})", tree(x)),
+            Expression::Array(items, _) => format!("[{}]", items.iter().map(tree).collect::<Vec<_>>().join(" ")),
+            Expression::Repeat(x, n, _) => format!("(Repeat {} {})", tree(x), tree(n)),
+            Expression::Index(x, index, _) => format!("(Index {} {})", tree(x), tree(index)),
+            Expression::Call(f, args, _) => {
+                let args: Vec<_> = args.iter().map(tree).collect();
+                format!("(Call {} {})", tree(f), args.join(" "))
+            }
+            x => format!("{:?}", x),
+        }
+    }
+
+    fn parse(src: &str) -> String {
+        tree(&parse_str::<Expression>(src).unwrap())
+    }
+
+    #[test]
+    fn calls() {
+        assert_eq!(parse("f(a, b,)"), "(Call f a b)");
+        assert_eq!(parse("f()"), "(Call f )");
+        assert_eq!(parse("io.println(1)"), "(Call io.println 1)");
+        assert_eq!(parse("f(1)(2)"), "(Call (Call f 1) 2)");
+        assert_eq!(parse("a.b().c(1)"), "(Call (Field (Call a.b ) c) 1)");
+        assert_eq!(parse("f(\n    1,\n    g(2)\n)"), "(Call f 1 (Call g 2))");
+        assert_eq!(parse("-f(1) * 2"), "(Mul (Neg (Call f 1)) 2)");
+        assert!(parse_str::<Expression>("f(,)").is_err());
+        assert!(parse_str::<Expression>("f(1,,)").is_err());
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(parse("1 + 2 * 3"), "(Add 1 (Mul 2 3))");
+        assert_eq!(parse("1 * 2 + 3"), "(Add (Mul 1 2) 3)");
+        assert_eq!(parse("a - b - c"), "(Sub (Sub a b) c)");
+        assert_eq!(parse("a / b % c"), "(Mod (Div a b) c)");
+        assert_eq!(parse("(1 + 2) * 3"), "(Mul (Add 1 2) 3)");
+    }
+
+    #[test]
+    fn power() {
+        assert_eq!(parse("2^3^2"), "(Pow 2 (Pow 3 2))");
+        assert_eq!(parse("-2^2"), "(Neg (Pow 2 2))");
+        assert_eq!(parse("2 * 3^2"), "(Mul 2 (Pow 3 2))");
+        assert_eq!(parse("(2^3)^2"), "(Pow (Pow 2 3) 2)");
+    }
+
+    #[test]
+    fn unary() {
+        assert_eq!(parse("-a * b"), "(Mul (Neg a) b)");
+        assert_eq!(parse("!a && b"), "(And (Not a) b)");
+        assert_eq!(parse("!!a"), "(Not (Not a))");
+        assert_eq!(parse("-f(x)"), "(Neg (Call f x))");
+    }
+
+    #[test]
+    fn logic() {
+        assert_eq!(parse("a || b && c"), "(Or a (And b c))");
+        assert_eq!(parse("a && b || c"), "(Or (And a b) c)");
+        assert_eq!(parse("a or b and c"), "(Or a (And b c))");
+        assert_eq!(parse("a < b && b < c"), "(And (Less a b) (Less b c))");
+        assert_eq!(parse("a == b || c != d"), "(Or (Equal a b) (NotEqual c d))");
+    }
+
+    #[test]
+    fn bitwise() {
+        assert_eq!(parse("a | b & c"), "(BitOr a (BitAnd b c))");
+        assert_eq!(parse("a & b == c"), "(Equal (BitAnd a b) c)");
+        assert_eq!(parse("a | b < c"), "(Less (BitOr a b) c)");
+        assert_eq!(parse("1 << 2 + 3"), "(ShiftLeft 1 (Add 2 3))");
+        assert_eq!(parse("a & b << c"), "(BitAnd a (ShiftLeft b c))");
+    }
+
+    #[test]
+    fn cast_and_range() {
+        assert_eq!(parse("-x as u8 * 2"), "(Mul (As (Neg x)) 2)");
+        assert_eq!(parse("a + b as i64"), "(Add a (As b))");
+        assert_eq!(parse("0..n - 1"), "(Range 0 (Sub n 1))");
+        assert_eq!(parse("a + 1 is x"), "(Is (Add a 1))");
+    }
+
+    fn statement(src: &str) -> Statement {
+        let block = parse_str::<Block>(&format!("{{ {} }}", src)).expect(src);
+        block.statements()[0].clone()
+    }
+
+    #[test]
+    fn declarations() {
+        for (src, value, name, typed, defined) in &[
+            ("var a i32 = 1", Value::Variable, "a", true, true),
+            ("const b = f(1)", Value::Constant, "b", false, true),
+            ("ref r = a[0]", Value::Reference, "r", false, true),
+            ("var x []u8", Value::Variable, "x", true, false),
+        ] {
+            match statement(src) {
+                Statement::Declare(x) => {
+                    assert_eq!((x.value(), x.name().name()), (value, *name), "{}", src);
+                    assert_eq!((x.declare().is_some(), x.define().is_some()), (*typed, *defined), "{}", src);
+                }
+                x => panic!("{} {:?}", src, x),
+            }
+        }
+        match parse_str::<Block>("{ var x }") {
+            Err(PlumaError::UntypedDeclare(x)) => assert_eq!(x.span().start, 6),
+            x => panic!("{:?}", x),
+        }
+    }
+
+    #[test]
+    fn assignments() {
+        for (src, op) in &[("a = 1", None), ("a.b[0] += 1", Some(Binary::Add)), ("a %= 2", Some(Binary::Mod))] {
+            match statement(src) {
+                Statement::Assign(_, found, _, span) => {
+                    assert_eq!(found, *op, "{}", src);
+                    assert_eq!((span.start, span.end), (2, 2 + src.len()), "{}", src);
+                }
+                x => panic!("{} {:?}", src, x),
+            }
+        }
+        assert!(parse_str::<Block>("{ a = b = 1 }").is_err());
+    }
+
+    #[test]
+    fn branches() {
+        match parse_str::<Expression>("if a { 1 } else if b { 2 } else { 3 }").unwrap() {
+            Expression::If(a, _, Some(other), span) => {
+                assert_eq!(tree(&a), "a");
+                assert_eq!((span.start, span.end), (0, 37));
+                match *other {
+                    Expression::If(b, _, Some(other), span) => {
+                        assert_eq!(tree(&b), "b");
+                        assert_eq!(span.start, 16);
+                        assert!(matches!(*other, Expression::Block(_)));
+                    }
+                    x => panic!("{:?}", x),
+                }
+            }
+            x => panic!("{:?}", x),
+        }
+        // the `{` after a name opens the body, not a struct literal
+        match parse_str::<Expression>("if x { y }").unwrap() {
+            Expression::If(x, then, None, _) => {
+                assert_eq!(tree(&x), "x");
+                assert_eq!(then.value().map(tree), Some("y".to_string()));
+            }
+            x => panic!("{:?}", x),
+        }
+        assert!(parse_str::<Expression>("if a { } else b").is_err());
+        assert!(parse_str::<Expression>("if a 1").is_err());
+    }
+
+    #[test]
+    fn loops() {
+        let head = |src: &str| match parse_str::<Expression>(src).expect(src) {
+            Expression::For(Loop::Forever, _, _) => "forever".to_string(),
+            Expression::For(Loop::While(cond), _, _) => format!("while {}", tree(&cond)),
+            Expression::For(Loop::In(x, xs), _, _) => format!("{} in {}", x.name(), tree(&xs)),
+            x => panic!("{} {:?}", src, x),
+        };
+        assert_eq!(head("for { }"), "forever");
+        assert_eq!(head("for a < b { }"), "while (Less a b)");
+        assert_eq!(head("for i in 0..n { }"), "i in (Range 0 n)");
+        assert_eq!(head("for x in xs { x }"), "x in xs");
+        match parse_str::<Block>("{ for { break 1 }\n continue\n break }").unwrap().statements() {
+            [Statement::Expression(Expression::For(_, body, _)), Statement::Expression(Expression::Continue(_)), Statement::Expression(Expression::Break(None, _))] => {
+                assert!(matches!(body.value(), Some(Expression::Break(Some(_), _))));
+            }
+            x => panic!("{:?}", x),
+        }
+        assert!(parse_str::<Expression>("for x in { }").is_err());
+    }
+
+    #[test]
+    fn struct_literals() {
+        let fields = |src: &str| match parse_str::<Expression>(src).expect(src) {
+            Expression::Struct(path, args, values, _) => {
+                let values = values.iter().map(|(name, x)| format!("{}: {}", name.name(), tree(x))).collect::<Vec<_>>();
+                format!("{}<{}>{{{}}}", path.path().len(), args.len(), values.join(", "))
+            }
+            x => panic!("{} {:?}", src, x),
+        };
+        assert_eq!(fields("P{x: 1, y: a + 2}"), "1<0>{x: 1, y: (Add a 2)}");
+        assert_eq!(fields("geo.P{x: 1,}"), "2<0>{x: 1}");
+        assert_eq!(fields("Box<i32>{\n    v: 1,\n}"), "1<1>{v: 1}");
+        assert_eq!(fields("P{}"), "1<0>{}");
+        assert!(matches!(parse_str::<Expression>("P{x: 1}.x").expect("field"), Expression::Field(_, _, _)));
+    }
+
+    #[test]
+    fn type_tests() {
+        assert_eq!(parse("x is i32 && y"), "(And (Is x) y)");
+        assert_eq!(parse("!done || s is Circle{r}"), "(Or (Not done) (Is s))");
+        for src in &[
+            "s is { Circle{r} => r * r, Square{s: side} => side, _ => 0 }",
+            "s is {\n    Circle{r} => r * r,\n    Square{s: side} => side,\n    _ => 0,\n}",
+        ] {
+            match parse_str::<Expression>(src) {
+                Ok(Expression::Match(x, arms, span)) => {
+                    assert_eq!(tree(&x), "s");
+                    let values = arms.iter().map(|arm| tree(arm.value())).collect::<Vec<_>>();
+                    assert_eq!(values, vec!["(Mul r r)", "side", "0"], "{}", src);
+                    assert!(matches!(arms[2].pattern(), Pattern::Any(_)));
+                    assert_eq!((span.start, span.end), (0, src.len()));
+                }
+                x => panic!("{} {:?}", src, x),
+            }
+        }
+        assert!(parse_str::<Expression>("s is { Circle{r} => r Square{s} => s }").is_err());
+    }
+
+    #[test]
+    fn with_blocks() {
+        let src = "with f = open(\"a\") {\n    f.read()\n}";
+        match parse_str::<Expression>(src) {
+            Ok(Expression::With(name, x, body, span)) => {
+                assert_eq!(name.name(), "f");
+                assert_eq!(tree(&x), format!("(Call open {})", tree(&parse_str::<Expression>("\"a\"").unwrap())));
+                assert_eq!(body.value().map(tree), Some("(Call f.read )".to_string()));
+                assert_eq!((span.start, span.end), (0, src.len()));
+            }
+            x => panic!("{:?}", x),
+        }
+        match parse_str::<Expression>("with f = open(1) { 1 } + 1") {
+            Ok(Expression::Binary(Binary::Add, x, _, _)) => assert!(matches!(*x, Expression::With(_, _, _, _))),
+            x => panic!("{:?}", x),
+        }
+        // the head is a condition, a struct literal there needs parentheses
+        assert!(parse_str::<Expression>("with f = (File{}) { }").is_ok());
+        assert!(parse_str::<Expression>("with f = File{} { }").is_err());
+        assert!(parse_str::<Expression>("with open(1) { }").is_err());
+        assert!(parse_str::<Expression>("with f = open(1)").is_err());
+    }
+
+    #[test]
+    fn arrays() {
+        assert_eq!(parse("[1, 2, a + 1,]"), "[1 2 (Add a 1)]");
+        assert_eq!(parse("[]"), "[]");
+        assert_eq!(parse("[[1], [2, 3]]"), "[[1] [2 3]]");
+        assert_eq!(parse("[0; n * 2]"), "(Repeat 0 (Mul n 2))");
+        assert_eq!(parse("a[i + 1]"), "(Index a (Add i 1))");
+        assert_eq!(parse("a[1..n][0]"), "(Index (Index a (Range 1 n)) 0)");
+        assert_eq!(parse("p.xs[0].y"), "(Field (Index p.xs 0) y)");
+        assert_eq!(parse("-a[0] * 2"), "(Mul (Neg (Index a 0)) 2)");
+        match parse_str::<Expression>("a[i..j]") {
+            Ok(Expression::Index(_, index, span)) => {
+                assert!(matches!(*index, Expression::Range(_, _, _)));
+                assert_eq!((span.start, span.end), (0, 7));
+            }
+            x => panic!("{:?}", x),
+        }
+        assert!(parse_str::<Expression>("[1; 2; 3]").is_err());
+        assert!(parse_str::<Expression>("[0;]").is_err());
+        assert!(parse_str::<Expression>("a[]").is_err());
+    }
+
+    #[test]
+    fn block_values() {
+        let value = |src: &str| {
+            let block = parse_str::<Block>(src).expect(src);
+            (block.statements().len(), block.value().map(tree))
+        };
+        assert_eq!(value("{ a; b }"), (2, Some("b".to_string())));
+        assert_eq!(value("{\n    a\n    b + 1\n}"), (2, Some("(Add b 1)".to_string())));
+        assert_eq!(value("{ a; b; }"), (2, None));
+        assert_eq!(value("{ var a = 1 }"), (1, None));
+        assert_eq!(value("{ }"), (0, None));
+        assert_eq!(value("{\n}"), (0, None));
+    }
+
+    #[test]
+    fn returns() {
+        let returned = |src: &str| match parse_str::<Block>(src).expect(src).statements()[0].clone() {
+            Statement::Expression(Expression::Return(x, span)) => (x.map(|x| tree(&x)), span.end),
+            x => panic!("{} {:?}", src, x),
+        };
+        assert_eq!(returned("{ return a + 1 }"), (Some("(Add a 1)".to_string()), 14));
+        assert_eq!(returned("{ return }"), (None, 8));
+        assert_eq!(returned("{\n    return\n    a\n}"), (None, 12));
+        assert_eq!(returned("{ return; a }"), (None, 8));
+        match parse_str::<Expression>("a || return") {
+            Ok(Expression::Binary(Binary::Or, _, x, _)) => assert!(matches!(*x, Expression::Return(None, _))),
+            x => panic!("{:?}", x),
+        }
+    }
+
+    #[test]
+    fn instances() {
+        let instance = |src: &str| match parse_str::<Expression>(src).expect(src) {
+            Expression::Call(f, args, _) => match *f {
+                Expression::Instance(f, types, span) => (tree(&f), types.len(), args.len(), span.end),
+                f => panic!("{} {:?}", src, f),
+            },
+            x => panic!("{} {:?}", src, x),
+        };
+        assert_eq!(instance("max<i32>(1, 2)"), ("max".to_string(), 1, 2, 8));
+        assert_eq!(instance("geo.pair<str, Box<f64>>()"), ("geo.pair".to_string(), 2, 0, 23));
+        // with no `(` right after the `>` it is a comparison, with one it is a call
+        assert_eq!(parse("a < b"), "(Less a b)");
+        assert_eq!(parse("a < b > c"), "(Greater (Less a b) c)");
+        assert_eq!(instance("a < b > (c)"), ("a".to_string(), 1, 1, 7));
+        assert_eq!(parse("f(a < b, c > d)"), "(Call f (Less a b) (Greater c d))");
+    }
+
+    #[test]
+    fn reserved_declare_name() {
+        match parse_str::<Block>("{ var fn = 2 }") {
+            Err(PlumaError::InvalidSyntax(span, err)) => match *err {
+                PlumaError::ReservedWord(Keyword::Fn) => assert_eq!(span.start, 6),
+                err => panic!("{:?}", err),
+            },
+            x => panic!("{:?}", x),
+        }
+    }
+
+    #[test]
+    fn continued_lines() {
+        assert_eq!(parse("a >\n b"), "(Greater a b)");
+        assert_eq!(parse("a <\n b"), "(Less a b)");
+        assert_eq!(parse("1 +\n 2 *\n 3"), "(Add 1 (Mul 2 3))");
+        assert_eq!(parse("f(1,\n 2)"), "(Call f 1 2)");
+    }
+}
@@ -0,0 +1,156 @@
+use crate::pluma::ir::types::Primitive;
+
+/// `x as T` between two number primitives, every backend has to give what `apply` gives
+///
+/// * integer to integer keeps the low bits of the two's complement, widening extends by the sign of the source
+/// * integer to float rounds to the nearest, ties to even
+/// * float to integer rounds toward zero and saturates at the bounds of the target, NaN is 0
+/// * float to float rounds to the nearest, a f64 out of the f32 range is an infinity
+///
+/// `size` and `ptr` are unsigned integers, a `ptr` converts from and to other integers only.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct Cast {
+    from: Primitive,
+    to: Primitive,
+}
+
+/// value of a number primitive, an integer is the two's complement in the low `bits()` of the `u64`
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Scalar {
+    Integer(u64),
+    Float(f64),
+}
+
+fn number(p: Primitive) -> bool {
+    p.is_integer() || p.is_float()
+}
+
+/// the low `bits()` of `p` set
+pub fn mask(p: Primitive) -> u64 {
+    match p.bits() {
+        64 => u64::MAX,
+        bits => (1u64 << bits) - 1,
+    }
+}
+
+// integer value of the bits of `p`
+fn extend(p: Primitive, bits: u64) -> i128 {
+    let shift = 64 - p.bits();
+    if p.is_signed() {
+        ((bits << shift) as i64 >> shift) as i128
+    } else {
+        (bits & mask(p)) as i128
+    }
+}
+
+fn bounds(p: Primitive) -> (i128, i128) {
+    let bits = p.bits();
+    if p.is_signed() {
+        (-(1i128 << (bits - 1)), (1i128 << (bits - 1)) - 1)
+    } else {
+        (0, (1i128 << bits) - 1)
+    }
+}
+
+impl Scalar {
+    /// `value` in the bits of the integer `p`, what is left over is dropped as a cast would
+    pub fn wrap(p: Primitive, value: i128) -> Scalar {
+        Scalar::Integer(value as u64 & mask(p))
+    }
+    /// integer value of the bits as `p` reads them, a float has none
+    pub fn integer(&self, p: Primitive) -> Option<i128> {
+        match self {
+            Scalar::Integer(bits) => Some(extend(p, *bits)),
+            Scalar::Float(_) => None,
+        }
+    }
+}
+
+impl Cast {
+    /// `None` for a cast which means nothing, like `bool as f32` or `ptr as f64`
+    pub fn new(from: Primitive, to: Primitive) -> Option<Cast> {
+        let pointer = from == Primitive::Ptr || to == Primitive::Ptr;
+        match (from, to) {
+            _ if !number(from) || !number(to) => None,
+            _ if pointer && (from.is_float() || to.is_float()) => None,
+            _ => Some(Cast { from, to }),
+        }
+    }
+    pub fn apply(&self, x: Scalar) -> Scalar {
+        match x {
+            Scalar::Integer(bits) => {
+                let value = extend(self.from, bits);
+                match self.to {
+                    Primitive::F32 => Scalar::Float(value as f32 as f64),
+                    Primitive::F64 => Scalar::Float(value as f64),
+                    to => Scalar::Integer(value as u64 & mask(to)),
+                }
+            }
+            Scalar::Float(value) => match self.to {
+                Primitive::F32 => Scalar::Float(value as f32 as f64),
+                Primitive::F64 => Scalar::Float(value),
+                to => {
+                    let (min, max) = bounds(to);
+                    let value = match value.trunc() {
+                        x if x.is_nan() => 0,
+                        x if x <= min as f64 => min,
+                        x if x >= max as f64 => max,
+                        x => x as i128,
+                    };
+                    Scalar::Integer(value as u64 & mask(to))
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cast(from: Primitive, to: Primitive, x: Scalar) -> Scalar {
+        Cast::new(from, to).expect("cast between numbers").apply(x)
+    }
+
+    #[test]
+    fn integers_wrap() {
+        assert_eq!(cast(Primitive::I32, Primitive::U8, Scalar::Integer(300)), Scalar::Integer(44));
+        assert_eq!(cast(Primitive::U8, Primitive::I8, Scalar::Integer(255)), Scalar::Integer(0xff));
+        assert_eq!(cast(Primitive::I64, Primitive::I16, Scalar::Integer(0x1_2345)), Scalar::Integer(0x2345));
+    }
+
+    #[test]
+    fn widening_extends_by_the_source() {
+        assert_eq!(cast(Primitive::I8, Primitive::U64, Scalar::Integer(0xff)), Scalar::Integer(u64::MAX));
+        assert_eq!(cast(Primitive::I8, Primitive::I32, Scalar::Integer(0xc8)), Scalar::Integer(0xffff_ffc8));
+        assert_eq!(cast(Primitive::U8, Primitive::I32, Scalar::Integer(0xc8)), Scalar::Integer(200));
+    }
+
+    #[test]
+    fn float_to_integer_truncates_and_saturates() {
+        assert_eq!(cast(Primitive::F64, Primitive::I32, Scalar::Float(2.9)), Scalar::Integer(2));
+        assert_eq!(cast(Primitive::F64, Primitive::I32, Scalar::Float(-2.9)), Scalar::Integer(-2i32 as u32 as u64));
+        assert_eq!(cast(Primitive::F64, Primitive::I32, Scalar::Float(1e20)), Scalar::Integer(i32::MAX as u64));
+        assert_eq!(cast(Primitive::F64, Primitive::I32, Scalar::Float(-1e20)), Scalar::Integer(i32::MIN as u32 as u64));
+        assert_eq!(cast(Primitive::F64, Primitive::U8, Scalar::Float(-1.0)), Scalar::Integer(0));
+        assert_eq!(cast(Primitive::F64, Primitive::U64, Scalar::Float(f64::INFINITY)), Scalar::Integer(u64::MAX));
+        assert_eq!(cast(Primitive::F64, Primitive::I32, Scalar::Float(f64::NAN)), Scalar::Integer(0));
+    }
+
+    #[test]
+    fn floats_round_to_nearest() {
+        assert_eq!(cast(Primitive::F64, Primitive::F32, Scalar::Float(f64::MAX)), Scalar::Float(f64::INFINITY));
+        assert_eq!(cast(Primitive::F64, Primitive::F32, Scalar::Float(0.1)), Scalar::Float(0.1f32 as f64));
+        assert_eq!(cast(Primitive::I32, Primitive::F32, Scalar::Integer(16_777_217)), Scalar::Float(16_777_216.0));
+        assert_eq!(cast(Primitive::I64, Primitive::F64, Scalar::Integer(u64::MAX)), Scalar::Float(-1.0));
+    }
+
+    #[test]
+    fn meaningless_casts() {
+        assert!(Cast::new(Primitive::Bool, Primitive::F32).is_none());
+        assert!(Cast::new(Primitive::Char, Primitive::I32).is_none());
+        assert!(Cast::new(Primitive::Ptr, Primitive::F64).is_none());
+        assert!(Cast::new(Primitive::Ptr, Primitive::U64).is_some());
+        assert!(Cast::new(Primitive::Size, Primitive::Ptr).is_some());
+    }
+}
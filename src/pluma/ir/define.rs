@@ -1,95 +1,259 @@
 use nom::combinator::{map, opt};
 use nom::IResult;
 use nom::multi::separated_list;
-use nom::sequence::{delimited, pair, preceded, terminated, tuple};
+use nom::sequence::{delimited, pair, preceded};
 
 use crate::pluma::ir::block::Block;
 use crate::pluma::ir::commons::Value;
-use crate::pluma::ir::types::{Parameter, Type};
+use crate::pluma::ir::types::{Generic, Parameter, Type, generics};
 use crate::pluma::ir::types;
-use crate::pluma::token::{Control, GlobalIdentifier, Identifier, Keyword, NomTrait, opt_ws, PlumaError, ws};
+use crate::pluma::token::{Control, Identifier, Keyword, NomTrait, PlumaError, Tokens, commit, spanned, docs, comma_list, newline, sep};
+use crate::pluma::source::{Span, Spanned};
 use nom::branch::alt;
 
 #[derive(Debug, Clone)]
 pub enum Define {
     Function(Function),
     Static(Static),
+    Struct(Struct),
+    Interface(Interface),
 }
 
+/// `fn name(var a i32) -> T { }`, or a method `fn (ref w File) write(ref buf []u8) -> size { }`
 #[derive(Debug, Clone)]
 pub struct Function {
+    receiver: Option<(Identifier, Parameter)>,
     name: Identifier,
+    generics: Vec<Generic>,
     declare: types::Function,
     params: Vec<Identifier>,
     define: Block,
+    doc: Option<String>,
+    span: Span,
 }
 #[derive(Debug, Clone)]
 pub struct Static {
     value: Value,
     name: Identifier,
-    declare: Type,
+    declare: Spanned<Type>,
     define: Block,
+    doc: Option<String>,
+    span: Span,
+}
+/// `struct Point { x f64, y f64 }`, fields are split by commas or newlines
+#[derive(Debug, Clone)]
+pub struct Struct {
+    name: Identifier,
+    generics: Vec<Generic>,
+    fields: Vec<Field>,
+    doc: Option<String>,
+    span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct Field {
+    name: Identifier,
+    declare: Spanned<Type>,
 }
 
+/// `interface Writer { fn write(ref buf []u8) -> size }`
+///
+/// A struct implements it by having methods of the same names and signatures, nothing is declared.
+#[derive(Debug, Clone)]
+pub struct Interface {
+    name: Identifier,
+    methods: Vec<Method>,
+    doc: Option<String>,
+    span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct Method {
+    name: Identifier,
+    declare: types::Function,
+    params: Vec<Identifier>,
+    doc: Option<String>,
+    span: Span,
+}
+
+impl Define {
+    pub fn span(&self) -> Span {
+        match self {
+            Define::Function(x) => x.span,
+            Define::Static(x) => x.span,
+            Define::Struct(x) => x.span,
+            Define::Interface(x) => x.span,
+        }
+    }
+    pub fn doc(&self) -> Option<&str> {
+        match self {
+            Define::Function(x) => x.doc.as_deref(),
+            Define::Static(x) => x.doc.as_deref(),
+            Define::Struct(x) => x.doc.as_deref(),
+            Define::Interface(x) => x.doc.as_deref(),
+        }
+    }
+}
+
+impl Function {
+    pub fn receiver(&self) -> Option<&(Identifier, Parameter)> {
+        self.receiver.as_ref()
+    }
+    pub fn name(&self) -> &Identifier {
+        &self.name
+    }
+    pub fn generics(&self) -> &[Generic] {
+        self.generics.as_slice()
+    }
+    pub fn declare(&self) -> &types::Function {
+        &self.declare
+    }
+    pub fn params(&self) -> &[Identifier] {
+        self.params.as_slice()
+    }
+    pub fn define(&self) -> &Block {
+        &self.define
+    }
+}
+
+impl Static {
+    pub fn value(&self) -> &Value {
+        &self.value
+    }
+    pub fn name(&self) -> &Identifier {
+        &self.name
+    }
+    pub fn declare(&self) -> &Spanned<Type> {
+        &self.declare
+    }
+    pub fn define(&self) -> &Block {
+        &self.define
+    }
+}
+
+impl Struct {
+    pub fn name(&self) -> &Identifier {
+        &self.name
+    }
+    pub fn generics(&self) -> &[Generic] {
+        self.generics.as_slice()
+    }
+    pub fn fields(&self) -> &[Field] {
+        self.fields.as_slice()
+    }
+}
+
+impl Field {
+    pub fn name(&self) -> &Identifier {
+        &self.name
+    }
+    pub fn declare(&self) -> &Spanned<Type> {
+        &self.declare
+    }
+}
+
+impl Interface {
+    pub fn name(&self) -> &Identifier {
+        &self.name
+    }
+    pub fn methods(&self) -> &[Method] {
+        self.methods.as_slice()
+    }
+}
+
+impl Method {
+    pub fn name(&self) -> &Identifier {
+        &self.name
+    }
+    pub fn declare(&self) -> &types::Function {
+        &self.declare
+    }
+    pub fn params(&self) -> &[Identifier] {
+        self.params.as_slice()
+    }
+    pub fn doc(&self) -> Option<&str> {
+        self.doc.as_deref()
+    }
+    pub fn span(&self) -> Span {
+        self.span
+    }
+}
 
 impl<'t> NomTrait<'t> for Define {
-    fn nom(i: &'t str) -> IResult<&'t str, Self, PlumaError<'t>> {
+    fn nom(i: Tokens<'t>) -> IResult<Tokens<'t>, Self, PlumaError> {
         alt((
-            map(Function::nom, |x| Define::Function(x)),
-            map(Static::nom, |x| Define::Static(x)),
+            map(Function::nom, Define::Function),
+            map(Static::nom, Define::Static),
+            map(Struct::nom, Define::Struct),
+            map(Interface::nom, Define::Interface),
         ))(i)
     }
 }
 
+// `var a i32`, the value kind defaults to `var`
+//
+// a keyword where the name goes is reported as such, with or without `var` before it
+fn param(i: Tokens) -> IResult<Tokens, (Identifier, Parameter), PlumaError> {
+    let (i, value) = opt(Value::nom)(i)?;
+    let (i, name) = match (&value, Identifier::nom(i)) {
+        (_, Ok(x)) => x,
+        (Some(_), Err(_)) | (None, Err(nom::Err::Error(PlumaError::ReservedWord(_)))) => commit(Identifier::nom)(i)?,
+        (None, Err(err)) => return Err(err),
+    };
+    let (i, tp) = Spanned::<Type>::nom(i)?;
+    Ok((i, (name, Parameter::new(value.unwrap_or_default(), tp))))
+}
+
+// `(var a i32) -> T` after the name of functions and interface methods
+fn signature(i: Tokens) -> IResult<Tokens, (types::Function, Vec<Identifier>), PlumaError> {
+    map(
+        pair(
+            spanned(delimited(
+                Control::GroupStart.matcher(),
+                comma_list(param),
+                Control::GroupEnd.matcher(),
+            )),
+            opt(preceded(Control::Arrow.matcher(), Spanned::<Type>::nom)),
+        ),
+        |((params, span), ret)| {
+            let (names, dec) = params.into_iter().unzip();
+            let ret = ret.unwrap_or_else(|| Spanned::new(Type::Void, span));
+            (types::Function { params: dec, ret }, names)
+        },
+    )(i)
+}
+
 impl<'t> NomTrait<'t> for Function {
-    fn nom(i: &'t str) -> IResult<&'t str, Self, PlumaError<'t>> {
+    fn nom(i: Tokens<'t>) -> IResult<Tokens<'t>, Self, PlumaError> {
         map(
-            preceded(
-                // 'fn '
-                pair(Keyword::Fn.matcher(), ws),
-                pair(
+            pair(docs, spanned(preceded(
+                // 'fn'
+                Keyword::Fn.matcher(),
+                commit(pair(
                     pair(
-                        terminated(Identifier::nom, opt(ws)),
-                        delimited(
-                            pair(Control::GroupStart.matcher(), opt_ws),
-                            separated_list(
-                                tuple((opt_ws, Control::Comma.matcher(), opt_ws)),
-                                map(
-                                    pair(
-                                        opt(terminated(Value::nom, ws)),
-                                        pair(
-                                            terminated(Identifier::nom, ws),
-                                            Type::nom,
-                                        ),
-                                    ),
-                                    |(ov, (ident, tp))| {
-                                        (ov.unwrap_or_default(), ident, tp)
-                                    },
-                                ),
-                            ),
-                            pair(opt_ws, Control::GroupEnd.matcher()),
-                        ),
+                        opt(delimited(
+                            Control::GroupStart.matcher(),
+                            param,
+                            Control::GroupEnd.matcher(),
+                        )),
+                        pair(Identifier::nom, opt(generics)),
                     ),
                     pair(
-                        opt(preceded(tuple((opt_ws, Control::Arrow.matcher(), opt_ws)), Type::nom)),
+                        signature,
                         Block::nom,
                     ),
-                ),
-            ),
-            |((ident, params), (ret, block))| {
-                let (dec, names) = params.into_iter().fold((Vec::new(), Vec::new()), |(mut a, mut b), (v, n, t)| {
-                    a.push(Parameter::new(v, t));
-                    b.push(n);
-                    (a, b)
-                });
+                )),
+            ))),
+            |(doc, (((receiver, (name, generics)), ((declare, params), define)), span))| {
                 Function {
-                    name: ident,
-                    declare: types::Function {
-                        params: dec,
-                        ret: ret.unwrap_or_default(),
-                    },
-                    params: names,
-                    define: block,
+                    receiver,
+                    name,
+                    generics: generics.unwrap_or_default(),
+                    declare,
+                    params,
+                    define,
+                    doc,
+                    span,
                 }
             },
         )(i)
@@ -97,30 +261,215 @@ impl<'t> NomTrait<'t> for Function {
 }
 
 impl<'t> NomTrait<'t> for Static {
-    fn nom(i: &'t str) -> IResult<&'t str, Self, PlumaError<'t>> {
+    fn nom(i: Tokens<'t>) -> IResult<Tokens<'t>, Self, PlumaError> {
         map(
-            pair(
-                pair(
-                    terminated(Value::nom, ws),
-                    terminated(Identifier::nom, ws),
-                ),
-                pair(
-                    Type::nom,
-                    preceded(
-                        tuple((opt_ws, Control::Equal.matcher(), opt_ws)),
-                        Block::nom,
+            pair(docs, spanned(pair(
+                Value::nom,
+                commit(pair(
+                    Identifier::nom,
+                    pair(
+                        Spanned::<Type>::nom,
+                        preceded(
+                            Control::Equal.matcher(),
+                            commit(Block::nom),
+                        ),
                     ),
-                ),
-            ),
-            |((v, i), (t, b))| {
+                )),
+            ))),
+            |(doc, ((v, (i, (t, b))), span))| {
                 Static {
                     value: v,
                     name: i,
                     declare: t,
                     define: b,
+                    doc,
+                    span,
                 }
             },
         )(i)
     }
 }
 
+
+// `,` may be followed by a newline, a newline alone splits too
+fn field_sep(i: Tokens) -> IResult<Tokens, (), PlumaError> {
+    alt((
+        map(pair(Control::Comma.matcher(), opt(newline)), |_| ()),
+        newline,
+    ))(i)
+}
+
+impl<'t> NomTrait<'t> for Struct {
+    fn nom(i: Tokens<'t>) -> IResult<Tokens<'t>, Self, PlumaError> {
+        map(
+            pair(docs, spanned(preceded(
+                Keyword::Struct.matcher(),
+                commit(pair(
+                    pair(Identifier::nom, opt(generics)),
+                    delimited(
+                        pair(Control::BlockStart.matcher(), opt(newline)),
+                        separated_list(field_sep, Field::nom),
+                        pair(opt(field_sep), commit(Control::BlockEnd.matcher())),
+                    ),
+                )),
+            ))),
+            |(doc, (((name, generics), fields), span))| {
+                Struct { name, generics: generics.unwrap_or_default(), fields, doc, span }
+            },
+        )(i)
+    }
+}
+
+impl<'t> NomTrait<'t> for Field {
+    fn nom(i: Tokens<'t>) -> IResult<Tokens<'t>, Self, PlumaError> {
+        map(
+            pair(Identifier::nom, Spanned::<Type>::nom),
+            |(name, declare)| Field { name, declare },
+        )(i)
+    }
+}
+
+impl<'t> NomTrait<'t> for Interface {
+    fn nom(i: Tokens<'t>) -> IResult<Tokens<'t>, Self, PlumaError> {
+        map(
+            pair(docs, spanned(preceded(
+                Keyword::Interface.matcher(),
+                commit(pair(
+                    Identifier::nom,
+                    delimited(
+                        pair(Control::BlockStart.matcher(), opt(sep)),
+                        separated_list(sep, Method::nom),
+                        pair(opt(sep), commit(Control::BlockEnd.matcher())),
+                    ),
+                )),
+            ))),
+            |(doc, ((name, methods), span))| Interface { name, methods, doc, span },
+        )(i)
+    }
+}
+
+impl<'t> NomTrait<'t> for Method {
+    fn nom(i: Tokens<'t>) -> IResult<Tokens<'t>, Self, PlumaError> {
+        map(
+            pair(docs, spanned(preceded(Keyword::Fn.matcher(), commit(pair(Identifier::nom, signature))))),
+            |(doc, ((name, (declare, params)), span))| Method { name, declare, params, doc, span },
+        )(i)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pluma::ir::ast::SourceFile;
+    use crate::pluma::token::parse_str;
+
+    fn reserved(src: &str) -> (usize, Keyword) {
+        match parse_str::<Define>(src) {
+            Err(PlumaError::InvalidSyntax(span, err)) => match *err {
+                PlumaError::ReservedWord(key) => (span.start, key),
+                err => panic!("{} {:?}", src, err),
+            },
+            x => panic!("{} {:?}", src, x.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn docs() {
+        let src = "/// adds\n/// two\nfn add() { }\n\n// plain\nstruct P { }\n/// closes\n\ninterface C {\n    /// the end\n    fn close()\n}";
+        let file = parse_str::<SourceFile>(src).unwrap();
+        let docs = file.defines().iter().map(|x| x.doc()).collect::<Vec<_>>();
+        assert_eq!(docs, vec![Some(" adds\n two"), None, Some(" closes")]);
+        match &file.defines()[2] {
+            Define::Interface(x) => assert_eq!(x.methods()[0].doc(), Some(" the end")),
+            x => panic!("{:?}", x),
+        }
+    }
+
+    fn structure(src: &str) -> Struct {
+        match parse_str::<Define>(src) {
+            Ok(Define::Struct(x)) => x,
+            x => panic!("{} {:?}", src, x),
+        }
+    }
+
+    #[test]
+    fn structs() {
+        for src in &["struct P { x f64, y f64 }", "struct P {\n    x f64\n    y f64,\n}", "struct P { x f64,\n y f64 }"] {
+            let fields = structure(src).fields().iter()
+                .map(|x| (x.name().name().to_string(), x.declare().node.clone()))
+                .collect::<Vec<_>>();
+            let f64 = Type::Primitive(types::Primitive::F64);
+            assert_eq!(fields, vec![("x".to_string(), f64.clone()), ("y".to_string(), f64)], "{}", src);
+        }
+        assert!(structure("struct Unit { }").fields().is_empty());
+        assert_eq!(structure("struct Box<T> { v T }").generics().len(), 1);
+        match parse_str::<Define>("struct P { x f64 y f64 }") {
+            Err(PlumaError::InvalidSyntax(span, _)) => assert_eq!(span.start, 17),
+            x => panic!("{:?}", x.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn methods() {
+        match parse_str::<Define>("fn (ref w File) write(const buf []u8) -> size { 0 }") {
+            Ok(Define::Function(f)) => {
+                let (name, receiver) = f.receiver().expect("receiver");
+                assert_eq!((name.name(), receiver.value()), ("w", &Value::Reference));
+                assert!(matches!(&receiver.declare().node, Type::Named(path, _) if path.path()[0].name() == "File"));
+                assert_eq!(f.name().name(), "write");
+                assert_eq!(f.params().iter().map(|x| x.name()).collect::<Vec<_>>(), vec!["buf"]);
+            }
+            x => panic!("{:?}", x),
+        }
+        match parse_str::<Define>("fn write() { }") {
+            Ok(Define::Function(f)) => assert!(f.receiver().is_none()),
+            x => panic!("{:?}", x),
+        }
+    }
+
+    #[test]
+    fn interfaces() {
+        let src = "interface Shape {\n    fn area() -> f64\n    fn scale(const by f64)\n}";
+        match parse_str::<Define>(src) {
+            Ok(Define::Interface(x)) => {
+                assert_eq!(x.name().name(), "Shape");
+                let methods = x.methods().iter()
+                    .map(|m| (m.name().name(), m.params().len(), m.declare().ret.node.clone()))
+                    .collect::<Vec<_>>();
+                let f64 = Type::Primitive(types::Primitive::F64);
+                assert_eq!(methods, vec![("area", 0, f64), ("scale", 1, Type::Void)]);
+                assert_eq!(Some(x.methods()[1].span().start), src.find("fn scale"));
+            }
+            x => panic!("{:?}", x),
+        }
+        match parse_str::<Define>("interface Empty { }") {
+            Ok(Define::Interface(x)) => assert!(x.methods().is_empty()),
+            x => panic!("{:?}", x),
+        }
+        // a method of an interface has no body
+        assert!(parse_str::<Define>("interface Shape { fn area() -> f64 { 0.0 } }").is_err());
+    }
+
+    #[test]
+    fn generic_defines() {
+        match parse_str::<Define>("fn max<T Comparable>(var a T, var b T) -> T { a }") {
+            Ok(Define::Function(f)) => {
+                let generics = f.generics().iter().map(|g| (g.name.name(), g.bound.is_some())).collect::<Vec<_>>();
+                assert_eq!(generics, vec![("T", true)]);
+                assert!(matches!(&f.declare().ret.node, Type::Named(path, args) if path.path()[0].name() == "T" && args.is_empty()));
+            }
+            x => panic!("{:?}", x),
+        }
+        let generics = structure("struct Pair<A, B> { a A, b B }").generics().iter().map(|g| g.name.name().to_string()).collect::<Vec<_>>();
+        assert_eq!(generics, vec!["A", "B"]);
+        assert!(parse_str::<Define>("fn (const b Box<T>) get() -> T { b.v }").is_ok());
+    }
+
+    #[test]
+    fn reserved_param_name() {
+        assert_eq!(reserved("fn f(var fn i32) { }"), (9, Keyword::Fn));
+        assert_eq!(reserved("fn f(fn i32) { }"), (5, Keyword::Fn));
+        assert_eq!(reserved("fn f(var a i32, const if bool) { }"), (22, Keyword::If));
+        assert!(parse_str::<Define>("fn f() { }").is_ok());
+    }
+}
@@ -0,0 +1,43 @@
+use nom::IResult;
+
+use crate::pluma::ir::types::{Primitive, Type};
+use crate::pluma::token::{any_token, Keyword, NomTrait, PlumaError, TokenKind, Tokens};
+use crate::pluma::token::literal::Numeric;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    Numeric(Numeric),
+    String(String),
+    Character(char),
+    Byte(u8),
+    Bool(bool),
+}
+
+impl Literal {
+    // unsuffixed integers are i32 and unsuffixed floats are f64
+    pub fn literal_type(&self) -> Type {
+        match self {
+            Literal::Numeric(Numeric::Integer(_, p)) => Type::Primitive(p.unwrap_or(Primitive::I32)),
+            Literal::Numeric(Numeric::Float(_, p)) => Type::Primitive(p.unwrap_or(Primitive::F64)),
+            Literal::String(_) => Type::String,
+            Literal::Character(_) => Type::Primitive(Primitive::Char),
+            Literal::Byte(_) => Type::Primitive(Primitive::U8),
+            Literal::Bool(_) => Type::Primitive(Primitive::Bool),
+        }
+    }
+}
+
+impl<'t> NomTrait<'t> for Literal {
+    fn nom(i: Tokens<'t>) -> IResult<Tokens<'t>, Self, PlumaError> {
+        let (left, tk) = any_token(i)?;
+        match &tk.kind {
+            TokenKind::Numeric(x) => Ok((left, Literal::Numeric(x.clone()))),
+            TokenKind::Literal(x) => Ok((left, Literal::String(x.clone()))),
+            TokenKind::Character(x) => Ok((left, Literal::Character(*x))),
+            TokenKind::Byte(x) => Ok((left, Literal::Byte(*x))),
+            TokenKind::Keyword(Keyword::True) => Ok((left, Literal::Bool(true))),
+            TokenKind::Keyword(Keyword::False) => Ok((left, Literal::Bool(false))),
+            _ => Err(nom::Err::Error(PlumaError::NotLiteral)),
+        }
+    }
+}
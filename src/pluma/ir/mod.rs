@@ -4,4 +4,7 @@ pub mod ast;
 pub mod types;
 pub mod define;
 pub mod commons;
-pub mod block;
\ No newline at end of file
+pub mod block;
+pub mod cast;
+pub mod literal;
+pub mod pattern;
\ No newline at end of file
@@ -0,0 +1,143 @@
+use nom::IResult;
+use nom::branch::alt;
+use nom::combinator::{map, opt, peek, verify};
+use nom::sequence::{delimited, pair, preceded, terminated};
+
+use crate::pluma::ir::block::Binary;
+use crate::pluma::ir::types::Type;
+use crate::pluma::source::{Span, Spanned};
+use crate::pluma::token::{Control, GlobalIdentifier, Identifier, NomTrait, PlumaError, Tokens, comma_list, newline, spanned};
+
+/// right side of `is`, what a value is tested against
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    /// `_`, matches anything
+    Any(Span),
+    /// `i32` or `Circle`
+    Type(Spanned<Type>),
+    /// `Circle{r}` or `Circle{r: radius}`, takes the fields out under the names given
+    Struct(GlobalIdentifier, Vec<(Identifier, Identifier)>, Span),
+}
+
+impl Pattern {
+    pub fn span(&self) -> Span {
+        match self {
+            Pattern::Any(span) => *span,
+            Pattern::Type(tp) => tp.span,
+            Pattern::Struct(_, _, span) => *span,
+        }
+    }
+
+    // right before the body of an `if` the `{ }` of a struct pattern needs the body or an operator after it,
+    // `if s is Circle { r }` is a type test with `{ r }` as the body
+    pub fn nom_with(i: Tokens, structs: bool) -> IResult<Tokens, Pattern, PlumaError> {
+        alt((
+            map(verify(Identifier::nom, |x: &Identifier| x.name() == "_"), |x| Pattern::Any(x.span())),
+            |i| if structs {
+                Self::structure(i)
+            } else {
+                terminated(Self::structure, peek(alt((
+                    map(Control::BlockStart.matcher(), |_| ()),
+                    map(Binary::nom, |_| ()),
+                ))))(i)
+            },
+            map(Spanned::<Type>::nom, Pattern::Type),
+        ))(i)
+    }
+
+    fn structure(i: Tokens) -> IResult<Tokens, Pattern, PlumaError> {
+        map(
+            spanned(pair(
+                GlobalIdentifier::nom,
+                delimited(
+                    Control::BlockStart.matcher(),
+                    comma_list(pair(Identifier::nom, opt(preceded(Control::Meta.matcher(), Identifier::nom)))),
+                    pair(opt(newline), Control::BlockEnd.matcher()),
+                ),
+            )),
+            |((name, fields), span)| {
+                let fields = fields.into_iter()
+                    .map(|(field, bind)| {
+                        let bind = bind.unwrap_or_else(|| field.clone());
+                        (field, bind)
+                    })
+                    .collect();
+                Pattern::Struct(name, fields, span)
+            },
+        )(i)
+    }
+}
+
+impl<'t> NomTrait<'t> for Pattern {
+    fn nom(i: Tokens<'t>) -> IResult<Tokens<'t>, Self, PlumaError> {
+        Self::nom_with(i, true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pluma::ir::block::Expression;
+    use crate::pluma::token::{Token, parse_str};
+
+    fn path(x: &GlobalIdentifier) -> String {
+        x.path().iter().map(|x| x.name()).collect::<Vec<_>>().join(".")
+    }
+
+    fn describe(pattern: &Pattern) -> String {
+        match pattern {
+            Pattern::Any(_) => "_".to_string(),
+            Pattern::Type(Spanned { node: Type::Named(x, _), .. }) => path(x),
+            Pattern::Type(tp) => format!("{:?}", tp.node),
+            Pattern::Struct(x, fields, _) => {
+                let path = path(x);
+                let fields = fields.iter().map(|(field, bind)| format!("{}: {}", field.name(), bind.name())).collect::<Vec<_>>();
+                format!("{}{{{}}}", path, fields.join(", "))
+            }
+        }
+    }
+
+    fn pattern(src: &str) -> String {
+        describe(&parse_str::<Pattern>(src).expect(src))
+    }
+
+    #[test]
+    fn patterns() {
+        assert_eq!(pattern("_"), "_");
+        assert_eq!(pattern("i32"), "Primitive(I32)");
+        assert_eq!(pattern("Circle"), "Circle");
+        assert_eq!(pattern("Circle{r}"), "Circle{r: r}");
+        assert_eq!(pattern("geo.Rect{w: width, h,}"), "geo.Rect{w: width, h: h}");
+        assert_eq!(pattern("Circle{\n    r\n}"), "Circle{r: r}");
+        assert_eq!(pattern("Unit{}"), "Unit{}");
+        let src = "x is Circle{r}";
+        match parse_str::<Expression>(src) {
+            Ok(Expression::Is(_, pattern, span)) => {
+                assert_eq!(Some(pattern.span().start), src.find("Circle"));
+                assert_eq!((span.start, span.end), (0, src.len()));
+            }
+            x => panic!("{:?}", x),
+        }
+    }
+
+    // before the body of an `if` the `{ }` is the body unless an operator follows it
+    #[test]
+    fn condition_patterns() {
+        let condition = |src: &str| {
+            let tokens = Token::tokenize_str(src).unwrap();
+            let (left, x) = Expression::condition(&tokens).expect(src);
+            let pattern = match x {
+                Expression::Is(_, pattern, _) => describe(&pattern),
+                Expression::Binary(_, x, _, _) => match *x {
+                    Expression::Is(_, pattern, _) => describe(&pattern),
+                    x => panic!("{} {:?}", src, x),
+                },
+                x => panic!("{} {:?}", src, x),
+            };
+            (pattern, left.len())
+        };
+        assert_eq!(condition("s is Circle { r }"), ("Circle".to_string(), 3));
+        assert_eq!(condition("s is Circle{r} { r }"), ("Circle{r: r}".to_string(), 3));
+        assert_eq!(condition("s is Circle{r} && r > 1.0 { r }"), ("Circle{r: r}".to_string(), 3));
+    }
+}
@@ -1,11 +1,12 @@
 use nom::branch::alt;
 use nom::combinator::{map, opt};
 use nom::IResult;
-use nom::multi::separated_list;
-use nom::sequence::{delimited, pair, preceded};
+use nom::sequence::{delimited, pair, preceded, terminated};
 
-use crate::pluma::token::{Control, Keyword, NomTrait, opt_ws, PlumaError, ws};
+use crate::pluma::token::{Control, GlobalIdentifier, Identifier, Keyword, NomTrait, PlumaError, TokenKind, Tokens, any_token, comma_list, commit, spanned};
+use crate::pluma::token::literal::Numeric;
 use crate::pluma::ir::commons::Value;
+use crate::pluma::source::Spanned;
 
 
 //
@@ -16,9 +17,23 @@ pub enum Type {
     String,
     Function(Box<Function>),
     Array(Box<Type>),
+    /// `[3]f64`, the items are stored in place so `[3][3]f64` is nine `f64` in a row
+    ///
+    /// It is taken as a `[]T` by a call to a function wanting one.
+    FixedArray(Box<Type>, usize),
+    /// `[N]T` with a `const` for the length, the checker works `N` out and makes it a `FixedArray`
+    ConstArray(Box<Type>, GlobalIdentifier),
+    /// `Point`, `geo.Point` or `Box<i32>`, a user defined type and its type arguments
+    Named(GlobalIdentifier, Vec<Type>),
+    /// `T` inside a generic function or struct, the parser reads it as `Named` first
+    Param(Identifier),
+    /// `a..b`, only written as an expression
+    Range(Box<Type>),
+    /// `break`, `continue` and anything else that never gives a value, it fits any type
+    Never,
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
 pub enum Primitive {
     I8,
     I16,
@@ -32,16 +47,26 @@ pub enum Primitive {
     F64,
     Size,
     Ptr,
+    Char,
+    Bool,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub struct Function {
     pub params: Vec<Parameter>,
-    pub ret: Type,
+    /// `Void` at the parameter list when no `->` is written
+    pub ret: Spanned<Type>,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
-pub struct Parameter(Value, Type);
+pub struct Parameter(Value, Spanned<Type>);
+
+/// `T` or `T Comparable` in the `<...>` after a function or struct name
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Generic {
+    pub name: Identifier,
+    pub bound: Option<GlobalIdentifier>,
+}
 
 impl Default for Type {
     fn default() -> Self {
@@ -50,34 +75,25 @@ impl Default for Type {
 }
 
 impl Function {
-    fn nom_without_fn<'t>(i: &'t str) -> IResult<&'t str, Function, PlumaError<'t>> {
+    fn nom_without_fn(i: Tokens) -> IResult<Tokens, Function, PlumaError> {
         map(
             pair(
-                delimited(
-                    pair(
-                        Control::GroupStart.matcher(),
-                        opt_ws,
-                    ),
-                    separated_list(
-                        delimited(opt_ws, Control::Comma.matcher(), opt_ws),
-                        Parameter::nom,
-                    ),
-                    pair(
-                        opt_ws,
-                        Control::GroupEnd.matcher(),
-                    ),
-                ),
+                spanned(delimited(
+                    Control::GroupStart.matcher(),
+                    comma_list(Parameter::nom),
+                    Control::GroupEnd.matcher(),
+                )),
                 opt(
                     preceded(
-                        delimited(opt_ws, Control::Arrow.matcher(), opt_ws),
-                        Type::nom,
+                        Control::Arrow.matcher(),
+                        Spanned::<Type>::nom,
                     )
                 ),
             ),
-            |(params, ret)| {
+            |((params, span), ret)| {
                 Function {
                     params,
-                    ret: ret.unwrap_or(Type::Void),
+                    ret: ret.unwrap_or_else(|| Spanned::new(Type::Void, span)),
                 }
             },
         )(i)
@@ -85,53 +101,147 @@ impl Function {
 }
 
 impl Parameter {
-    pub fn new(v: Value, t: Type) -> Self {
+    pub fn new(v: Value, t: Spanned<Type>) -> Self {
         Self(v, t)
     }
+    pub fn value(&self) -> &Value {
+        &self.0
+    }
+    pub fn declare(&self) -> &Spanned<Type> {
+        &self.1
+    }
+}
+
+/// `<T, U Comparable>` of a define
+pub fn generics(i: Tokens) -> IResult<Tokens, Vec<Generic>, PlumaError> {
+    delimited(
+        Control::GenericStart.matcher(),
+        comma_list(Generic::nom),
+        Control::GenericEnd.matcher(),
+    )(i)
+}
+
+/// `<i32, Box<f64>>` given to a generic type or function
+pub fn arguments(i: Tokens) -> IResult<Tokens, Vec<Spanned<Type>>, PlumaError> {
+    delimited(
+        Control::GenericStart.matcher(),
+        comma_list(Spanned::<Type>::nom),
+        Control::GenericEnd.matcher(),
+    )(i)
+}
+
+impl<'t> NomTrait<'t> for Generic {
+    fn nom(i: Tokens<'t>) -> IResult<Tokens<'t>, Self, PlumaError> {
+        map(
+            pair(Identifier::nom, opt(GlobalIdentifier::nom)),
+            |(name, bound)| Generic { name, bound },
+        )(i)
+    }
 }
 
 impl<'t> NomTrait<'t> for Type {
-    fn nom(i: &str) -> IResult<&str, Self, PlumaError> {
+    fn nom(i: Tokens<'t>) -> IResult<Tokens<'t>, Self, PlumaError> {
         alt((
-            map(Primitive::nom, |x| Type::Primitive(x)),
+            map(Primitive::nom, Type::Primitive),
             map(Function::nom, |x| Type::Function(Box::new(x))),
             map(Keyword::Str.matcher(), |_|Type::String),
             map(
-                preceded(
-                    pair(Control::ArrayStart.matcher(), Control::ArrayEnd.matcher()),
-                    Type::nom,
-                ),
-                |x| Type::Array(Box::new(x)),
+                pair(GlobalIdentifier::nom, opt(arguments)),
+                |(x, args)| Type::Named(x, args.unwrap_or_default().into_iter().map(|x| x.node).collect()),
+            ),
+            preceded(
+                Control::ArrayStart.matcher(),
+                alt((
+                    map(preceded(Control::ArrayEnd.matcher(), Type::nom), |x| Type::Array(Box::new(x))),
+                    // past `[` only a length is left
+                    map(
+                        pair(terminated(commit(length), commit(Control::ArrayEnd.matcher())), commit(Type::nom)),
+                        |(n, x)| match n {
+                            Length::Literal(n) => Type::FixedArray(Box::new(x), n),
+                            Length::Constant(n) => Type::ConstArray(Box::new(x), n),
+                        },
+                    ),
+                )),
             ),
         ))(i)
     }
 }
 
+enum Length {
+    Literal(usize),
+    Constant(GlobalIdentifier),
+}
+
+// `3` or `N` of `[N]T`, a literal or a `const` so that the length is known with the type
+fn length(i: Tokens) -> IResult<Tokens, Length, PlumaError> {
+    if let Ok((left, n)) = GlobalIdentifier::nom(i) {
+        return Ok((left, Length::Constant(n)));
+    }
+    let (left, tk) = any_token(i)?;
+    match &tk.kind {
+        TokenKind::Numeric(Numeric::Integer(n, p)) if !matches!(p, Some(p) if !p.is_integer()) => {
+            Ok((left, Length::Literal(*n as usize)))
+        }
+        _ => Err(nom::Err::Error(PlumaError::NotArrayLength)),
+    }
+}
+
+impl Primitive {
+    pub fn from_keyword(key: Keyword) -> Option<Primitive> {
+        match key {
+            Keyword::I8 => Some(Primitive::I8),
+            Keyword::I16 => Some(Primitive::I16),
+            Keyword::I32 => Some(Primitive::I32),
+            Keyword::I64 => Some(Primitive::I64),
+            Keyword::U8 => Some(Primitive::U8),
+            Keyword::U16 => Some(Primitive::U16),
+            Keyword::U32 => Some(Primitive::U32),
+            Keyword::U64 => Some(Primitive::U64),
+            Keyword::F32 => Some(Primitive::F32),
+            Keyword::F64 => Some(Primitive::F64),
+            Keyword::Ptr => Some(Primitive::Ptr),
+            Keyword::Size => Some(Primitive::Size),
+            Keyword::Char => Some(Primitive::Char),
+            Keyword::Bool => Some(Primitive::Bool),
+            _ => None,
+        }
+    }
+    pub fn is_float(&self) -> bool {
+        matches!(self, Primitive::F32 | Primitive::F64)
+    }
+    // char and bool are not numbers even though they have a width
+    pub fn is_integer(&self) -> bool {
+        !matches!(self, Primitive::F32 | Primitive::F64 | Primitive::Char | Primitive::Bool)
+    }
+    pub fn is_signed(&self) -> bool {
+        matches!(self, Primitive::I8 | Primitive::I16 | Primitive::I32 | Primitive::I64)
+    }
+    // size and ptr are 64bit wide on every target pluma supports
+    pub fn bits(&self) -> u32 {
+        match self {
+            Primitive::I8 | Primitive::U8 | Primitive::Bool => 8,
+            Primitive::I16 | Primitive::U16 => 16,
+            Primitive::I32 | Primitive::U32 | Primitive::F32 | Primitive::Char => 32,
+            Primitive::I64 | Primitive::U64 | Primitive::F64 => 64,
+            Primitive::Size | Primitive::Ptr => 64,
+        }
+    }
+}
+
 impl<'t> NomTrait<'t> for Primitive {
-    fn nom(i: &str) -> IResult<&str, Self, PlumaError> {
+    fn nom(i: Tokens<'t>) -> IResult<Tokens<'t>, Self, PlumaError> {
         Keyword::any_matcher()(i)
             .and_then(|(left, key)| {
-                match key {
-                    Keyword::I8 => Ok((left, Primitive::I8)),
-                    Keyword::I16 => Ok((left, Primitive::I16)),
-                    Keyword::I32 => Ok((left, Primitive::I32)),
-                    Keyword::I64 => Ok((left, Primitive::I64)),
-                    Keyword::U8 => Ok((left, Primitive::U8)),
-                    Keyword::U16 => Ok((left, Primitive::U16)),
-                    Keyword::U32 => Ok((left, Primitive::U32)),
-                    Keyword::U64 => Ok((left, Primitive::U64)),
-                    Keyword::F32 => Ok((left, Primitive::F32)),
-                    Keyword::F64 => Ok((left, Primitive::F64)),
-                    Keyword::Ptr => Ok((left, Primitive::Ptr)),
-                    Keyword::Size => Ok((left, Primitive::Size)),
-                    _ => Err(nom::Err::Error(PlumaError::NotPrimitive))
+                match Primitive::from_keyword(key) {
+                    Some(p) => Ok((left, p)),
+                    None => Err(nom::Err::Error(PlumaError::NotPrimitive))
                 }
             })
     }
 }
 
 impl<'t> NomTrait<'t> for Value {
-    fn nom(i: &str) -> IResult<&str, Self, PlumaError> {
+    fn nom(i: Tokens<'t>) -> IResult<Tokens<'t>, Self, PlumaError> {
         alt((
             map(Keyword::Var.matcher(), |_| Value::Variable),
             map(Keyword::Const.matcher(), |_| Value::Constant),
@@ -141,14 +251,11 @@ impl<'t> NomTrait<'t> for Value {
 }
 
 impl<'t> NomTrait<'t> for Parameter {
-    fn nom(i: &str) -> IResult<&str, Self, PlumaError> {
+    fn nom(i: Tokens<'t>) -> IResult<Tokens<'t>, Self, PlumaError> {
         map(
             pair(
                 Value::nom,
-                preceded(
-                    ws,
-                    Type::nom,
-                ),
+                Spanned::<Type>::nom,
             ),
             |(v, t)| Parameter(v, t),
         )(i)
@@ -156,13 +263,87 @@ impl<'t> NomTrait<'t> for Parameter {
 }
 
 impl<'t> NomTrait<'t> for Function {
-    fn nom(i: &str) -> IResult<&str, Self, PlumaError> {
+    fn nom(i: Tokens<'t>) -> IResult<Tokens<'t>, Self, PlumaError> {
         preceded(
-            pair(
-                Keyword::Fn.matcher(),
-                opt_ws,
-            ),
+            Keyword::Fn.matcher(),
             Function::nom_without_fn,
         )(i)
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pluma::token::{Token, parse_str};
+
+    fn item(p: Primitive) -> Box<Type> {
+        Box::new(Type::Primitive(p))
+    }
+
+    #[test]
+    fn arrays() {
+        assert_eq!(parse_str::<Type>("[]i32").unwrap(), Type::Array(item(Primitive::I32)));
+        assert_eq!(parse_str::<Type>("[3]i32").unwrap(), Type::FixedArray(item(Primitive::I32), 3));
+        assert_eq!(
+            parse_str::<Type>("[2][3]f64").unwrap(),
+            Type::FixedArray(Box::new(Type::FixedArray(item(Primitive::F64), 3)), 2),
+        );
+        assert_eq!(parse_str::<Type>("[][4]u8").unwrap(), Type::Array(Box::new(Type::FixedArray(item(Primitive::U8), 4))));
+        match parse_str::<Type>("[N]u8").unwrap() {
+            Type::ConstArray(x, n) => {
+                assert_eq!(x, item(Primitive::U8));
+                assert_eq!(n.path()[0].name(), "N");
+            }
+            tp => panic!("{:?}", tp),
+        }
+    }
+
+    #[test]
+    fn not_array_length() {
+        // past `[` there is no going back to try another type
+        for (src, at) in &[("[3.5]i32", 1), ("[-1]i32", 1), ("[3 i32", 3), ("[3] +", 4)] {
+            match parse_str::<Type>(src) {
+                Err(PlumaError::InvalidSyntax(span, _)) => assert_eq!(span.start, *at, "{}", src),
+                x => panic!("{} {:?}", src, x),
+            }
+        }
+        match parse_str::<Type>("[3.5]i32") {
+            Err(PlumaError::InvalidSyntax(_, err)) => match *err {
+                PlumaError::NotArrayLength => (),
+                err => panic!("{:?}", err),
+            },
+            x => panic!("{:?}", x),
+        }
+    }
+
+    fn named(name: &str, args: Vec<Type>) -> Type {
+        Type::Named(parse_str::<GlobalIdentifier>(name).unwrap(), args)
+    }
+
+    #[test]
+    fn generic_types() {
+        assert_eq!(parse_str::<Type>("Box<i32>").unwrap(), named("Box", vec![Type::Primitive(Primitive::I32)]));
+        assert_eq!(
+            parse_str::<Type>("geo.Map<str, []u8,>").unwrap(),
+            named("geo.Map", vec![Type::String, Type::Array(item(Primitive::U8))]),
+        );
+        assert_eq!(
+            parse_str::<Type>("Box<Box<f64>>").unwrap(),
+            named("Box", vec![named("Box", vec![Type::Primitive(Primitive::F64)])]),
+        );
+        assert_eq!(parse_str::<Type>("T").unwrap(), named("T", Vec::new()));
+        assert!(parse_str::<Type>("Box<>").is_ok());
+        assert!(parse_str::<Type>("Box<i32").is_err());
+    }
+
+    #[test]
+    fn generic_params() {
+        let tokens = Token::tokenize_str("<T, U Comparable, V geo.Shape,>").unwrap();
+        let (_, params) = generics(&tokens).unwrap();
+        let params = params.iter()
+            .map(|g| (g.name.name(), g.bound.as_ref().map(|b| b.path().iter().map(|x| x.name()).collect::<Vec<_>>().join("."))))
+            .collect::<Vec<_>>();
+        assert_eq!(params, vec![("T", None), ("U", Some("Comparable".to_string())), ("V", Some("geo.Shape".to_string()))]);
+        let tokens = Token::tokenize_str("<T U V>").unwrap();
+        assert!(generics(&tokens).is_err());
+    }
+}
@@ -1,6 +1,8 @@
 
 
+pub mod check;
 pub mod ir;
 // pub mod iir;
 pub mod runtime;
+pub mod source;
 pub mod token;
\ No newline at end of file
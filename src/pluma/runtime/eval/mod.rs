@@ -0,0 +1,839 @@
+//! evaluator walking the checked defines, what the program does when it runs
+//!
+//! Integers wrap at their width and integer `/` and `%` by zero panic, see `ops`.
+//! `var` and `const` bind a copy of arrays and structs, `ref` binds the place itself
+//! and a slice `a[i..j]` shares the items of `a`. `with` closes its value however its block is left.
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::mem;
+use std::rc::Rc;
+
+use crate::pluma::ir::block::{Binary, Block, Expression, Loop, Statement};
+use crate::pluma::ir::commons;
+use crate::pluma::ir::define::{Define, Function, Static, Struct};
+use crate::pluma::ir::literal::Literal;
+use crate::pluma::ir::pattern::Pattern;
+use crate::pluma::ir::types::{Primitive, Type};
+use crate::pluma::runtime::eval::value::{Array, Place, Value, integer, rounded};
+use crate::pluma::runtime::panic::Panic;
+use crate::pluma::source::{Span, Spanned};
+use crate::pluma::token::GlobalIdentifier;
+use crate::pluma::token::literal::Numeric;
+
+mod ops;
+pub mod value;
+
+/// how an expression is left other than with its value
+enum Flow {
+    Break(Value),
+    Continue,
+    Return(Value),
+    Panic(Panic, Span),
+}
+
+type Run<T> = Result<T, Flow>;
+
+type Scope = HashMap<String, Place>;
+
+/// the defines of a checked program and the variables of the call running
+pub struct Machine<'d, W> {
+    functions: HashMap<&'d str, &'d Function>,
+    /// by the name of the struct, then of the method
+    methods: HashMap<&'d str, HashMap<&'d str, &'d Function>>,
+    structs: HashMap<&'d str, &'d Struct>,
+    statics: HashMap<&'d str, &'d Static>,
+    /// statics worked out so far, each on its first use
+    globals: HashMap<&'d str, Place>,
+    /// blocks of the function running, innermost last
+    scopes: Vec<Scope>,
+    out: W,
+}
+
+// last name of `geo.Point`
+fn last(path: &GlobalIdentifier) -> &str {
+    path.path().last().map_or("", |x| x.name())
+}
+
+fn literal(l: &Literal) -> Value {
+    match l {
+        Literal::Numeric(Numeric::Integer(n, None)) => Value::Integer(*n, None),
+        Literal::Numeric(Numeric::Integer(n, p)) => integer(*p, i128::from(*n)),
+        Literal::Numeric(Numeric::Float(x, p)) => Value::Float(rounded(*p, *x), *p),
+        Literal::String(x) => Value::String(Rc::from(x.as_str())),
+        Literal::Character(x) => Value::Char(*x),
+        Literal::Byte(x) => Value::Integer(u64::from(*x), Some(Primitive::U8)),
+        Literal::Bool(x) => Value::Bool(*x),
+    }
+}
+
+// a checked program only counts with integers
+fn count(x: &Value) -> i128 {
+    x.integer().unwrap_or_else(|| unreachable!("checked program counts with {:?}", x))
+}
+
+fn saturated(x: i128) -> i64 {
+    if x < i128::from(i64::MIN) {
+        i64::MIN
+    } else if x > i128::from(i64::MAX) {
+        i64::MAX
+    } else {
+        x as i64
+    }
+}
+
+// `T` of a generic define
+fn param<'t>(tp: &'t Type, generics: &HashMap<&str, Type>) -> Option<&'t str> {
+    match tp {
+        Type::Named(path, args) if path.path().len() == 1 && args.is_empty() && generics.contains_key(last(path)) => {
+            Some(last(path))
+        }
+        _ => None,
+    }
+}
+
+impl<'d, W: Write> Machine<'d, W> {
+    /// `out` takes what `io.print` writes
+    pub fn new(defines: &'d [Define], out: W) -> Self {
+        let mut machine = Machine {
+            functions: HashMap::new(),
+            methods: HashMap::new(),
+            structs: HashMap::new(),
+            statics: HashMap::new(),
+            globals: HashMap::new(),
+            scopes: Vec::new(),
+            out,
+        };
+        // the first of a name is the one the checker took
+        for define in defines {
+            match define {
+                Define::Function(f) => match f.receiver() {
+                    Some((_, receiver)) => {
+                        if let Type::Named(path, _) = &receiver.declare().node {
+                            machine.methods.entry(last(path)).or_default().entry(f.name().name()).or_insert(f);
+                        }
+                    }
+                    None => {
+                        machine.functions.entry(f.name().name()).or_insert(f);
+                    }
+                },
+                Define::Static(s) => {
+                    machine.statics.entry(s.name().name()).or_insert(s);
+                }
+                Define::Struct(s) => {
+                    machine.structs.entry(s.name().name()).or_insert(s);
+                }
+                Define::Interface(_) => (),
+            }
+        }
+        machine
+    }
+
+    /// runs the function `name` of the defines, a panic comes with the expression it was raised at
+    pub fn call(&mut self, name: &str, args: Vec<Value>) -> Result<Value, (Panic, Span)> {
+        let f = *self.functions.get(name).unwrap_or_else(|| panic!("no function `{}` to call", name));
+        let args = args.into_iter().map(Place::new).collect();
+        match self.invoke(f, &[], None, args) {
+            Ok(value) => Ok(value),
+            Err(Flow::Panic(panic, span)) => Err((panic, span)),
+            Err(_) => unreachable!("checked function is left by `break` or `continue`"),
+        }
+    }
+
+    // `ref` takes the place itself, `var` and `const` a copy of what is there
+    fn bind(kind: &commons::Value, place: Place, tp: &Type) -> Place {
+        match kind {
+            commons::Value::Reference => place,
+            _ => Place::new(place.get().copy().fit(tp)),
+        }
+    }
+
+    // the params of a generic function are what its typed arguments are, an untyped one stays as it is
+    fn invoke(&mut self, f: &'d Function, explicit: &[Spanned<Type>], receiver: Option<Place>, args: Vec<Place>) -> Run<Value> {
+        let mut generics: HashMap<&str, Type> = f.generics().iter()
+            .map(|generic| (generic.name.name(), Type::Void))
+            .collect();
+        for (generic, tp) in f.generics().iter().zip(explicit) {
+            generics.insert(generic.name.name(), tp.node.clone());
+        }
+        for (declare, arg) in f.declare().params.iter().zip(&args) {
+            if let Some(name) = param(&declare.declare().node, &generics) {
+                match (&generics[name], arg.get()) {
+                    (Type::Void, Value::Integer(_, Some(p))) | (Type::Void, Value::Float(_, Some(p))) => {
+                        generics.insert(name, Type::Primitive(p));
+                    }
+                    _ => (),
+                }
+            }
+        }
+        // `Void` for a param no argument told, its values stay as they are
+        let resolve = |tp: &Type| param(tp, &generics).map_or_else(|| tp.clone(), |name| generics[name].clone());
+        let mut frame = Scope::new();
+        if let (Some((name, declare)), Some(place)) = (f.receiver(), receiver) {
+            frame.insert(name.name().to_string(), Self::bind(declare.value(), place, &declare.declare().node));
+        }
+        for ((name, declare), arg) in f.params().iter().zip(&f.declare().params).zip(args) {
+            frame.insert(name.name().to_string(), Self::bind(declare.value(), arg, &resolve(&declare.declare().node)));
+        }
+        let ret = resolve(&f.declare().ret.node);
+        let saved = mem::replace(&mut self.scopes, vec![frame]);
+        let result = self.block(f.define());
+        self.scopes = saved;
+        match result {
+            Ok(value) | Err(Flow::Return(value)) => Ok(value.fit(&ret)),
+            Err(flow) => Err(flow),
+        }
+    }
+
+    fn global(&mut self, name: &str) -> Run<Option<Place>> {
+        if let Some(place) = self.globals.get(name) {
+            return Ok(Some(place.clone()));
+        }
+        let s = match self.statics.get(name) {
+            Some(s) => *s,
+            None => return Ok(None),
+        };
+        let place = Place::new(Value::Void);
+        self.globals.insert(s.name().name(), place.clone());
+        let saved = mem::take(&mut self.scopes);
+        let value = self.block(s.define());
+        self.scopes = saved;
+        place.set(value?.copy().fit(&s.declare().node));
+        Ok(Some(place))
+    }
+
+    // locals, then statics, then functions
+    fn variable(&mut self, name: &str) -> Run<Place> {
+        if let Some(place) = self.scopes.iter().rev().find_map(|scope| scope.get(name)) {
+            return Ok(place.clone());
+        }
+        if let Some(place) = self.global(name)? {
+            return Ok(place);
+        }
+        let f = match name {
+            "len" if !self.functions.contains_key(name) => value::Function::Len,
+            name => value::Function::Define(Rc::from(name)),
+        };
+        Ok(Place::new(Value::Function(f)))
+    }
+
+    // field `name` of the struct at `place`, or its method of the name
+    fn member(&self, place: Place, name: &str) -> Place {
+        let (tp, field) = place.inspect(|x| match x {
+            Value::Struct(tp, fields) => (tp.clone(), fields.iter().position(|(field, _)| **field == *name)),
+            x => unreachable!("checked program takes `{}` of {:?}", name, x),
+        });
+        match field {
+            Some(i) => place.field(i),
+            None => Place::new(Value::Function(value::Function::Method(place, tp, Rc::from(name)))),
+        }
+    }
+
+    // the value a variable of `tp` has before it is set
+    fn zero(&mut self, tp: &Type) -> Run<Value> {
+        let value = match tp {
+            Type::Primitive(Primitive::Bool) => Value::Bool(false),
+            Type::Primitive(Primitive::Char) => Value::Char('\0'),
+            Type::Primitive(p) if p.is_float() => Value::Float(0.0, Some(*p)),
+            Type::Primitive(p) => Value::Integer(0, Some(*p)),
+            Type::String => Value::String(Rc::from("")),
+            Type::Array(_) => Value::Array(Array::new(Vec::new())),
+            Type::FixedArray(item, n) => self.zeros(item, *n)?,
+            Type::ConstArray(item, n) => {
+                let n = match n.path() {
+                    [n] => self.global(n.name())?.map_or(0, |n| count(&n.get())),
+                    _ => 0,
+                };
+                self.zeros(item, n as usize)?
+            }
+            Type::Named(path, _) => match self.structs.get(last(path)).copied() {
+                Some(s) => {
+                    let mut fields = Vec::new();
+                    for field in s.fields() {
+                        fields.push((Rc::from(field.name().name()), self.zero(&field.declare().node)?));
+                    }
+                    Value::Struct(Rc::from(s.name().name()), fields)
+                }
+                None => Value::Void,
+            },
+            _ => Value::Void,
+        };
+        Ok(value)
+    }
+
+    fn zeros(&mut self, item: &Type, n: usize) -> Run<Value> {
+        let mut items = Vec::with_capacity(n);
+        for _ in 0..n {
+            items.push(self.zero(item)?);
+        }
+        Ok(Value::Array(Array::new(items)))
+    }
+
+    fn block(&mut self, b: &'d Block) -> Run<Value> {
+        self.scoped(Scope::new(), |machine| machine.statements(b))
+    }
+
+    // `f` with the variables of `scope` in a block of their own
+    fn scoped<R>(&mut self, scope: Scope, f: impl FnOnce(&mut Self) -> Run<R>) -> Run<R> {
+        self.scopes.push(scope);
+        let result = f(self);
+        self.scopes.pop();
+        result
+    }
+
+    fn statements(&mut self, b: &'d Block) -> Run<Value> {
+        let (statements, value) = match (b.statements().split_last(), b.value()) {
+            (Some((_, statements)), Some(value)) => (statements, Some(value)),
+            _ => (b.statements(), None),
+        };
+        for statement in statements {
+            self.statement(statement)?;
+        }
+        match value {
+            Some(x) => self.expr(x),
+            None => Ok(Value::Void),
+        }
+    }
+
+    fn statement(&mut self, statement: &'d Statement) -> Run<()> {
+        match statement {
+            Statement::Declare(declare) => {
+                let place = match (declare.value(), declare.define()) {
+                    (commons::Value::Reference, Some(x)) => self.place(x)?,
+                    (_, Some(x)) => {
+                        let value = self.expr(x)?.copy();
+                        Place::new(match declare.declare() {
+                            Some(tp) => value.fit(&tp.node),
+                            None => value.settle(),
+                        })
+                    }
+                    (_, None) => {
+                        let tp = declare.declare().map_or(Type::Void, |tp| tp.node.clone());
+                        Place::new(self.zero(&tp)?)
+                    }
+                };
+                if let Some(scope) = self.scopes.last_mut() {
+                    scope.insert(declare.name().name().to_string(), place);
+                }
+            }
+            Statement::Assign(target, op, x, span) => {
+                let place = self.place(target)?;
+                let value = self.expr(x)?.copy();
+                let value = match op {
+                    Some(op) => self.binary(*op, place.get(), value, *span)?,
+                    None => like(value, &place.get()),
+                };
+                place.set(value);
+            }
+            Statement::Expression(x) => {
+                self.expr(x)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn binary(&self, op: Binary, a: Value, b: Value, span: Span) -> Run<Value> {
+        ops::binary(op, a, b).map_err(|panic| Flow::Panic(panic, span))
+    }
+
+    fn truth(&mut self, x: &'d Expression) -> Run<bool> {
+        match self.expr(x)? {
+            Value::Bool(x) => Ok(x),
+            x => unreachable!("checked condition is {:?}", x),
+        }
+    }
+
+    // whether `cond` holds and what its `is` bind where it does
+    fn condition(&mut self, cond: &'d Expression) -> Run<Option<Scope>> {
+        match cond {
+            Expression::Is(x, pattern, _) => {
+                let x = self.expr(x)?;
+                Ok(self.matches(&x, pattern))
+            }
+            Expression::Binary(Binary::And, a, b, _) => {
+                let mut scope = match self.condition(a)? {
+                    Some(scope) => scope,
+                    None => return Ok(None),
+                };
+                match self.scoped(scope.clone(), |machine| machine.condition(b))? {
+                    Some(more) => {
+                        scope.extend(more);
+                        Ok(Some(scope))
+                    }
+                    None => Ok(None),
+                }
+            }
+            cond => Ok(if self.truth(cond)? { Some(Scope::new()) } else { None }),
+        }
+    }
+
+    fn matches(&self, x: &Value, pattern: &Pattern) -> Option<Scope> {
+        match pattern {
+            Pattern::Any(_) => Some(Scope::new()),
+            Pattern::Type(tp) if self.is(x, &tp.node) => Some(Scope::new()),
+            Pattern::Type(_) => None,
+            Pattern::Struct(path, binds, _) => match x {
+                Value::Struct(tp, fields) if **tp == *last(path) => Some(
+                    binds.iter()
+                        .filter(|(_, bind)| bind.name() != "_")
+                        .map(|(field, bind)| {
+                            let value = fields.iter()
+                                .find(|(name, _)| **name == *field.name())
+                                .map_or(Value::Void, |(_, x)| x.copy());
+                            (bind.name().to_string(), Place::new(value))
+                        })
+                        .collect()
+                ),
+                _ => None,
+            },
+        }
+    }
+
+    // `x is tp` of a value the checker let be tested against `tp`
+    fn is(&self, x: &Value, tp: &Type) -> bool {
+        match (x, tp) {
+            (Value::Integer(_, p), Type::Primitive(q)) => p.unwrap_or(Primitive::I32) == *q,
+            (Value::Float(_, p), Type::Primitive(q)) => p.unwrap_or(Primitive::F64) == *q,
+            (Value::Bool(_), Type::Primitive(q)) => *q == Primitive::Bool,
+            (Value::Char(_), Type::Primitive(q)) => *q == Primitive::Char,
+            (Value::Array(a), Type::FixedArray(_, n)) => a.len() == *n,
+            (Value::Struct(name, _), Type::Named(path, _)) if self.structs.contains_key(last(path)) => **name == *last(path),
+            // an interface, the checker lets only the values implementing it be tested
+            (_, Type::Named(_, _)) => true,
+            (Value::String(_), Type::String) | (Value::Array(_), Type::Array(_)) | (Value::Void, Type::Void) => true,
+            (Value::Function(_), Type::Function(_)) | (Value::Range(_, _, _), Type::Range(_)) => true,
+            _ => false,
+        }
+    }
+
+    // where `x` lives, a variable, a field or an item of one, or a cell of its own
+    fn place(&mut self, x: &'d Expression) -> Run<Place> {
+        match x {
+            Expression::Identifier(name) => self.variable(name.name()),
+            Expression::Path(path) => {
+                let names = path.path().iter().map(|x| x.name()).collect::<Vec<_>>();
+                match names.as_slice() {
+                    ["io", "print"] => return Ok(Place::new(Value::Function(value::Function::Print { line: false }))),
+                    ["io", "println"] => return Ok(Place::new(Value::Function(value::Function::Print { line: true }))),
+                    _ => (),
+                }
+                let (first, fields) = names.split_first().unwrap_or_else(|| unreachable!("a path has a name"));
+                let mut place = self.variable(first)?;
+                for field in fields {
+                    place = self.member(place, field);
+                }
+                Ok(place)
+            }
+            Expression::Field(x, field, _) => {
+                let place = self.place(x)?;
+                Ok(self.member(place, field.name()))
+            }
+            Expression::Index(a, index, span) if !matches!(**index, Expression::Range(_, _, _)) => {
+                let place = self.place(a)?;
+                let index = count(&self.expr(index)?);
+                let len = place.inspect(|x| match x {
+                    Value::Array(a) => a.len(),
+                    x => unreachable!("checked program indexes {:?}", x),
+                });
+                if index < 0 || index >= len as i128 {
+                    return Err(Flow::Panic(Panic::Index { index: saturated(index), len: len as u64 }, *span));
+                }
+                Ok(place.item(index as usize))
+            }
+            x => Ok(Place::new(self.expr(x)?)),
+        }
+    }
+
+    fn slice(&mut self, a: &'d Expression, start: &'d Expression, end: &'d Expression, span: Span) -> Run<Value> {
+        let a = match self.expr(a)? {
+            Value::Array(a) => a,
+            x => unreachable!("checked program slices {:?}", x),
+        };
+        let (start, end) = (count(&self.expr(start)?), count(&self.expr(end)?));
+        if start < 0 || start > end || end > a.len() as i128 {
+            let (start, end, len) = (saturated(start), saturated(end), a.len() as u64);
+            return Err(Flow::Panic(Panic::Slice { start, end, len }, span));
+        }
+        Ok(Value::Array(a.slice(start as usize, end as usize)))
+    }
+
+    fn expr(&mut self, x: &'d Expression) -> Run<Value> {
+        match x {
+            Expression::Literal(l) => Ok(literal(&l.node)),
+            Expression::Identifier(_) | Expression::Path(_) | Expression::Field(_, _, _) => Ok(self.place(x)?.get()),
+            Expression::Index(a, index, span) => match &**index {
+                Expression::Range(start, end, _) => self.slice(a, start, end, *span),
+                _ => Ok(self.place(x)?.get()),
+            },
+            Expression::Call(f, args, _) => self.call_expr(f, args),
+            Expression::Unary(op, x, _) => Ok(ops::unary(*op, self.expr(x)?)),
+            Expression::Binary(Binary::And, _, _, _) => Ok(Value::Bool(self.condition(x)?.is_some())),
+            Expression::Binary(Binary::Or, a, b, _) => Ok(Value::Bool(self.truth(a)? || self.truth(b)?)),
+            Expression::Binary(op, a, b, span) => {
+                let (a, b) = (self.expr(a)?, self.expr(b)?);
+                self.binary(*op, a, b, *span)
+            }
+            Expression::Block(b) => self.block(b),
+            Expression::If(cond, then, otherwise, _) => match self.condition(cond)? {
+                Some(scope) => self.scoped(scope, |machine| machine.block(then)),
+                None => match otherwise {
+                    Some(otherwise) => self.expr(otherwise),
+                    None => Ok(Value::Void),
+                },
+            },
+            Expression::For(kind, body, _) => self.repeat(kind, body),
+            Expression::Break(x, _) => {
+                let value = match x {
+                    Some(x) => self.expr(x)?,
+                    None => Value::Void,
+                };
+                Err(Flow::Break(value))
+            }
+            Expression::Continue(_) => Err(Flow::Continue),
+            Expression::Return(x, _) => {
+                let value = match x {
+                    Some(x) => self.expr(x)?,
+                    None => Value::Void,
+                };
+                Err(Flow::Return(value))
+            }
+            Expression::Range(start, end, _) => {
+                let (start, end) = ops::adopt(self.expr(start)?, self.expr(end)?);
+                let p = match &start {
+                    Value::Integer(_, p) => *p,
+                    _ => None,
+                };
+                Ok(Value::Range(count(&start), count(&end), p))
+            }
+            Expression::Struct(path, _, values, _) => {
+                let s = self.structs[last(path)];
+                let mut given = HashMap::new();
+                for (name, x) in values {
+                    given.insert(name.name(), self.expr(x)?.copy());
+                }
+                let fields = s.fields().iter()
+                    .map(|field| {
+                        let value = given.remove(field.name().name()).unwrap_or(Value::Void);
+                        (Rc::from(field.name().name()), value.fit(&field.declare().node))
+                    })
+                    .collect();
+                Ok(Value::Struct(Rc::from(s.name().name()), fields))
+            }
+            // the type arguments matter only to a call
+            Expression::Instance(f, _, _) => self.expr(f),
+            Expression::Is(x, pattern, _) => {
+                let x = self.expr(x)?;
+                Ok(Value::Bool(self.matches(&x, pattern).is_some()))
+            }
+            Expression::Match(x, arms, _) => {
+                let x = self.expr(x)?;
+                for arm in arms {
+                    if let Some(scope) = self.matches(&x, arm.pattern()) {
+                        return self.scoped(scope, |machine| machine.expr(arm.value()));
+                    }
+                }
+                unreachable!("checked match has an arm for {:?}", x)
+            }
+            Expression::Array(items, _) => {
+                let mut values = Vec::with_capacity(items.len());
+                for x in items {
+                    values.push(self.expr(x)?.copy());
+                }
+                Ok(Value::Array(Array::new(values)))
+            }
+            Expression::Repeat(x, n, _) => {
+                let x = self.expr(x)?;
+                let n = count(&self.expr(n)?).max(0) as usize;
+                Ok(Value::Array(Array::new((0..n).map(|_| x.copy()).collect())))
+            }
+            Expression::Cast(x, tp, _) => Ok(ops::cast(self.expr(x)?, &tp.node)),
+            Expression::With(name, x, body, _) => {
+                let place = Place::new(self.expr(x)?);
+                let mut scope = Scope::new();
+                scope.insert(name.name().to_string(), place.clone());
+                let result = self.scoped(scope, |machine| machine.block(body));
+                // a panic of the body is the one told, a panic of `close()` only when the body had none
+                match (self.close(place), result) {
+                    (_, Err(Flow::Panic(panic, span))) => Err(Flow::Panic(panic, span)),
+                    (Err(flow), _) => Err(flow),
+                    (Ok(()), result) => result,
+                }
+            }
+        }
+    }
+
+    fn call_expr(&mut self, f: &'d Expression, args: &'d [Expression]) -> Run<Value> {
+        let explicit: &[Spanned<Type>] = match f {
+            Expression::Instance(_, explicit, _) => explicit,
+            _ => &[],
+        };
+        let callee = match self.expr(f)? {
+            Value::Function(callee) => callee,
+            x => unreachable!("checked program calls {:?}", x),
+        };
+        match callee {
+            value::Function::Define(name) => {
+                let f = self.functions[&*name];
+                let args = self.arguments(f, args)?;
+                self.invoke(f, explicit, None, args)
+            }
+            value::Function::Method(receiver, tp, name) => {
+                let f = self.methods[&*tp][&*name];
+                let args = self.arguments(f, args)?;
+                self.invoke(f, &[], Some(receiver), args)
+            }
+            value::Function::Print { line } => {
+                let text = match self.expr(&args[0])? {
+                    Value::String(text) => text,
+                    x => unreachable!("checked program prints {:?}", x),
+                };
+                let written = if line { writeln!(self.out, "{}", text) } else { write!(self.out, "{}", text) };
+                // a program printing to a closed stdout goes on
+                written.ok();
+                Ok(Value::Void)
+            }
+            value::Function::Len => match self.expr(&args[0])? {
+                Value::Array(a) => Ok(Value::Integer(a.len() as u64, Some(Primitive::Size))),
+                x => unreachable!("checked program takes `len` of {:?}", x),
+            },
+        }
+    }
+
+    // `close()` of the `Closer` opened by a `with`
+    fn close(&mut self, place: Place) -> Run<()> {
+        let tp = place.inspect(|x| match x {
+            Value::Struct(tp, _) => tp.clone(),
+            x => unreachable!("checked program closes {:?}", x),
+        });
+        let f = self.methods[&*tp]["close"];
+        self.invoke(f, &[], Some(place), Vec::new()).map(|_| ())
+    }
+
+    // places for `ref` params, values for the others
+    fn arguments(&mut self, f: &'d Function, args: &'d [Expression]) -> Run<Vec<Place>> {
+        let mut places = Vec::with_capacity(args.len());
+        for (declare, x) in f.declare().params.iter().zip(args) {
+            places.push(match declare.value() {
+                commons::Value::Reference => self.place(x)?,
+                _ => Place::new(self.expr(x)?),
+            });
+        }
+        Ok(places)
+    }
+
+    fn repeat(&mut self, kind: &'d Loop, body: &'d Block) -> Run<Value> {
+        match kind {
+            Loop::Forever => loop {
+                if let Some(value) = self.turn(body, None)? {
+                    return Ok(value);
+                }
+            },
+            Loop::While(cond) => {
+                while self.truth(cond)? {
+                    if let Some(value) = self.turn(body, None)? {
+                        return Ok(value);
+                    }
+                }
+            }
+            // the items are the ones there when the loop starts
+            Loop::In(name, xs) => match self.expr(xs)?.settle() {
+                Value::Array(a) => {
+                    for x in a.items() {
+                        if let Some(value) = self.turn(body, Some((name.name(), x.copy())))? {
+                            return Ok(value);
+                        }
+                    }
+                }
+                Value::Range(start, end, p) => {
+                    let mut i = start;
+                    while i < end {
+                        if let Some(value) = self.turn(body, Some((name.name(), integer(p, i))))? {
+                            return Ok(value);
+                        }
+                        i += 1;
+                    }
+                }
+                x => unreachable!("checked program loops over {:?}", x),
+            },
+        }
+        Ok(Value::Void)
+    }
+
+    // one time through the body of a loop, `Some` with the value of a `break`
+    fn turn(&mut self, body: &'d Block, item: Option<(&str, Value)>) -> Run<Option<Value>> {
+        let mut scope = Scope::new();
+        if let Some((name, x)) = item {
+            scope.insert(name.to_string(), Place::new(x));
+        }
+        match self.scoped(scope, |machine| machine.block(body)) {
+            Ok(_) | Err(Flow::Continue) => Ok(None),
+            Err(Flow::Break(value)) => Ok(Some(value)),
+            Err(flow) => Err(flow),
+        }
+    }
+}
+
+// an unsuffixed literal assigned takes the type of what it replaces
+fn like(value: Value, old: &Value) -> Value {
+    match (value, old) {
+        (value, Value::Integer(_, Some(p))) | (value, Value::Float(_, Some(p))) => value.fit(&Type::Primitive(*p)),
+        (Value::Array(a), Value::Array(old)) => match old.items().first() {
+            Some(Value::Integer(_, Some(p))) | Some(Value::Float(_, Some(p))) => {
+                Value::Array(a).fit(&Type::Array(Box::new(Type::Primitive(*p))))
+            }
+            _ => Value::Array(a),
+        },
+        (value, _) => value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pluma::check::Checker;
+    use crate::pluma::ir::ast::SourceFile;
+    use crate::pluma::token::parse_str;
+
+    // `main` of a program the checker takes, and what it printed
+    fn run(src: &str) -> (Result<Value, (Panic, Span)>, String) {
+        let file = parse_str::<SourceFile>(src).expect(src);
+        Checker::new().check(file.defines()).expect(src);
+        let mut machine = Machine::new(file.defines(), Vec::new());
+        let result = machine.call("main", Vec::new());
+        (result, String::from_utf8(machine.out.clone()).expect(src))
+    }
+
+    fn value(src: &str) -> Value {
+        run(src).0.expect(src)
+    }
+
+    // the panic and where in `src` it was raised
+    fn panic(src: &str) -> (Panic, usize) {
+        match run(src).0 {
+            Err((panic, span)) => (panic, span.start),
+            Ok(x) => panic!("{} gives {:?}", src, x),
+        }
+    }
+
+    fn int(p: Primitive, value: i128) -> Value {
+        integer(Some(p), value)
+    }
+
+    #[test]
+    fn arithmetic() {
+        for (src, want) in &[
+            ("fn main() -> i32 { 7 / 2 + 3 % 2 }", int(Primitive::I32, 4)),
+            ("fn main() -> i32 { -7 / 2 }", int(Primitive::I32, -3)),
+            ("fn main() -> u8 { 250u8 + 10 }", int(Primitive::U8, 4)),
+            ("fn main() -> i8 { -128i8 / -1 }", int(Primitive::I8, -128)),
+            ("fn main() -> i64 { 2i64 ^ 62 }", int(Primitive::I64, 1 << 62)),
+            ("fn main() -> i32 { 2 ^ -1 }", int(Primitive::I32, 0)),
+            ("fn main() -> i32 { -8 >> 1 }", int(Primitive::I32, -4)),
+            ("fn main() -> u32 { 1u32 << 33 }", int(Primitive::U32, 2)),
+            ("fn main() -> f64 { 1 + 0.5 }", Value::Float(1.5, Some(Primitive::F64))),
+            ("fn main() -> bool { 1 < 2 && \"a\" < \"b\" && 'a' != 'b' }", Value::Bool(true)),
+            ("fn main() -> u8 { 300 as u8 }", int(Primitive::U8, 44)),
+            ("fn main() -> i32 { 2.9 as i32 }", int(Primitive::I32, 2)),
+        ] {
+            assert_eq!(value(src), *want, "{}", src);
+        }
+        for src in &["fn main() -> i32 { 1 / 0 }", "fn main() -> u8 {\n    var x u8 = 0\n    7u8 % x\n}"] {
+            assert_eq!(panic(src).0, Panic::DivideByZero, "{}", src);
+        }
+    }
+
+    #[test]
+    fn control() {
+        let (result, out) = run("fn main() {\n    io.println(\"a\")\n    io.print(\"b\")\n}");
+        assert_eq!(result, Ok(Value::Void));
+        assert_eq!(out, "a\nb");
+        for (src, want) in &[
+            ("fn main() -> i32 {\n    var n = 0\n    for i in 0..5 {\n        n += i\n    }\n    n\n}", 10),
+            ("fn main() -> i32 {\n    var n = 0\n    for n < 10 {\n        n += 3\n    }\n    n\n}", 12),
+            ("fn main() -> i32 {\n    var n = 0\n    for x in [1, 2, 3] {\n        if x == 2 {\n            continue\n        }\n        n += x\n    }\n    n\n}", 4),
+            ("fn main() -> i32 {\n    var n = 1\n    for {\n        n *= 2\n        if n > 20 {\n            break\n        }\n    }\n    n\n}", 32),
+            ("fn main() -> i32 {\n    for i in 0..10 {\n        if i * i > 10 {\n            return i\n        }\n    }\n    -1\n}", 4),
+            ("fn f(const n i32) -> i32 { if n < 2 { n } else { f(n - 1) + f(n - 2) } }\nfn main() -> i32 { f(10) }", 55),
+        ] {
+            assert_eq!(value(src), int(Primitive::I32, *want), "{}", src);
+        }
+    }
+
+    #[test]
+    fn places() {
+        for (src, want) in &[
+            // `var` copies, `ref` is the place itself
+            ("struct P { x i32 }\nfn main() -> i32 {\n    var p = P{x: 1}\n    var q = p\n    ref r = p\n    q.x = 2\n    r.x += 10\n    p.x\n}", 11),
+            ("fn bump(ref a []i32) { a[0] += 1 }\nfn main() -> i32 {\n    var a = [1, 2]\n    bump(a)\n    bump(a)\n    a[0]\n}", 3),
+            ("fn bump(var a []i32) { a[0] += 1 }\nfn main() -> i32 {\n    var a = [1, 2]\n    bump(a)\n    a[0]\n}", 1),
+            // a slice shares the items of the array
+            ("fn main() -> i32 {\n    var a = [1, 2, 3]\n    ref s = a[1..3]\n    s[0] = 7\n    a[1] + len(s) as i32\n}", 9),
+            ("struct P { x i32 }\nfn (ref p P) add(const n i32) { p.x += n }\nfn main() -> i32 {\n    var p = P{x: 1}\n    p.add(2)\n    p.x\n}", 3),
+            ("const N i32 = { 2 + 1 }\nvar total i32 = { N * 2 }\nfn main() -> i32 {\n    total += 1\n    var a [N]i32\n    total + len(a) as i32\n}", 10),
+        ] {
+            assert_eq!(value(src), int(Primitive::I32, *want), "{}", src);
+        }
+    }
+
+    #[test]
+    fn patterns() {
+        let shapes = "struct Circle { r f64 }\nstruct Square { s f64 }\ninterface Shape { fn area() -> f64 }\n\
+            fn (const c Circle) area() -> f64 { c.r * c.r * 3.0 }\nfn (const s Square) area() -> f64 { s.s * s.s }\n";
+        for (body, want) in &[
+            ("var s Shape = Square{s: 2.0}\n    s.area()", 4.0),
+            ("var s Shape = Circle{r: 1.0}\n    s is { Square{s} => s, Circle{r} => r * 10.0 }", 10.0),
+            ("var s Shape = Circle{r: 2.0}\n    if s is Circle{r} && r > 1.0 { r } else { 0.0 }", 2.0),
+        ] {
+            let src = format!("{}fn main() -> f64 {{\n    {}\n}}", shapes, body);
+            assert_eq!(value(&src), Value::Float(*want, Some(Primitive::F64)), "{}", src);
+        }
+    }
+
+    #[test]
+    fn with() {
+        let file = "struct File { name str }\nfn open(const name str) -> File { File{name: name} }\n\
+            fn (const f File) close() { io.println(\"close \" + f.name) }\n";
+        for (body, want, out) in &[
+            ("with f = open(\"a\") {\n        io.println(\"body\")\n    }\n    0", Ok(0), "body\nclose a\n"),
+            ("with f = open(\"a\") {\n        return 1\n    }\n    0", Ok(1), "close a\n"),
+            ("with f = open(\"a\") { 2 }", Ok(2), "close a\n"),
+            // the inner one first
+            ("with f = open(\"a\") {\n        with g = open(\"b\") {\n            return 3\n        }\n    }\n    0", Ok(3), "close b\nclose a\n"),
+            ("for i in 0..3 {\n        with f = open(\"a\") {\n            if i == 1 {\n                break\n            }\n            io.println(\"body\")\n        }\n    }\n    4", Ok(4), "body\nclose a\nclose a\n"),
+            ("for i in 0..2 {\n        with f = open(\"a\") {\n            continue\n        }\n    }\n    5", Ok(5), "close a\nclose a\n"),
+            ("with f = open(\"a\") {\n        var a = [1]\n        a[1]\n    }", Err(Panic::Index { index: 1, len: 1 }), "close a\n"),
+        ] {
+            let src = format!("{}fn main() -> i32 {{\n    {}\n}}", file, body);
+            let (result, printed) = run(&src);
+            assert_eq!(result.map_err(|(panic, _)| panic), want.clone().map(|x| int(Primitive::I32, x)), "{}", src);
+            assert_eq!(printed, *out, "{}", src);
+        }
+        // a panic of `close()` is told only when the body had none
+        let file = "struct File { n i32 }\nfn open(const n i32) -> File { File{n: n} }\n\
+            fn (const f File) close() {\n    var a = [0]\n    a[f.n] = 1\n}\n";
+        for (body, want) in &[
+            ("with f = open(2) { 0 }", Panic::Index { index: 2, len: 1 }),
+            ("with f = open(2) { 1 / (f.n - 2) }", Panic::DivideByZero),
+        ] {
+            let src = format!("{}fn main() -> i32 {{\n    {}\n}}", file, body);
+            assert_eq!(panic(&src).0, *want, "{}", src);
+        }
+    }
+
+    #[test]
+    fn bounds() {
+        let array = "fn main() -> i32 {\n    var a = [1, 2, 3]\n    var i = 3\n    ";
+        for (x, want) in &[
+            ("a[i]", Panic::Index { index: 3, len: 3 }),
+            ("a[i - 4]", Panic::Index { index: -1, len: 3 }),
+            ("a[i] = 0\n    0", Panic::Index { index: 3, len: 3 }),
+            ("a[2..1][0]", Panic::Slice { start: 2, end: 1, len: 3 }),
+            ("a[1..i + 1][0]", Panic::Slice { start: 1, end: 4, len: 3 }),
+            ("a[1..3][2]", Panic::Index { index: 2, len: 2 }),
+        ] {
+            let src = format!("{}{}\n}}", array, x);
+            assert_eq!(panic(&src), (want.clone(), array.len()), "{}", src);
+        }
+        assert_eq!(value(&format!("{}a[i - 1] + a[0..i][1]\n}}", array)), int(Primitive::I32, 5));
+    }
+}
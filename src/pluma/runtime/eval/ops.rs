@@ -0,0 +1,165 @@
+use std::cmp::Ordering;
+use std::rc::Rc;
+
+use crate::pluma::ir::block::{Binary, Unary};
+use crate::pluma::ir::cast::{Cast, Scalar};
+use crate::pluma::ir::types::{Primitive, Type};
+use crate::pluma::runtime::eval::value::{Value, integer, rounded};
+use crate::pluma::runtime::panic::Panic;
+
+// primitive of a number, `None` inside for an unsuffixed literal
+fn number(x: &Value) -> Option<Option<Primitive>> {
+    match x {
+        Value::Integer(_, p) | Value::Float(_, p) => Some(*p),
+        _ => None,
+    }
+}
+
+/// both sides as one type, an unsuffixed literal takes the type of the other side
+///
+/// `1 + 2.5` with neither typed is a float with no type yet.
+pub fn adopt(a: Value, b: Value) -> (Value, Value) {
+    match (number(&a), number(&b)) {
+        (Some(Some(p)), Some(None)) => (a, b.fit(&Type::Primitive(p))),
+        (Some(None), Some(Some(p))) => (a.fit(&Type::Primitive(p)), b),
+        (Some(None), Some(None)) => match (&a, &b) {
+            (Value::Integer(_, _), Value::Float(_, _)) => (untyped_float(&a), b),
+            (Value::Float(_, _), Value::Integer(_, _)) => (a, untyped_float(&b)),
+            _ => (a, b),
+        },
+        _ => (a, b),
+    }
+}
+
+fn untyped_float(x: &Value) -> Value {
+    Value::Float(x.integer().unwrap_or(0) as f64, None)
+}
+
+fn compare(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Integer(_, _), Value::Integer(_, _)) => a.integer()?.partial_cmp(&b.integer()?),
+        (Value::Float(x, _), Value::Float(y, _)) => x.partial_cmp(y),
+        (Value::Char(x), Value::Char(y)) => x.partial_cmp(y),
+        (Value::String(x), Value::String(y)) => x.partial_cmp(y),
+        _ => None,
+    }
+}
+
+// `x ^ y`, a negative `y` truncates `1 / x ^ -y` toward zero
+fn power(x: i128, y: i128) -> Result<i128, Panic> {
+    if y < 0 {
+        return match x {
+            0 => Err(Panic::DivideByZero),
+            1 => Ok(1),
+            -1 if y % 2 == 0 => Ok(1),
+            -1 => Ok(-1),
+            _ => Ok(0),
+        };
+    }
+    let (mut base, mut exp, mut acc) = (x, y, 1i128);
+    while exp > 0 {
+        if exp & 1 == 1 {
+            acc = acc.wrapping_mul(base);
+        }
+        base = base.wrapping_mul(base);
+        exp >>= 1;
+    }
+    Ok(acc)
+}
+
+// wrapping at the width of `p`, a shift takes its count modulo the width
+fn integers(op: Binary, x: i128, y: i128, p: Option<Primitive>) -> Result<Value, Panic> {
+    let bits = i128::from(p.map_or(64, |p| p.bits()));
+    let value = match op {
+        Binary::Add => x.wrapping_add(y),
+        Binary::Sub => x.wrapping_sub(y),
+        Binary::Mul => x.wrapping_mul(y),
+        Binary::Div if y == 0 => return Err(Panic::DivideByZero),
+        Binary::Div => x.wrapping_div(y),
+        Binary::Mod if y == 0 => return Err(Panic::DivideByZero),
+        Binary::Mod => x.wrapping_rem(y),
+        Binary::Pow => power(x, y)?,
+        Binary::BitOr => x | y,
+        Binary::BitAnd => x & y,
+        Binary::ShiftLeft => x << y.rem_euclid(bits),
+        // `x` is extended by the sign of its type, so a signed shift keeps the sign
+        Binary::ShiftRight => x >> y.rem_euclid(bits),
+        op => unreachable!("{:?} is no arithmetic", op),
+    };
+    Ok(integer(p, value))
+}
+
+fn floats(op: Binary, x: f64, y: f64, p: Option<Primitive>) -> Value {
+    let value = match op {
+        Binary::Add => x + y,
+        Binary::Sub => x - y,
+        Binary::Mul => x * y,
+        Binary::Div => x / y,
+        Binary::Mod => x % y,
+        Binary::Pow => x.powf(y),
+        op => unreachable!("{:?} is no float arithmetic", op),
+    };
+    Value::Float(rounded(p, value), p)
+}
+
+/// `a op b` of a checked program, but `&&` and `||` which the caller cuts short
+pub fn binary(op: Binary, a: Value, b: Value) -> Result<Value, Panic> {
+    let (a, b) = adopt(a, b);
+    let order = |test: fn(Ordering) -> bool| Value::Bool(compare(&a, &b).map(test) == Some(true));
+    let value = match op {
+        Binary::Equal => Value::Bool(a == b),
+        Binary::NotEqual => Value::Bool(a != b),
+        Binary::Less => order(|x| x == Ordering::Less),
+        Binary::LessEqual => order(|x| x != Ordering::Greater),
+        Binary::Greater => order(|x| x == Ordering::Greater),
+        Binary::GreaterEqual => order(|x| x != Ordering::Less),
+        _ => match (&a, &b) {
+            (Value::Integer(_, p), Value::Integer(_, _)) => {
+                let (x, y) = (a.integer().unwrap_or(0), b.integer().unwrap_or(0));
+                return integers(op, x, y, *p);
+            }
+            (Value::Float(x, p), Value::Float(y, _)) => floats(op, *x, *y, *p),
+            (Value::Bool(x), Value::Bool(y)) => match op {
+                Binary::BitOr | Binary::Or => Value::Bool(*x | *y),
+                _ => Value::Bool(*x & *y),
+            },
+            (Value::String(x), Value::String(y)) => Value::String(Rc::from(format!("{}{}", x, y))),
+            _ => unreachable!("checked program gives {:?} and {:?} to {:?}", a, b, op),
+        },
+    };
+    Ok(value)
+}
+
+pub fn unary(op: Unary, x: Value) -> Value {
+    match (op, x) {
+        (Unary::Neg, Value::Float(x, p)) => Value::Float(-x, p),
+        (Unary::Neg, x @ Value::Integer(_, _)) => {
+            let p = number(&x).and_then(|p| p);
+            integer(p, x.integer().unwrap_or(0).wrapping_neg())
+        }
+        (Unary::Not, Value::Bool(x)) => Value::Bool(!x),
+        (Unary::Not, x @ Value::Integer(_, _)) => {
+            let p = number(&x).and_then(|p| p);
+            integer(p, !x.integer().unwrap_or(0))
+        }
+        (op, x) => unreachable!("checked program gives {:?} to {:?}", x, op),
+    }
+}
+
+/// `x as tp`, what `cast::Cast` gives between numbers, anything else is a `tp` already
+pub fn cast(x: Value, tp: &Type) -> Value {
+    let to = match tp {
+        Type::Primitive(p) => *p,
+        _ => return x,
+    };
+    let (from, scalar) = match x.settle() {
+        Value::Integer(bits, Some(p)) => (p, Scalar::Integer(bits)),
+        Value::Float(x, Some(p)) => (p, Scalar::Float(x)),
+        x => return x,
+    };
+    match Cast::new(from, to).map(|cast| cast.apply(scalar)) {
+        Some(Scalar::Integer(bits)) => Value::Integer(bits, Some(to)),
+        Some(Scalar::Float(x)) => Value::Float(x, Some(to)),
+        None => unreachable!("checked program casts {:?} to {:?}", from, to),
+    }
+}
@@ -0,0 +1,209 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::pluma::ir::cast::{Scalar, mask};
+use crate::pluma::ir::types::{Primitive, Type};
+
+/// what an expression gives while the program runs
+///
+/// An integer is the two's complement in the low bits of the `u64` like a `cast::Scalar`.
+/// A literal with no suffix has no primitive until it meets a type, it is kept in the bits of an `i64` till then.
+#[derive(Debug, Clone)]
+pub enum Value {
+    Void,
+    Bool(bool),
+    Char(char),
+    Integer(u64, Option<Primitive>),
+    Float(f64, Option<Primitive>),
+    String(Rc<str>),
+    Array(Array),
+    /// name of the struct and its fields in the order they are declared
+    Struct(Rc<str>, Vec<(Rc<str>, Value)>),
+    Function(Function),
+    /// `a..b` of integers of the primitive, end exclusive
+    Range(i128, i128, Option<Primitive>),
+}
+
+/// items `start..start + len` of storage which slices of it share
+#[derive(Debug, Clone)]
+pub struct Array {
+    items: Rc<RefCell<Vec<Value>>>,
+    start: usize,
+    len: usize,
+}
+
+/// function value, a define, a method with the place of its receiver or a builtin
+#[derive(Debug, Clone)]
+pub enum Function {
+    Define(Rc<str>),
+    Method(Place, Rc<str>, Rc<str>),
+    Print { line: bool },
+    Len,
+}
+
+/// where a value lives, a variable and the fields and items down from it
+///
+/// A value with no variable gets a cell of its own, so anything has a place.
+#[derive(Debug, Clone)]
+pub struct Place {
+    cell: Rc<RefCell<Value>>,
+    steps: Vec<Step>,
+}
+
+#[derive(Debug, Copy, Clone)]
+enum Step {
+    Field(usize),
+    Item(usize),
+}
+
+impl Array {
+    pub fn new(items: Vec<Value>) -> Array {
+        let len = items.len();
+        Array { items: Rc::new(RefCell::new(items)), start: 0, len }
+    }
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    pub fn items(&self) -> Vec<Value> {
+        self.items.borrow()[self.start..self.start + self.len].to_vec()
+    }
+    /// `start..end` of this one sharing its items, the caller checks the bounds
+    pub fn slice(&self, start: usize, end: usize) -> Array {
+        Array { items: self.items.clone(), start: self.start + start, len: end - start }
+    }
+}
+
+impl Place {
+    pub fn new(value: Value) -> Place {
+        Place { cell: Rc::new(RefCell::new(value)), steps: Vec::new() }
+    }
+    /// field `i` of the struct here
+    pub fn field(&self, i: usize) -> Place {
+        let mut steps = self.steps.clone();
+        steps.push(Step::Field(i));
+        Place { cell: self.cell.clone(), steps }
+    }
+    /// item `i` of the array here, the caller checks the bounds
+    pub fn item(&self, i: usize) -> Place {
+        let mut steps = self.steps.clone();
+        steps.push(Step::Item(i));
+        Place { cell: self.cell.clone(), steps }
+    }
+    pub fn get(&self) -> Value {
+        self.with(|x| x.clone())
+    }
+    pub fn set(&self, value: Value) {
+        self.with(|x| *x = value)
+    }
+    /// `f` of the value here, without a copy of it
+    pub fn inspect<R>(&self, f: impl FnOnce(&Value) -> R) -> R {
+        self.with(|x| f(x))
+    }
+
+    fn with<R>(&self, f: impl FnOnce(&mut Value) -> R) -> R {
+        fn walk<R>(x: &mut Value, steps: &[Step], f: impl FnOnce(&mut Value) -> R) -> R {
+            match (steps.split_first(), x) {
+                (None, x) => f(x),
+                (Some((Step::Field(i), steps)), Value::Struct(_, fields)) => walk(&mut fields[*i].1, steps, f),
+                // the items are in storage of their own, no longer under the cell
+                (Some((Step::Item(i), steps)), Value::Array(a)) => {
+                    let items = a.items.clone();
+                    let mut items = items.borrow_mut();
+                    walk(&mut items[a.start + *i], steps, f)
+                }
+                (Some(_), x) => unreachable!("checked program steps into {:?}", x),
+            }
+        }
+        walk(&mut self.cell.borrow_mut(), &self.steps, f)
+    }
+}
+
+/// `f32` keeps what fits in it
+pub fn rounded(p: Option<Primitive>, x: f64) -> f64 {
+    match p {
+        Some(Primitive::F32) => f64::from(x as f32),
+        _ => x,
+    }
+}
+
+impl Value {
+    /// the same value bound anew, arrays and structs are copied so that changing one leaves the other
+    pub fn copy(&self) -> Value {
+        match self {
+            Value::Array(a) => Value::Array(Array::new(a.items().iter().map(Value::copy).collect())),
+            Value::Struct(name, fields) => {
+                Value::Struct(name.clone(), fields.iter().map(|(name, x)| (name.clone(), x.copy())).collect())
+            }
+            x => x.clone(),
+        }
+    }
+
+    /// the value as a `tp`, an unsuffixed literal in it takes the type, anything else is one already
+    pub fn fit(self, tp: &Type) -> Value {
+        match (self, tp) {
+            (Value::Integer(bits, None), Type::Primitive(p)) if p.is_integer() => integer(Some(*p), i128::from(bits as i64)),
+            (Value::Integer(bits, None), Type::Primitive(p)) if p.is_float() => {
+                Value::Float(rounded(Some(*p), bits as i64 as f64), Some(*p))
+            }
+            (Value::Float(x, None), Type::Primitive(p)) if p.is_float() => Value::Float(rounded(Some(*p), x), Some(*p)),
+            (Value::Array(a), Type::Array(item)) | (Value::Array(a), Type::FixedArray(item, _)) | (Value::Array(a), Type::ConstArray(item, _)) => {
+                for x in a.items.borrow_mut()[a.start..a.start + a.len].iter_mut() {
+                    *x = std::mem::replace(x, Value::Void).fit(item);
+                }
+                Value::Array(a)
+            }
+            (Value::Range(start, end, None), Type::Range(item)) => match &**item {
+                Type::Primitive(p) => Value::Range(start, end, Some(*p)),
+                _ => Value::Range(start, end, None),
+            },
+            (x, _) => x,
+        }
+    }
+
+    /// an unsuffixed literal which never met a type is what the checker took it for, `i32` or `f64`
+    pub fn settle(self) -> Value {
+        match self {
+            Value::Integer(bits, None) => integer(Some(Primitive::I32), i128::from(bits as i64)),
+            Value::Float(x, None) => Value::Float(x, Some(Primitive::F64)),
+            Value::Range(start, end, None) => Value::Range(start, end, Some(Primitive::I32)),
+            Value::Array(a) => {
+                for x in a.items.borrow_mut()[a.start..a.start + a.len].iter_mut() {
+                    *x = std::mem::replace(x, Value::Void).settle();
+                }
+                Value::Array(a)
+            }
+            x => x,
+        }
+    }
+
+    /// integer value, by the primitive it is or as an `i64` with none
+    pub fn integer(&self) -> Option<i128> {
+        match self {
+            Value::Integer(bits, p) => Scalar::Integer(*bits).integer(p.unwrap_or(Primitive::I64)),
+            _ => None,
+        }
+    }
+}
+
+/// `value` in the bits of `p`, what is left over is dropped
+pub fn integer(p: Option<Primitive>, value: i128) -> Value {
+    Value::Integer(value as u64 & mask(p.unwrap_or(Primitive::I64)), p)
+}
+
+// what `==` says, both sides are of one type
+impl PartialEq for Value {
+    fn eq(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Void, Value::Void) => true,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Char(a), Value::Char(b)) => a == b,
+            (Value::Integer(a, p), Value::Integer(b, q)) => a == b && p == q,
+            (Value::Float(a, p), Value::Float(b, q)) => a == b && p == q,
+            (Value::String(a), Value::String(b)) => a == b,
+            (Value::Array(a), Value::Array(b)) => a.items() == b.items(),
+            (Value::Struct(a, xs), Value::Struct(b, ys)) => a == b && xs == ys,
+            (Value::Range(a, b, p), Value::Range(c, d, q)) => a == c && b == d && p == q,
+            _ => false,
+        }
+    }
+}
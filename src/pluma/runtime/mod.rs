@@ -1,11 +1,13 @@
 use crate::pluma::token::{GlobalIdentifier, Identifier};
 use llvm_sys::prelude::{LLVMContextRef, LLVMModuleRef};
-use nom::lib::std::collections::{HashMap, HashSet};
+use nom::lib::std::collections::HashSet;
 use std::any::Any;
 use std::hash::{Hash, Hasher};
 
 pub mod obj;
 pub mod library;
+pub mod panic;
+pub mod eval;
 
 pub struct Runtime {
     llctx: LLVMContextRef,
@@ -0,0 +1,31 @@
+use std::fmt::{self, Display, Formatter};
+
+/// why a running program stops, the message every backend is to report it with
+///
+/// `eval` raises them at the expression which failed.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Panic {
+    /// `a[i]` past the end, or below 0
+    Index { index: i64, len: u64 },
+    /// `a[i..j]` past the end or with `i` after `j`
+    Slice { start: i64, end: i64, len: u64 },
+    /// `a / 0` or `a % 0` of integers
+    DivideByZero,
+}
+
+impl Display for Panic {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Panic::Index { index, len } => {
+                write!(f, "index out of bounds: the len is {} but the index is {}", len, index)
+            }
+            Panic::Slice { start, end, .. } if start > end => {
+                write!(f, "slice out of bounds: {}..{} starts after it ends", start, end)
+            }
+            Panic::Slice { start, end, len } => {
+                write!(f, "slice out of bounds: the len is {} but the slice is {}..{}", len, start, end)
+            }
+            Panic::DivideByZero => write!(f, "integer divided by zero"),
+        }
+    }
+}
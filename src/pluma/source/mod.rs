@@ -0,0 +1,223 @@
+use std::fmt;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::Read;
+use std::ops::Deref;
+
+use nom::IResult;
+
+use crate::pluma::token::{NomTrait, PlumaError, Token, TokenKind, Tokens};
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone, Hash, Default)]
+pub struct FileId(pub usize);
+
+/// Byte range of a node inside one source file.
+#[derive(Debug, Eq, PartialEq, Copy, Clone, Hash, Default)]
+pub struct Span {
+    pub file: FileId,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(file: FileId, start: usize, end: usize) -> Self {
+        Span { file, start, end }
+    }
+    pub fn join(self, other: Span) -> Span {
+        Span {
+            file: self.file,
+            start: self.start.min(other.start),
+            end: self.end.max(other.end),
+        }
+    }
+    // newline and comment tokens around a node are not part of it
+    pub fn of(tokens: Tokens) -> Span {
+        let mut used = tokens.iter()
+            .filter(|tk| !matches!(tk.kind, TokenKind::Newline | TokenKind::Comment(_) | TokenKind::DocComment(_)));
+        match used.next() {
+            Some(first) => {
+                let last = used.next_back().unwrap_or(first);
+                first.span.join(last.span)
+            }
+            None => Span::default(),
+        }
+    }
+}
+
+/// node and where it is, equal by the node only like `Identifier`
+#[derive(Debug, Clone)]
+pub struct Spanned<T> {
+    pub node: T,
+    pub span: Span,
+}
+
+impl<T> Spanned<T> {
+    pub fn new(node: T, span: Span) -> Self {
+        Spanned { node, span }
+    }
+    /// another node at the same place
+    pub fn map<U>(&self, f: impl FnOnce(&T) -> U) -> Spanned<U> {
+        Spanned { node: f(&self.node), span: self.span }
+    }
+}
+
+impl<T: PartialEq> PartialEq for Spanned<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.node == other.node
+    }
+}
+
+impl<T: Eq> Eq for Spanned<T> {}
+
+impl<T: Hash> Hash for Spanned<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.node.hash(state)
+    }
+}
+
+impl<T> Deref for Spanned<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.node
+    }
+}
+
+impl<'t, T: NomTrait<'t>> NomTrait<'t> for Spanned<T> {
+    fn nom(i: Tokens<'t>) -> IResult<Tokens<'t>, Self, PlumaError> {
+        let (left, node) = T::nom(i)?;
+        Ok((left, Spanned { node, span: Span::of(&i[..i.len() - left.len()]) }))
+    }
+}
+
+/// 1-based line and column, column counts chars not bytes.
+#[derive(Debug, Eq, PartialEq, Copy, Clone, Hash)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct File {
+    id: FileId,
+    name: String,
+    text: String,
+    lines: Vec<usize>,
+}
+
+impl File {
+    pub fn new(id: FileId, name: String, text: String) -> Self {
+        let lines = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        File { id, name, text, lines }
+    }
+    pub fn id(&self) -> FileId {
+        self.id
+    }
+    pub fn name(&self) -> &str {
+        self.name.as_str()
+    }
+    pub fn text(&self) -> &str {
+        self.text.as_str()
+    }
+    pub fn location(&self, offset: usize) -> Location {
+        let offset = offset.min(self.text.len());
+        let line = match self.lines.binary_search(&offset) {
+            Ok(line) => line,
+            Err(next) => next - 1,
+        };
+        let column = self.text[self.lines[line]..offset].chars().count() + 1;
+        Location { line: line + 1, column }
+    }
+    pub fn tokenize(&self) -> Result<Vec<Token>, PlumaError> {
+        Token::tokenize_file(self.id, self.text.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    files: Vec<File>,
+}
+
+impl SourceMap {
+    pub fn add(&mut self, name: String, text: String) -> FileId {
+        let id = FileId(self.files.len());
+        self.files.push(File::new(id, name, text));
+        id
+    }
+    pub fn open(&mut self, path: &str) -> Result<FileId, PlumaError> {
+        let mut text = String::new();
+        fs::File::open(path)
+            .and_then(|mut f| f.read_to_string(&mut text))
+            .map_err(PlumaError::IOError)?;
+        Ok(self.add(String::from(path), text))
+    }
+    pub fn get(&self, id: FileId) -> &File {
+        &self.files[id.0]
+    }
+    /// `file:line:col` of the span start
+    pub fn describe(&self, span: Span) -> String {
+        let file = self.get(span.file);
+        format!("{}:{}", file.name(), file.location(span.start))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pluma::ir::block::{Block, Expression};
+    use crate::pluma::ir::define::Define;
+    use crate::pluma::token::parse_str;
+
+    fn at(line: usize, column: usize) -> Location {
+        Location { line, column }
+    }
+
+    #[test]
+    fn locations() {
+        let file = File::new(FileId(0), "a.pluma".to_string(), "ab\n\nλx y\n".to_string());
+        assert_eq!(file.location(0), at(1, 1));
+        assert_eq!(file.location(2), at(1, 3));
+        assert_eq!(file.location(3), at(2, 1));
+        assert_eq!(file.location(4), at(3, 1));
+        // `λ` is two bytes and one column
+        assert_eq!(file.location(7), at(3, 3));
+        assert_eq!(file.location(100), at(4, 1));
+        let mut sources = SourceMap::default();
+        sources.add("a.pluma".to_string(), "x\n  y".to_string());
+        let b = sources.add("b.pluma".to_string(), "\n\n  z".to_string());
+        assert_eq!(sources.describe(Span::new(b, 4, 5)), "b.pluma:3:3");
+    }
+
+    #[test]
+    fn node_spans() {
+        let src = "{\n    var a = f(1, 2)\n    a.b + 1 // x\n}";
+        let text = |span: Span| &src[span.start..span.end];
+        let block = parse_str::<Block>(src).unwrap();
+        assert_eq!(text(block.span()), src);
+        let statements = block.statements().iter().map(|x| text(x.span())).collect::<Vec<_>>();
+        assert_eq!(statements, vec!["var a = f(1, 2)", "a.b + 1"]);
+        match block.value() {
+            Some(Expression::Binary(_, lhs, rhs, _)) => assert_eq!((text(lhs.span()), text(rhs.span())), ("a.b", "1")),
+            x => panic!("{:?}", x),
+        }
+        let src = "fn f(var a [3]i32) -> i32 { 1 }";
+        let text = |span: Span| &src[span.start..span.end];
+        let define = parse_str::<Define>(src).unwrap();
+        assert_eq!(text(define.span()), src);
+        match define {
+            Define::Function(f) => {
+                assert_eq!(text(f.declare().params[0].declare().span), "[3]i32");
+                assert_eq!(text(f.declare().ret.span), "i32");
+            }
+            x => panic!("{:?}", x),
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use std::collections::{HashMap, HashSet};
+
+use unicode_security::{MixedScript, skeleton};
+
+use crate::pluma::source::Span;
+use crate::pluma::token::{Identifier, TokenKind, Tokens};
+
+/// Suspicious source that still compiles.
+#[derive(Debug, Clone)]
+pub enum Warning {
+    /// letters of several scripts in one name, like latin `a` with cyrillic `р`
+    MixedScript(Identifier),
+    /// different names that render alike, the later one first
+    Confusable(Identifier, Identifier),
+}
+
+impl Warning {
+    pub fn span(&self) -> Span {
+        match self {
+            Warning::MixedScript(ident) => ident.span(),
+            Warning::Confusable(ident, _) => ident.span(),
+        }
+    }
+}
+
+/// UTS #39 checks over every identifier in a token stream, each name is reported once.
+pub fn identifier_warnings(tokens: Tokens) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+    let mut seen = HashSet::new();
+    let mut skeletons: HashMap<String, &Identifier> = HashMap::new();
+    for tk in tokens {
+        let ident = match &tk.kind {
+            TokenKind::Identifier(ident) => ident,
+            _ => continue,
+        };
+        if !seen.insert(ident.name()) {
+            continue;
+        }
+        if !ident.name().is_single_script() {
+            warnings.push(Warning::MixedScript(ident.clone()));
+        }
+        let key = skeleton(ident.name()).collect::<String>();
+        match skeletons.get(&key) {
+            Some(other) => warnings.push(Warning::Confusable(ident.clone(), (*other).clone())),
+            None => {
+                skeletons.insert(key, ident);
+            }
+        }
+    }
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pluma::token::Token;
+
+    fn warnings(src: &str) -> Vec<Warning> {
+        identifier_warnings(&Token::tokenize_str(src).unwrap())
+    }
+
+    #[test]
+    fn mixed_script() {
+        // cyrillic `а` in a latin name, reported once however often it is used
+        match warnings("p\u{430}y + p\u{430}y + x").as_slice() {
+            [Warning::MixedScript(x)] => assert_eq!((x.name(), x.span().start), ("p\u{430}y", 0)),
+            warnings => panic!("{:?}", warnings),
+        }
+        assert!(warnings("名前 + δ + x").is_empty());
+    }
+
+    #[test]
+    fn confusable() {
+        // all cyrillic, so only confusable with the latin one before it
+        let src = "scope = \u{455}\u{441}\u{43e}\u{440}\u{435}";
+        match warnings(src).as_slice() {
+            [Warning::Confusable(later, first)] => {
+                assert_eq!(first.name(), "scope");
+                assert_eq!(later.span().start, src.find('\u{455}').unwrap());
+            }
+            warnings => panic!("{:?}", warnings),
+        }
+    }
+}
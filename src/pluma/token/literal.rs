@@ -0,0 +1,397 @@
+use nom::IResult;
+use nom::bytes::complete::{tag, take_while, take_while1, take_while_m_n};
+use nom::character::complete::one_of;
+use nom::combinator::{opt, recognize};
+use nom::sequence::{pair, tuple};
+use nom::lib::std::str::FromStr;
+
+use crate::pluma::ir::types::Primitive;
+use crate::pluma::token::{Identifier, Keyword, PlumaError};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Numeric {
+    Integer(u64, Option<Primitive>),
+    Float(f64, Option<Primitive>),
+}
+
+fn digits(radix: u32) -> impl Fn(&str) -> IResult<&str, &str, PlumaError> {
+    move |i| {
+        recognize(pair(
+            take_while1(move |x: char| x.is_digit(radix)),
+            take_while(move |x: char| x == '_' || x.is_digit(radix)),
+        ))(i)
+    }
+}
+
+fn suffix(i: &str) -> IResult<&str, Option<Primitive>, PlumaError> {
+    match Identifier::word(i) {
+        Ok((left, word)) => {
+            match Keyword::from_str(word).ok().and_then(Primitive::from_keyword) {
+                Some(Primitive::Char) | Some(Primitive::Bool) | None => Err(nom::Err::Failure(PlumaError::InvalidSuffix(String::from(word)))),
+                Some(p) => Ok((left, Some(p))),
+            }
+        }
+        Err(_) => Ok((i, None)),
+    }
+}
+
+/// whether an integer literal of `value` is in the range of `p`, `negative` when it has a `-` before it
+pub fn fits(value: u64, negative: bool, p: Primitive) -> bool {
+    match p.bits() {
+        _ if negative && !p.is_signed() => value == 0,
+        64 if !p.is_signed() => true,
+        bits if p.is_signed() && negative => value <= 1u64 << (bits - 1),
+        bits if p.is_signed() => value < 1u64 << (bits - 1),
+        bits => value < 1u64 << bits,
+    }
+}
+
+fn integer(text: &str, radix: u32, suffix: Option<Primitive>) -> Result<Numeric, PlumaError> {
+    let value = u64::from_str_radix(text.replace('_', "").as_str(), radix)
+        .map_err(|_| PlumaError::NumericOutOfRange(suffix))?;
+    match suffix {
+        Some(p) if p.is_float() => {
+            if radix == 10 {
+                float(text, suffix)
+            } else {
+                Err(PlumaError::InvalidSuffix(format!("{:?}", p).to_lowercase()))
+            }
+        }
+        // the `-` of `-128i8` is a token of its own, the checker rejects `128i8` without one
+        Some(p) if !fits(value, p.is_signed(), p) => Err(PlumaError::NumericOutOfRange(suffix)),
+        _ => Ok(Numeric::Integer(value, suffix)),
+    }
+}
+
+fn float(text: &str, suffix: Option<Primitive>) -> Result<Numeric, PlumaError> {
+    let value = f64::from_str(text.replace('_', "").as_str())
+        .map_err(|_| PlumaError::InvalidNumeric)?;
+    let finite = match suffix {
+        Some(Primitive::F32) => (value as f32).is_finite(),
+        Some(Primitive::F64) | None => value.is_finite(),
+        Some(p) => return Err(PlumaError::InvalidSuffix(format!("{:?}", p).to_lowercase())),
+    };
+    if finite {
+        Ok(Numeric::Float(value, suffix))
+    } else {
+        Err(PlumaError::NumericOutOfRange(suffix))
+    }
+}
+
+/// `42`, `0xff_ffu32`, `0o17`, `0b1010`, `3.5f32`, `1e-9`, `42size`
+///
+/// Suffix is any primitive type name, a malformed literal fails instead of splitting into tokens.
+pub fn numeric(src: &str) -> IResult<&str, Numeric, PlumaError> {
+    let radix = match src.get(..2) {
+        Some("0x") => 16,
+        Some("0o") => 8,
+        Some("0b") => 2,
+        _ => 10,
+    };
+    if radix != 10 {
+        let (left, text) = digits(radix)(&src[2..])
+            .map_err(|_| nom::Err::Failure(PlumaError::InvalidNumeric))?;
+        // `0b102` is a bad digit, not `0b10` and `2`
+        if left.starts_with(|x: char| x.is_ascii_digit()) {
+            return Err(nom::Err::Failure(PlumaError::InvalidNumeric));
+        }
+        let (left, suffix) = suffix(left)?;
+        return integer(text, radix, suffix)
+            .map(|x| (left, x))
+            .map_err(nom::Err::Failure);
+    }
+    let (left, (_, fraction, exponent)) = tuple((
+        digits(10),
+        opt(pair(tag("."), digits(10))),
+        opt(tuple((one_of("eE"), opt(one_of("+-")), digits(10)))),
+    ))(src)?;
+    let text = &src[..src.len() - left.len()];
+    let (left, suffix) = suffix(left)?;
+    let res = if fraction.is_some() || exponent.is_some() {
+        float(text, suffix)
+    } else {
+        integer(text, 10, suffix)
+    };
+    res.map(|x| (left, x)).map_err(nom::Err::Failure)
+}
+
+fn unicode(i: &str) -> IResult<&str, char, PlumaError> {
+    let invalid = || nom::Err::Failure(PlumaError::InvalidEscape(String::from("u")));
+    let (left, (_, hex, _)) = tuple((tag("{"), take_while_m_n(1, 6, |x: char| x.is_ascii_hexdigit()), tag("}")))(i)
+        .map_err(|_: nom::Err<PlumaError>| invalid())?;
+    u32::from_str_radix(hex, 16)
+        .ok()
+        .and_then(std::char::from_u32)
+        .map(|c| (left, c))
+        .ok_or_else(invalid)
+}
+
+/// one escape sequence starting at the backslash
+///
+/// `\n`, `\t`, `\r`, `\0`, `\\`, `\"`, `\'` or `\u{AC00}` with up to six hex digits.
+pub fn escape(i: &str) -> IResult<&str, char, PlumaError> {
+    let (i, _) = tag("\\")(i)?;
+    let c = match i.chars().next() {
+        Some(c) => c,
+        None => return Err(nom::Err::Failure(PlumaError::UnterminatedLiteral)),
+    };
+    let left = &i[c.len_utf8()..];
+    match c {
+        'n' => Ok((left, '\n')),
+        't' => Ok((left, '\t')),
+        'r' => Ok((left, '\r')),
+        '0' => Ok((left, '\0')),
+        '\\' => Ok((left, '\\')),
+        '"' => Ok((left, '"')),
+        '\'' => Ok((left, '\'')),
+        'u' => unicode(left),
+        c => Err(nom::Err::Failure(PlumaError::InvalidEscape(c.to_string()))),
+    }
+}
+
+// r"..." or r#"..."#, nothing inside is escaped and the closing quote needs as many `#`
+fn raw_string(src: &str) -> IResult<&str, String, PlumaError> {
+    let (i, (_, hashes, _)) = tuple((tag("r"), take_while(|x| x == '#'), tag("\"")))(src)?;
+    let close = format!("\"{}", hashes);
+    match i.find(close.as_str()) {
+        Some(end) => Ok((&i[end + close.len()..], i[..end].replace("\r\n", "\n"))),
+        None => Err(nom::Err::Failure(PlumaError::UnterminatedLiteral)),
+    }
+}
+
+/// `"Hello, World\n"`, strings may span several lines and a `\r\n` in them reads as `\n`
+pub fn string(src: &str) -> IResult<&str, String, PlumaError> {
+    if src.starts_with('r') {
+        return raw_string(src);
+    }
+    let (mut i, _) = tag("\"")(src)?;
+    let mut out = String::new();
+    loop {
+        match i.chars().next() {
+            None => return Err(nom::Err::Failure(PlumaError::UnterminatedLiteral)),
+            Some('"') => return Ok((&i[1..], out)),
+            Some('\\') => {
+                let (left, c) = escape(i)?;
+                out.push(c);
+                i = left;
+            }
+            Some('\r') if i[1..].starts_with('\n') => i = &i[1..],
+            Some(c) => {
+                out.push(c);
+                i = &i[c.len_utf8()..];
+            }
+        }
+    }
+}
+
+// body of a char literal after the opening quote, up to and including the closing quote
+fn quoted(i: &str) -> IResult<&str, char, PlumaError> {
+    let (left, c) = match i.chars().next() {
+        None | Some('\n') => return Err(nom::Err::Failure(PlumaError::UnterminatedLiteral)),
+        Some('\'') => return Err(nom::Err::Failure(PlumaError::EmptyCharLiteral)),
+        Some('\\') => escape(i)?,
+        Some(c) => (&i[c.len_utf8()..], c),
+    };
+    match left.chars().next() {
+        Some('\'') => Ok((&left[1..], c)),
+        _ => {
+            let line = &left[..left.find('\n').unwrap_or(left.len())];
+            if line.contains('\'') {
+                Err(nom::Err::Failure(PlumaError::MultiCharLiteral))
+            } else {
+                Err(nom::Err::Failure(PlumaError::UnterminatedLiteral))
+            }
+        }
+    }
+}
+
+/// `'a'`, `'\n'` or `'\u{AC00}'`, exactly one unicode scalar
+pub fn character(src: &str) -> IResult<&str, char, PlumaError> {
+    let (i, _) = tag("'")(src)?;
+    quoted(i)
+}
+
+/// `b'x'` or `b'\n'`, only ascii fits in a byte so `\u{...}` is rejected too
+pub fn byte(src: &str) -> IResult<&str, u8, PlumaError> {
+    let (i, _) = tag("b'")(src)?;
+    if i.starts_with("\\u") {
+        return Err(nom::Err::Failure(PlumaError::InvalidEscape(String::from("u"))));
+    }
+    let (left, c) = quoted(i)?;
+    if c.is_ascii() {
+        Ok((left, c as u8))
+    } else {
+        Err(nom::Err::Failure(PlumaError::NonAsciiByte))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lex(src: &str) -> Result<Numeric, PlumaError> {
+        match numeric(src) {
+            Ok((_, x)) => Ok(x),
+            Err(nom::Err::Error(err)) | Err(nom::Err::Failure(err)) => Err(err),
+            Err(nom::Err::Incomplete(need)) => Err(PlumaError::Needed(need)),
+        }
+    }
+
+    fn fails(src: &str) -> PlumaError {
+        lex(src).expect_err(src)
+    }
+
+    #[test]
+    fn integers() {
+        assert_eq!(lex("42").unwrap(), Numeric::Integer(42, None));
+        assert_eq!(lex("1_000_000").unwrap(), Numeric::Integer(1_000_000, None));
+        assert_eq!(lex("0xff_ff").unwrap(), Numeric::Integer(0xffff, None));
+        assert_eq!(lex("0o17").unwrap(), Numeric::Integer(0o17, None));
+        assert_eq!(lex("0b1010").unwrap(), Numeric::Integer(0b1010, None));
+    }
+
+    #[test]
+    fn floats() {
+        assert_eq!(lex("3.5").unwrap(), Numeric::Float(3.5, None));
+        assert_eq!(lex("1e-9").unwrap(), Numeric::Float(1e-9, None));
+        assert_eq!(lex("2.5E3").unwrap(), Numeric::Float(2500.0, None));
+        assert_eq!(lex("1_0.2_5").unwrap(), Numeric::Float(10.25, None));
+    }
+
+    #[test]
+    fn suffixes() {
+        assert_eq!(lex("10u8").unwrap(), Numeric::Integer(10, Some(Primitive::U8)));
+        assert_eq!(lex("3.5f32").unwrap(), Numeric::Float(3.5, Some(Primitive::F32)));
+        assert_eq!(lex("42size").unwrap(), Numeric::Integer(42, Some(Primitive::Size)));
+        assert_eq!(lex("0xffu32").unwrap(), Numeric::Integer(0xff, Some(Primitive::U32)));
+        assert_eq!(lex("2f64").unwrap(), Numeric::Float(2.0, Some(Primitive::F64)));
+        match fails("1bool") {
+            PlumaError::InvalidSuffix(suffix) => assert_eq!(suffix, "bool"),
+            err => panic!("{:?}", err),
+        }
+        match fails("1.5u8") {
+            PlumaError::InvalidSuffix(suffix) => assert_eq!(suffix, "u8"),
+            err => panic!("{:?}", err),
+        }
+    }
+
+    #[test]
+    fn out_of_range() {
+        match fails("256u8") {
+            PlumaError::NumericOutOfRange(Some(Primitive::U8)) => (),
+            err => panic!("{:?}", err),
+        }
+        match fails("129i8") {
+            PlumaError::NumericOutOfRange(Some(Primitive::I8)) => (),
+            err => panic!("{:?}", err),
+        }
+        match fails("18446744073709551616") {
+            PlumaError::NumericOutOfRange(None) => (),
+            err => panic!("{:?}", err),
+        }
+        match fails("1e39f32") {
+            PlumaError::NumericOutOfRange(Some(Primitive::F32)) => (),
+            err => panic!("{:?}", err),
+        }
+        // `-128i8` is `-` and `128i8`, the checker rejects the literal when no `-` is before it
+        assert_eq!(lex("128i8").unwrap(), Numeric::Integer(128, Some(Primitive::I8)));
+        assert_eq!(lex("18446744073709551615u64").unwrap(), Numeric::Integer(u64::MAX, Some(Primitive::U64)));
+    }
+
+    #[test]
+    fn range_with_sign() {
+        assert!(fits(127, false, Primitive::I8));
+        assert!(!fits(128, false, Primitive::I8));
+        assert!(fits(128, true, Primitive::I8));
+        assert!(!fits(129, true, Primitive::I8));
+        assert!(fits(255, false, Primitive::U8));
+        assert!(!fits(256, false, Primitive::U8));
+        assert!(!fits(1, true, Primitive::U8));
+        assert!(fits(0, true, Primitive::U8));
+        assert!(fits(u64::MAX, false, Primitive::U64));
+        assert!(fits(1 << 63, true, Primitive::I64));
+    }
+
+    #[test]
+    fn malformed_does_not_split() {
+        for src in &["0b102", "0o178", "0x", "0b"] {
+            match fails(src) {
+                PlumaError::InvalidNumeric => (),
+                err => panic!("{} {:?}", src, err),
+            }
+        }
+    }
+
+    fn text<T>(res: IResult<&str, T, PlumaError>) -> Result<T, PlumaError> {
+        match res {
+            Ok((_, x)) => Ok(x),
+            Err(nom::Err::Error(err)) | Err(nom::Err::Failure(err)) => Err(err),
+            Err(nom::Err::Incomplete(need)) => Err(PlumaError::Needed(need)),
+        }
+    }
+
+    #[test]
+    fn characters() {
+        assert_eq!(text(character("'a'")).unwrap(), 'a');
+        assert_eq!(text(character("'\\n'")).unwrap(), '\n');
+        assert_eq!(text(character("'\\''")).unwrap(), '\'');
+        assert_eq!(text(character("'\\u{AC00}'")).unwrap(), '\u{AC00}');
+        assert_eq!(text(character("'가'")).unwrap(), '가');
+        match text(character("''")) {
+            Err(PlumaError::EmptyCharLiteral) => (),
+            res => panic!("{:?}", res),
+        }
+        match text(character("'ab'")) {
+            Err(PlumaError::MultiCharLiteral) => (),
+            res => panic!("{:?}", res),
+        }
+        match text(character("'a\n'")) {
+            Err(PlumaError::UnterminatedLiteral) => (),
+            res => panic!("{:?}", res),
+        }
+        match text(character("'\\q'")) {
+            Err(PlumaError::InvalidEscape(c)) => assert_eq!(c, "q"),
+            res => panic!("{:?}", res),
+        }
+        match text(character("'\\u{110000}'")) {
+            Err(PlumaError::InvalidEscape(c)) => assert_eq!(c, "u"),
+            res => panic!("{:?}", res),
+        }
+    }
+
+    #[test]
+    fn strings() {
+        assert_eq!(text(string("\"Hello, World\\n\"")).unwrap(), "Hello, World\n");
+        assert_eq!(text(string("\"a\r\nb\"")).unwrap(), "a\nb");
+        assert_eq!(text(string("r\"\\n\"")).unwrap(), "\\n");
+        assert_eq!(text(string("r#\"say \"hi\"\"#")).unwrap(), "say \"hi\"");
+        assert_eq!(string("\"a\" + b").map(|(left, _)| left).ok(), Some(" + b"));
+        for src in &["\"abc", "r#\"abc\""] {
+            match text(string(src)) {
+                Err(PlumaError::UnterminatedLiteral) => (),
+                res => panic!("{} {:?}", src, res),
+            }
+        }
+    }
+
+    #[test]
+    fn bytes() {
+        assert_eq!(text(byte("b'x'")).unwrap(), b'x');
+        assert_eq!(text(byte("b'\\n'")).unwrap(), b'\n');
+        match text(byte("b'가'")) {
+            Err(PlumaError::NonAsciiByte) => (),
+            res => panic!("{:?}", res),
+        }
+        // even when the scalar is ascii
+        match text(byte("b'\\u{41}'")) {
+            Err(PlumaError::InvalidEscape(c)) => assert_eq!(c, "u"),
+            res => panic!("{:?}", res),
+        }
+    }
+
+    #[test]
+    fn rest_is_left() {
+        assert_eq!(numeric("42 + 1").map(|(left, _)| left).ok(), Some(" + 1"));
+        assert_eq!(numeric("1..2").map(|(left, _)| left).ok(), Some("..2"));
+    }
+}
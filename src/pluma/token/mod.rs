@@ -1,34 +1,82 @@
 use nom::{IResult, Needed};
 use nom::branch::alt;
-use nom::combinator::{map, opt, recognize};
-use nom::bytes::complete::{tag, take_till1, take_till, take_while1, take_while};
+use nom::combinator::{map, opt, recognize, not};
+use nom::bytes::complete::{tag, take_till, take_while1, take_while};
 use nom::error::{ErrorKind, context};
-use nom::character::complete::one_of;
 use nom::lib::std::str::FromStr;
-use nom::sequence::pair;
-use nom::multi::separated_list;
+use nom::sequence::{pair, preceded};
+use nom::multi::{separated_nonempty_list, many0, many1};
+use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
+use unicode_normalization::UnicodeNormalization;
+use unicode_xid::UnicodeXID;
+
+use crate::pluma::source::{FileId, Span};
+use crate::pluma::ir::types::Primitive;
+
+pub mod literal;
+pub mod lint;
+
+use self::literal::Numeric;
 
 #[derive(Debug)]
-pub enum PlumaError<'t> {
+pub enum PlumaError {
     Unknown,
     Needed(Needed),
-    Left(&'t str),
+    Left(Token),
+    IOError(std::io::Error),
     //
-    NoWhitespace,
+    NotToken(Span),
+    InvalidToken(Span, Box<PlumaError>),
+    InvalidNumeric,
+    InvalidSuffix(String),
+    NumericOutOfRange(Option<Primitive>),
+    UnterminatedLiteral,
+    EmptyCharLiteral,
+    MultiCharLiteral,
+    NonAsciiByte,
+    InvalidEscape(String),
+    UnterminatedComment,
     NotKeyword,
     NotExpectedKeyword(Keyword),
     NotControl,
     NotExpectedControl(Control),
+    NotIdentifier,
+    NotSeparator,
+    NotLiteral,
+    NotDocComment,
+    NotOperator,
+    NotExpression,
+    UntypedDeclare(Identifier),
+    /// a part which had to follow did not, at the first token of it
+    InvalidSyntax(Span, Box<PlumaError>),
     //
     NotPrimitive,
+    /// `N` of `[N]T` has to be an integer literal or the name of a `const`
+    NotArrayLength,
+    ReservedWord(Keyword),
+}
+
+impl PlumaError {
+    /// where in the source it is, `None` for errors with no place like `IOError`
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            PlumaError::Left(tk) => Some(tk.span),
+            PlumaError::UntypedDeclare(x) => Some(x.span()),
+            PlumaError::NotToken(span)
+            | PlumaError::InvalidToken(span, _)
+            | PlumaError::InvalidSyntax(span, _) => Some(*span),
+            _ => None,
+        }
+    }
 }
 
-impl<'t> nom::error::ParseError<&'t str> for PlumaError<'t> {
-    fn from_error_kind(input: &'t str, kind: ErrorKind) -> Self {
+impl<I> nom::error::ParseError<I> for PlumaError {
+    fn from_error_kind(_input: I, _kind: ErrorKind) -> Self {
         PlumaError::Unknown
     }
 
-    fn append(input: &'t str, kind: ErrorKind, other: Self) -> Self {
+    fn append(_input: I, _kind: ErrorKind, other: Self) -> Self {
         other
     }
 
@@ -36,7 +84,7 @@ impl<'t> nom::error::ParseError<&'t str> for PlumaError<'t> {
     //     unimplemented!()
     // }
 
-    fn add_context(_input: &'t str, _ctx: &'static str, other: Self) -> Self {
+    fn add_context(_input: I, _ctx: &'static str, other: Self) -> Self {
         match _ctx {
             "NotKeyword" => PlumaError::NotKeyword,
             "NotControl" => PlumaError::NotControl,
@@ -64,6 +112,12 @@ pub enum Keyword {
     Ref,
     For,
     In,
+    Break,
+    Continue,
+    Return,
+    True,
+    False,
+    Bool,
     I8,
     I16,
     I32,
@@ -76,54 +130,77 @@ pub enum Keyword {
     F64,
     Ptr,
     Size,
+    Char,
     Str,
 }
 
+impl FromStr for Keyword {
+    type Err = PlumaError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "if" => Ok(Keyword::If),
+            "else" => Ok(Keyword::Else),
+            "is" => Ok(Keyword::Is),
+            "in" => Ok(Keyword::In),
+            "with" => Ok(Keyword::With),
+            "and" => Ok(Keyword::And),
+            "as" => Ok(Keyword::As),
+            "or" => Ok(Keyword::Or),
+            "fn" => Ok(Keyword::Fn),
+            "struct" => Ok(Keyword::Struct),
+            "interface" => Ok(Keyword::Interface),
+            "import" => Ok(Keyword::Import),
+            "package" => Ok(Keyword::Package),
+            "var" => Ok(Keyword::Var),
+            "const" => Ok(Keyword::Const),
+            "ref" => Ok(Keyword::Ref),
+            "for" => Ok(Keyword::For),
+            "break" => Ok(Keyword::Break),
+            "continue" => Ok(Keyword::Continue),
+            "return" => Ok(Keyword::Return),
+            "true" => Ok(Keyword::True),
+            "false" => Ok(Keyword::False),
+            //
+            "bool" => Ok(Keyword::Bool),
+            "i8" => Ok(Keyword::I8),
+            "i16" => Ok(Keyword::I16),
+            "i32" => Ok(Keyword::I32),
+            "i64" => Ok(Keyword::I64),
+            "u8" => Ok(Keyword::U8),
+            "u16" => Ok(Keyword::U16),
+            "u32" => Ok(Keyword::U32),
+            "u64" => Ok(Keyword::U64),
+            "f32" => Ok(Keyword::F32),
+            "f64" => Ok(Keyword::F64),
+            "size" => Ok(Keyword::Size),
+            "ptr" => Ok(Keyword::Ptr),
+            "char" => Ok(Keyword::Char),
+            "str" => Ok(Keyword::Str),
+            _ => Err(PlumaError::NotKeyword),
+        }
+    }
+}
+
 impl Keyword {
-    pub fn any_matcher() -> impl Fn(&str) -> IResult<&str, Keyword, PlumaError> {
+    // keyword must be a whole word, `index` is identifier not `in` + `dex`
+    pub fn lex(i: &str) -> IResult<&str, Keyword, PlumaError> {
+        let (left, word) = Identifier::word(i).map_err(|_| nom::Err::Error(PlumaError::NotKeyword))?;
+        match Keyword::from_str(word) {
+            Ok(key) => Ok((left, key)),
+            Err(err) => Err(nom::Err::Error(err)),
+        }
+    }
+    pub fn any_matcher() -> impl Fn(Tokens) -> IResult<Tokens, Keyword, PlumaError> {
         move |i| {
-            context(
-                "NotKeyword",
-                alt((
-                    alt((
-                        map(tag("if"), |_| Keyword::If),
-                        map(tag("else"), |_| Keyword::Else),
-                        map(tag("is"), |_| Keyword::Is),
-                        map(tag("in"), |_| Keyword::In),
-                        map(tag("with"), |_| Keyword::With),
-                        map(tag("and"), |_| Keyword::And),
-                        map(tag("as"), |_| Keyword::As),
-                        map(tag("or"), |_| Keyword::Or),
-                        map(tag("fn"), |_| Keyword::Fn),
-                        map(tag("struct"), |_| Keyword::Struct),
-                        map(tag("interface"), |_| Keyword::Interface),
-                        map(tag("import"), |_| Keyword::Import),
-                        map(tag("package"), |_| Keyword::Package),
-                        map(tag("var"), |_| Keyword::Var),
-                        map(tag("const"), |_| Keyword::Const),
-                        map(tag("ref"), |_| Keyword::Ref),
-                        map(tag("for"), |_| Keyword::For),
-                    )),
-                    alt((
-                        map(tag("i8"), |_| Keyword::I8),
-                        map(tag("i16"), |_| Keyword::I16),
-                        map(tag("i32"), |_| Keyword::I32),
-                        map(tag("i64"), |_| Keyword::I64),
-                        map(tag("u8"), |_| Keyword::U8),
-                        map(tag("u16"), |_| Keyword::U16),
-                        map(tag("u32"), |_| Keyword::U32),
-                        map(tag("u64"), |_| Keyword::U64),
-                        map(tag("f32"), |_| Keyword::F32),
-                        map(tag("f64"), |_| Keyword::F64),
-                        map(tag("size"), |_| Keyword::Size),
-                        map(tag("ptr"), |_| Keyword::Ptr),
-                        map(tag("str"), |_| Keyword::Str),
-                    )),
-                )),
-            )(i)
+            let (left, tk) = any_token(i)?;
+            match tk.kind {
+                TokenKind::Keyword(key) => Ok((left, key)),
+                _ => Err(nom::Err::Error(PlumaError::NotKeyword)),
+            }
         }
     }
-    pub fn matcher(self) -> impl Fn(&str) -> IResult<&str, (), PlumaError> {
+    pub fn matcher(self) -> impl Fn(Tokens) -> IResult<Tokens, (), PlumaError> {
         move |i| {
             let (left, key) = Self::any_matcher()(i)?;
             if key == self {
@@ -135,6 +212,12 @@ impl Keyword {
     }
 }
 
+/// `<` and `>` are lexed only as `GenericStart` and `GenericEnd`, which one they mean is up to the parser:
+/// in a type they always open and close generics, in an expression they compare unless
+/// `<` types `>` is directly followed by `(` as in `max<i32>(1, 2)`.
+///
+/// `>` is never glued by the lexer so `Box<Box<T>>` closes twice,
+/// `>>` and `>=` are two adjacent `>` tokens which `any_matcher` and `matcher` glue back.
 #[derive(Debug, Eq, PartialEq, Copy, Clone, Hash)]
 pub enum Control {
     Plus,
@@ -157,6 +240,22 @@ pub enum Control {
     Meta,
     //
     Arrow,
+    FatArrow,
+    EqualEqual,
+    NotEqual,
+    LessEqual,
+    GreaterEqual,
+    AndAnd,
+    OrOr,
+    ShiftLeft,
+    ShiftRight,
+    PlusEqual,
+    MinusEqual,
+    MulEqual,
+    SlashEqual,
+    ModEqual,
+    DotDot,
+    Path,
     //
     GroupStart,
     GroupEnd,
@@ -169,47 +268,105 @@ pub enum Control {
 }
 
 impl Control {
-    pub fn any_matcher() -> impl Fn(&str) -> IResult<&str, Control, PlumaError> {
-        move |i| {
-            context(
-                "NotControl",
+    pub const LESS: Control = Control::GenericStart;
+    pub const GREATER: Control = Control::GenericEnd;
+
+    // longest first, `>` followed by `>` or `=` is glued by the parser
+    pub fn lex(i: &str) -> IResult<&str, Control, PlumaError> {
+        context(
+            "NotControl",
+            alt((
                 alt((
-                    alt((
-                        map(tag("->"), |_| Control::Arrow),
-                        map(tag("+"), |_| Control::Plus),
-                        map(tag("="), |_| Control::Equal),
-                        map(tag("-"), |_| Control::Minus),
-                        map(tag("!"), |_| Control::Not),
-                        map(tag("#"), |_| Control::Sharp),
-                        map(tag("%"), |_| Control::Mod),
-                        map(tag("^"), |_| Control::Pow),
-                        map(tag("&"), |_| Control::And),
-                        map(tag("*"), |_| Control::Mul),
-                        map(tag(";"), |_| Control::Semicolon),
-                        map(tag(","), |_| Control::Comma),
-                        map(tag("."), |_| Control::Dot),
-                        map(tag("?"), |_| Control::Question),
-                        map(tag("/"), |_| Control::Slash),
-                        map(tag("|"), |_| Control::Or),
-                        map(tag(":"), |_| Control::Meta),
-                    )),
-                    alt((
-                        map(tag("("), |_| Control::GroupStart),
-                        map(tag(")"), |_| Control::GroupEnd),
-                        map(tag("{"), |_| Control::BlockStart),
-                        map(tag("}"), |_| Control::BlockEnd),
-                        map(tag("["), |_| Control::ArrayStart),
-                        map(tag("]"), |_| Control::ArrayEnd),
-                        map(tag("<"), |_| Control::GenericStart),
-                        map(tag(">"), |_| Control::GenericEnd),
-                    ))
+                    map(tag("->"), |_| Control::Arrow),
+                    map(tag("=>"), |_| Control::FatArrow),
+                    map(tag("=="), |_| Control::EqualEqual),
+                    map(tag("!="), |_| Control::NotEqual),
+                    map(tag("<="), |_| Control::LessEqual),
+                    map(tag("&&"), |_| Control::AndAnd),
+                    map(tag("||"), |_| Control::OrOr),
+                    map(tag("<<"), |_| Control::ShiftLeft),
+                    map(tag("+="), |_| Control::PlusEqual),
+                    map(tag("-="), |_| Control::MinusEqual),
+                    map(tag("*="), |_| Control::MulEqual),
+                    map(tag("/="), |_| Control::SlashEqual),
+                    map(tag("%="), |_| Control::ModEqual),
+                    map(tag(".."), |_| Control::DotDot),
+                    map(tag("::"), |_| Control::Path),
                 )),
-            )(i)
+                alt((
+                    map(tag("+"), |_| Control::Plus),
+                    map(tag("="), |_| Control::Equal),
+                    map(tag("-"), |_| Control::Minus),
+                    map(tag("!"), |_| Control::Not),
+                    map(tag("#"), |_| Control::Sharp),
+                    map(tag("%"), |_| Control::Mod),
+                    map(tag("^"), |_| Control::Pow),
+                    map(tag("&"), |_| Control::And),
+                    map(tag("*"), |_| Control::Mul),
+                    map(tag(";"), |_| Control::Semicolon),
+                    map(tag(","), |_| Control::Comma),
+                    map(tag("."), |_| Control::Dot),
+                    map(tag("?"), |_| Control::Question),
+                    map(tag("/"), |_| Control::Slash),
+                    map(tag("|"), |_| Control::Or),
+                    map(tag(":"), |_| Control::Meta),
+                )),
+                alt((
+                    map(tag("("), |_| Control::GroupStart),
+                    map(tag(")"), |_| Control::GroupEnd),
+                    map(tag("{"), |_| Control::BlockStart),
+                    map(tag("}"), |_| Control::BlockEnd),
+                    map(tag("["), |_| Control::ArrayStart),
+                    map(tag("]"), |_| Control::ArrayEnd),
+                    map(tag("<"), |_| Control::GenericStart),
+                    map(tag(">"), |_| Control::GenericEnd),
+                ))
+            )),
+        )(i)
+    }
+    // operators made of a `>` and the token right after it
+    fn glued(self) -> Option<(Control, Control)> {
+        match self {
+            Control::ShiftRight => Some((Control::GenericEnd, Control::GenericEnd)),
+            Control::GreaterEqual => Some((Control::GenericEnd, Control::Equal)),
+            _ => None,
         }
     }
-    pub fn matcher(self) -> impl Fn(&str) -> IResult<&str, (), PlumaError> {
+    fn single(i: Tokens) -> IResult<Tokens, Control, PlumaError> {
+        let (left, tk) = any_token(i)?;
+        match tk.kind {
+            TokenKind::Control(ctl) => Ok((left, ctl)),
+            _ => Err(nom::Err::Error(PlumaError::NotControl)),
+        }
+    }
+    fn joint(i: Tokens) -> IResult<Tokens, Control, PlumaError> {
+        let (left, first) = any_token(i)?;
+        match (&first.kind, left.split_first()) {
+            (TokenKind::Control(a), Some((Token { kind: TokenKind::Control(b), span }, left))) if first.span.end == span.start => {
+                [Control::ShiftRight, Control::GreaterEqual].iter()
+                    .find(|ctl| ctl.glued() == Some((*a, *b)))
+                    .map(|ctl| (left, *ctl))
+                    .ok_or(nom::Err::Error(PlumaError::NotControl))
+            }
+            _ => Err(nom::Err::Error(PlumaError::NotControl)),
+        }
+    }
+    /// longest control at the input, gluing `>>` and `>=`
+    pub fn any_matcher() -> impl Fn(Tokens) -> IResult<Tokens, Control, PlumaError> {
         move |i| {
-            let (left, ctl) = Self::any_matcher()(i)?;
+            alt((
+                Self::joint,
+                Self::single,
+            ))(i)
+        }
+    }
+    /// exactly this control, `GenericEnd` takes a single `>` even when another one follows
+    pub fn matcher(self) -> impl Fn(Tokens) -> IResult<Tokens, (), PlumaError> {
+        move |i| {
+            let (left, ctl) = match self.glued() {
+                Some(_) => Self::joint(i),
+                None => Self::single(i),
+            }.map_err(|_| nom::Err::Error(PlumaError::NotExpectedControl(self)))?;
             if ctl == self {
                 Ok((left, ()))
             } else {
@@ -220,8 +377,8 @@ impl Control {
 }
 
 pub trait NomTrait<'t>: Sized {
-    fn nom(i: &'t str) -> IResult<&'t str, Self, PlumaError<'t>>;
-    fn parse(i: &'t str) -> Result<Self, PlumaError<'t>> {
+    fn nom(i: Tokens<'t>) -> IResult<Tokens<'t>, Self, PlumaError>;
+    fn parse(i: Tokens<'t>) -> Result<Self, PlumaError> {
         Self::nom(i)
             .map_err(|x| {
                 match x {
@@ -230,174 +387,649 @@ pub trait NomTrait<'t>: Sized {
                 }
             })
             .and_then(|(left, nt)| {
-                if left.is_empty() {
-                    Ok(nt)
-                } else {
-                    Err(PlumaError::Left(left))
+                // trailing newlines are fine, anything after them is not
+                let left = many0(newline)(left).map_or(left, |(left, _)| left);
+                match any_token(left) {
+                    Ok((_, tk)) => Err(PlumaError::Left(tk.clone())),
+                    Err(_) => Ok(nt),
                 }
             })
     }
 }
 
-pub fn opt_ws(i: &str) -> IResult<&str, (), PlumaError> {
-    map(opt(ws), |_| ())(i)
+pub fn parse_str<T>(src: &str) -> Result<T, PlumaError> where T: for<'a> NomTrait<'a> {
+    let tokens = Token::tokenize_str(src)?;
+    T::parse(&tokens)
+}
+
+pub type Tokens<'t> = &'t [Token];
+
+pub fn spanned<'t, O, F>(f: F) -> impl Fn(Tokens<'t>) -> IResult<Tokens<'t>, (O, Span), PlumaError>
+    where F: Fn(Tokens<'t>) -> IResult<Tokens<'t>, O, PlumaError> {
+    move |i| {
+        let (left, o) = f(i)?;
+        Ok((left, (o, Span::of(&i[..i.len() - left.len()]))))
+    }
+}
+
+/// `f` has to match from here on, so its error is a failure
+///
+/// An error with no place of its own is put at the first token `f` saw.
+pub fn commit<'t, O, F>(f: F) -> impl Fn(Tokens<'t>) -> IResult<Tokens<'t>, O, PlumaError>
+    where F: Fn(Tokens<'t>) -> IResult<Tokens<'t>, O, PlumaError> {
+    move |i| {
+        f(i).map_err(|err| match err {
+            nom::Err::Error(err) | nom::Err::Failure(err) => match (err.span(), any_token(i)) {
+                (None, Ok((_, tk))) => nom::Err::Failure(PlumaError::InvalidSyntax(tk.span, Box::new(err))),
+                _ => nom::Err::Failure(err),
+            },
+            err => err,
+        })
+    }
+}
+
+fn skip_comments(i: Tokens, doc: bool) -> Tokens {
+    let mut i = i;
+    while let Some((tk, left)) = i.split_first() {
+        match tk.kind {
+            TokenKind::Comment(_) => i = left,
+            TokenKind::DocComment(_) if doc => i = left,
+            _ => break,
+        }
+    }
+    i
+}
+
+/// `a, b, c,` zero or more items with an optional trailing comma, not a lone `,`
+pub fn comma_list<'t, O, F>(f: F) -> impl Fn(Tokens<'t>) -> IResult<Tokens<'t>, Vec<O>, PlumaError>
+    where F: Fn(Tokens<'t>) -> IResult<Tokens<'t>, O, PlumaError> {
+    move |i| {
+        let (i, items) = opt(separated_nonempty_list(Control::Comma.matcher(), &f))(i)?;
+        match items {
+            Some(items) => map(opt(Control::Comma.matcher()), |_| ())(i).map(|(i, _)| (i, items)),
+            None => Ok((i, Vec::new())),
+        }
+    }
+}
+
+// every token matcher starts here, so comments never reach the grammar
+pub fn any_token(i: Tokens<'_>) -> IResult<Tokens<'_>, &Token, PlumaError> {
+    match skip_comments(i, true).split_first() {
+        Some((tk, left)) => Ok((left, tk)),
+        None => Err(nom::Err::Error(PlumaError::Needed(Needed::Size(1)))),
+    }
 }
 
-pub fn ws(i: &str) -> IResult<&str, (), PlumaError> {
-    map(one_of(" \t\n"), |_| ())(i)
+pub fn doc_comment(i: Tokens) -> IResult<Tokens, String, PlumaError> {
+    match skip_comments(i, false).split_first() {
+        Some((Token { kind: TokenKind::DocComment(doc), .. }, left)) => Ok((left, doc.clone())),
+        _ => Err(nom::Err::Error(PlumaError::NotDocComment)),
+    }
 }
 
-pub fn sep(i: &str) -> IResult<&str, (), PlumaError> {
-    map(one_of(";\n"), |_| ())(i)
+/// `///` lines before a define, newlines between them are skipped
+pub fn docs(i: Tokens) -> IResult<Tokens, Option<String>, PlumaError> {
+    map(
+        many0(alt((
+            map(newline, |_| None),
+            map(doc_comment, Some),
+        ))),
+        |lines| {
+            let lines = lines.into_iter().flatten().collect::<Vec<_>>();
+            if lines.is_empty() {
+                None
+            } else {
+                Some(lines.join("\n"))
+            }
+        },
+    )(i)
 }
 
-pub fn ws_sep(i: &str) -> IResult<&str, (), PlumaError> {
-    alt((
-        ws,
-        sep,
-    ))(i)
+// stops at doc comments so that they stay for the define below
+pub fn newline(i: Tokens) -> IResult<Tokens, (), PlumaError> {
+    match skip_comments(i, false).split_first() {
+        Some((Token { kind: TokenKind::Newline, .. }, left)) => Ok((left, ())),
+        _ => Err(nom::Err::Error(PlumaError::NotSeparator)),
+    }
+}
+
+/// one or more `;` or newline, `a;;\n\n b` has a single separator
+pub fn sep(i: Tokens) -> IResult<Tokens, (), PlumaError> {
+    map(
+        many1(alt((
+            newline,
+            Control::Semicolon.matcher(),
+        ))),
+        |_| (),
+    )(i)
+}
+
+/// equal by name only, the same name in two places is the same identifier
+#[derive(Debug, Clone)]
+pub struct Identifier {
+    name: String,
+    span: Span,
+}
+
+impl PartialEq for Identifier {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
 }
 
-#[derive(Debug, Eq, PartialEq, Clone, Ord, PartialOrd, Hash)]
-pub struct Identifier(String);
+impl Eq for Identifier {}
+
+impl PartialOrd for Identifier {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Identifier {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.name.cmp(&other.name)
+    }
+}
+
+impl Hash for Identifier {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.name.hash(state)
+    }
+}
 
 impl Identifier {
+    pub fn name(&self) -> &str {
+        self.name.as_str()
+    }
+    pub fn span(&self) -> Span {
+        self.span
+    }
+    // UAX #31 default identifiers, plus `_` at start
     pub fn ident_char(c: char) -> bool {
-        match c {
-            '_' => true,
-            'a'..='z' => true,
-            'A'..='Z' => true,
-            'ㄱ'..='ㅎ' => true,
-            'ㅏ'..='ㅣ' => true,
-            '가'..='힣' => true,
-            _ => false,
-        }
+        c == '_' || UnicodeXID::is_xid_start(c)
     }
     pub fn ident_char_and_numeric(c: char) -> bool {
-        match c {
-            '0'..='9' => true,
-            c => Self::ident_char(c),
+        UnicodeXID::is_xid_continue(c)
+    }
+    pub fn word(i: &str) -> IResult<&str, &str, PlumaError> {
+        recognize(pair(
+            take_while1(Self::ident_char),
+            take_while(Self::ident_char_and_numeric),
+        ))(i)
+    }
+    // names are NFC normalized, so identifiers equal when they look the same
+    pub fn lex(i: &str) -> IResult<&str, Self, PlumaError> {
+        let (left, word) = Self::word(i)?;
+        let name = word.nfc().collect::<String>();
+        match Keyword::from_str(name.as_str()) {
+            Ok(key) => Err(nom::Err::Error(PlumaError::ReservedWord(key))),
+            Err(_) => Ok((left, Identifier { name, span: Span::default() })),
         }
     }
 }
 
 impl<'t> NomTrait<'t> for Identifier {
-    fn nom(i: &'t str) -> IResult<&'t str, Self, PlumaError<'t>> {
-        map(
-            recognize(pair(
-                take_while1(Self::ident_char),
-                take_while(Self::ident_char_and_numeric),
-            )),
-            |x: &str| {
-                Identifier(String::from(x))
-            },
-        )(i)
+    fn nom(i: Tokens<'t>) -> IResult<Tokens<'t>, Self, PlumaError> {
+        let (left, tk) = any_token(i)?;
+        match &tk.kind {
+            TokenKind::Identifier(ident) => Ok((left, ident.clone())),
+            TokenKind::Keyword(key) => Err(nom::Err::Error(PlumaError::ReservedWord(*key))),
+            _ => Err(nom::Err::Error(PlumaError::NotIdentifier)),
+        }
+    }
+}
+
+/// equal by path only, like `Identifier`
+#[derive(Debug, Clone)]
+pub struct GlobalIdentifier {
+    path: Vec<Identifier>,
+    span: Span,
+}
+
+impl PartialEq for GlobalIdentifier {
+    fn eq(&self, other: &Self) -> bool {
+        self.path == other.path
     }
 }
 
-#[derive(Debug, Eq, PartialEq, Clone, Ord, PartialOrd, Hash)]
-pub struct GlobalIdentifier(Vec<Identifier>);
+impl Eq for GlobalIdentifier {}
+
+impl PartialOrd for GlobalIdentifier {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for GlobalIdentifier {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.path.cmp(&other.path)
+    }
+}
+
+impl Hash for GlobalIdentifier {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.path.hash(state)
+    }
+}
+
+impl GlobalIdentifier {
+    pub fn path(&self) -> &[Identifier] {
+        self.path.as_slice()
+    }
+    pub fn span(&self) -> Span {
+        self.span
+    }
+}
+
+impl From<Identifier> for GlobalIdentifier {
+    fn from(ident: Identifier) -> Self {
+        let span = ident.span();
+        GlobalIdentifier { path: vec![ident], span }
+    }
+}
 
 impl<'t> NomTrait<'t> for GlobalIdentifier {
-    fn nom(i: &'t str) -> IResult<&'t str, Self, PlumaError<'t>> {
+    fn nom(i: Tokens<'t>) -> IResult<Tokens<'t>, Self, PlumaError> {
         map(
-            separated_list(
+            spanned(separated_nonempty_list(
                 Control::Dot.matcher(),
                 Identifier::nom,
-            ),
-            |x| GlobalIdentifier(x),
+            )),
+            |(path, span)| GlobalIdentifier { path, span },
         )(i)
     }
 }
-//
-// fn integer(src: &str) -> IResult<&str, String, TokenError> {
-//     map(
-//         take_while1(|x: char| x.is_digit(10)),
-//         |x: &str| { x.to_string() },
-//     )(src)
-// }
-//
-// fn decimal(src: &str) -> IResult<&str, String, TokenError> {
-//     map(
-//         preceded(
-//             tag("."),
-//             take_while(|x: char| x.is_digit(10)),
-//         ),
-//         |x: &str| { ".".to_string() + x },
-//     )(src)
-// }
-//
-// fn literal(src: &str) -> IResult<&str, String, TokenError> {
-//     delimited(
-//         tag("\""),
-//         fold_many0(
-//             unescaped,
-//             String::new(),
-//             |mut s, c| {
-//                 s.push(c);
-//                 s
-//             },
-//         ),
-//         tag("\""),
-//     )(src)
-// }
-//
-// fn unescaped(src: &str) -> IResult<&str, char, TokenError> {
-//     match src.iter_elements().next().map(|t: char| {
-//         match t {
-//             '\\' => None,
-//             '\"' => None,
-//             '\'' => None,
-//             t => Some(t),
-//         }
-//     }) {
-//         Some(Some(c)) => Ok((src.slice(c.len()..), c)),
-//         Some(None) | _ => Err(nom::Err::Error(TokenError::UnknownInput(src.to_string()))),
-//     }
-// }
-//
-// fn sep(src: &str) -> IResult<&str, (), TokenError> {
-//     map(
-//         take_while1(|x: char| {
-//             match x {
-//                 ';' => true,
-//                 x => x.is_whitespace(),
-//             }
-//         }),
-//         |_| (),
-//     )(src)
-// }
-//
-// impl NomMatcher for Token {
-//     fn nom(src: &str) -> IResult<&str, Self, TokenError> {
-//         alt((
-//             map(sep, |_| Token::Whitespace),
-//             map(Control::nom, |c| Token::Control(c)),
-//             map(Keyword::nom, |k| Token::Keyword(k)),
-//             map(Identifier::nom, |id| Token::Identifier(id)),
-//             map(numeric, |x| Token::Numeric(x)),
-//             map(literal, |x| Token::Literal(x)),
-//         ))(src)
-//     }
-// }
-//
-// impl Token {
-//     pub fn tokenize_str(s: &str) -> Result<Vec<Token>, TokenError> {
-//         all_consuming(
-//             many0(Token::nom),
-//         )(s)
-//             .map(|(_, ok)| ok)
-//             .map_err(|err| {
-//                 match err {
-//                     nom::Err::Incomplete(_) => unreachable!(),
-//                     nom::Err::Error(err) => err,
-//                     nom::Err::Failure(err) => err,
-//                 }
-//             })
-//     }
-//     pub fn tokenize(src: &mut dyn Read) -> Result<Vec<Token>, TokenError> {
-//         let mut str_src = String::new();
-//         src.read_to_string(&mut str_src).map_err(|err| TokenError::IOError(err))?;
-//         Self::tokenize_str(str_src.as_str())
-//     }
-// }
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenKind {
+    Keyword(Keyword),
+    Control(Control),
+    Identifier(Identifier),
+    Numeric(Numeric),
+    Literal(String),
+    Character(char),
+    Byte(u8),
+    Newline,
+    Comment(String),
+    DocComment(String),
+}
+
+// `\r` is blank so `\r\n` ends a line like `\n`
+fn blank(src: &str) -> IResult<&str, (), PlumaError> {
+    map(take_while(|x| x == ' ' || x == '\t' || x == '\r'), |_| ())(src)
+}
+
+fn comment(src: &str) -> IResult<&str, String, PlumaError> {
+    map(
+        preceded(tag("//"), take_till(|x| x == '\n' || x == '\r')),
+        |x: &str| x.to_string(),
+    )(src)
+}
+
+// `////` is a plain comment again
+fn doc(src: &str) -> IResult<&str, String, PlumaError> {
+    map(
+        preceded(pair(tag("///"), not(tag("/"))), take_till(|x| x == '\n' || x == '\r')),
+        |x: &str| x.to_string(),
+    )(src)
+}
+
+// block comments nest, `/* a /* b */ c */` is one comment
+fn block_comment(src: &str) -> IResult<&str, String, PlumaError> {
+    let (mut i, _) = tag("/*")(src)?;
+    let mut depth = 1;
+    while depth > 0 {
+        if i.starts_with("/*") {
+            depth += 1;
+            i = &i[2..];
+        } else if i.starts_with("*/") {
+            depth -= 1;
+            i = &i[2..];
+        } else {
+            match i.chars().next() {
+                Some(c) => i = &i[c.len_utf8()..],
+                None => return Err(nom::Err::Failure(PlumaError::UnterminatedComment)),
+            }
+        }
+    }
+    Ok((i, String::from(&src[2..src.len() - i.len() - 2])))
+}
+
+impl TokenKind {
+    /// whether a newline right after this token ends the statement
+    pub fn terminates(&self) -> bool {
+        match self {
+            TokenKind::Identifier(_) | TokenKind::Numeric(_) | TokenKind::Literal(_) => true,
+            TokenKind::Character(_) | TokenKind::Byte(_) => true,
+            TokenKind::Keyword(key) => match key {
+                Keyword::Str | Keyword::True | Keyword::False => true,
+                Keyword::Break | Keyword::Continue | Keyword::Return => true,
+                key => Primitive::from_keyword(*key).is_some(),
+            },
+            TokenKind::Control(Control::GroupEnd | Control::ArrayEnd | Control::BlockEnd) => true,
+            _ => false,
+        }
+    }
+    // type names and what may stand between `<` and `>` of generics
+    fn in_generics(&self) -> bool {
+        match self {
+            TokenKind::Identifier(_) | TokenKind::Numeric(_) => true,
+            TokenKind::Comment(_) | TokenKind::DocComment(_) => true,
+            TokenKind::Keyword(key) => match key {
+                Keyword::Str | Keyword::Fn | Keyword::Var | Keyword::Const | Keyword::Ref => true,
+                key => Primitive::from_keyword(*key).is_some(),
+            },
+            TokenKind::Control(Control::Comma | Control::Dot | Control::Arrow) => true,
+            TokenKind::Control(Control::GroupStart | Control::GroupEnd | Control::ArrayStart | Control::ArrayEnd) => true,
+            _ => false,
+        }
+    }
+    pub fn lex(src: &str) -> IResult<&str, Self, PlumaError> {
+        alt((
+            map(tag("\n"), |_| TokenKind::Newline),
+            map(doc, TokenKind::DocComment),
+            map(comment, TokenKind::Comment),
+            map(block_comment, TokenKind::Comment),
+            map(literal::string, TokenKind::Literal),
+            map(literal::character, TokenKind::Character),
+            map(literal::byte, TokenKind::Byte),
+            map(literal::numeric, TokenKind::Numeric),
+            map(Keyword::lex, TokenKind::Keyword),
+            map(Identifier::lex, TokenKind::Identifier),
+            map(Control::lex, TokenKind::Control),
+        ))(src)
+    }
+}
+
+impl Token {
+    pub fn tokenize_str(s: &str) -> Result<Vec<Token>, PlumaError> {
+        Self::tokenize_file(FileId::default(), s)
+    }
+    /// Newlines follow Go's automatic semicolon insertion, a `Newline` token is kept only when
+    ///
+    /// * the last token of the line can end a statement, see `TokenKind::terminates`,
+    ///   a `>` only when it closes generics as in `var b Box<i32>` so that `a >` goes on below
+    /// * it is not inside `(...)` or `[...]`, where lines are always joined
+    /// * no separator is right before it, so blank lines collapse
+    ///
+    /// A block comment spanning lines counts as a newline.
+    pub fn tokenize_file(file: FileId, s: &str) -> Result<Vec<Token>, PlumaError> {
+        let offset = |x: &str| s.len() - x.len();
+        let mut tokens: Vec<Token> = Vec::new();
+        let mut brackets = Vec::new();
+        let mut i = s;
+        loop {
+            let (left, _) = blank(i).map_err(|_| PlumaError::NotToken(Span::new(file, offset(i), offset(i))))?;
+            if left.is_empty() {
+                break;
+            }
+            let at = Span::new(file, offset(left), offset(left));
+            let (next, mut kind) = TokenKind::lex(left).map_err(|err| match err {
+                nom::Err::Failure(err) => PlumaError::InvalidToken(at, Box::new(err)),
+                _ => PlumaError::NotToken(at),
+            })?;
+            let span = Span::new(file, offset(left), offset(next));
+            i = next;
+            if let TokenKind::Identifier(ident) = &mut kind {
+                ident.span = span;
+            }
+            let line_end = match &kind {
+                TokenKind::Newline => true,
+                TokenKind::Comment(text) => text.contains('\n'),
+                _ => false,
+            };
+            match &kind {
+                TokenKind::Control(ctl @ Control::GroupStart)
+                | TokenKind::Control(ctl @ Control::ArrayStart)
+                | TokenKind::Control(ctl @ Control::BlockStart) => brackets.push(*ctl),
+                TokenKind::Control(Control::GroupEnd)
+                | TokenKind::Control(Control::ArrayEnd)
+                | TokenKind::Control(Control::BlockEnd) => {
+                    brackets.pop();
+                }
+                _ => {}
+            }
+            let newline = match kind {
+                TokenKind::Newline => span,
+                _ => {
+                    tokens.push(Token { kind, span });
+                    Span::new(file, span.end, span.end)
+                }
+            };
+            if line_end {
+                let joined = matches!(brackets.last(), Some(Control::GroupStart) | Some(Control::ArrayStart));
+                let last = tokens.iter()
+                    .rev()
+                    .find(|tk| !matches!(tk.kind, TokenKind::Comment(_) | TokenKind::DocComment(_)));
+                let terminated = match last {
+                    Some(Token { kind: TokenKind::Control(Control::GenericEnd), .. }) => Self::closes_generics(&tokens),
+                    Some(tk) => tk.kind.terminates(),
+                    None => false,
+                };
+                if !joined && terminated {
+                    tokens.push(Token { kind: TokenKind::Newline, span: newline });
+                }
+            }
+        }
+        Ok(tokens)
+    }
+    // whether the `>` at the end of `tokens` has its `<` before it with only a type in between
+    fn closes_generics(tokens: &[Token]) -> bool {
+        let mut depth = 0;
+        for tk in tokens.iter().rev() {
+            match tk.kind {
+                TokenKind::Control(Control::GenericEnd) => depth += 1,
+                TokenKind::Control(Control::GenericStart) => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return true;
+                    }
+                }
+                ref kind if kind.in_generics() => (),
+                _ => return false,
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pluma::ir::ast::SourceFile;
+    use crate::pluma::ir::types::Type;
+
+    fn kinds(src: &str) -> Vec<TokenKind> {
+        Token::tokenize_str(src).unwrap().into_iter().map(|tk| tk.kind).collect()
+    }
+
+    fn newlines(src: &str) -> usize {
+        kinds(src).iter().filter(|kind| **kind == TokenKind::Newline).count()
+    }
+
+    fn ident(name: &str) -> TokenKind {
+        TokenKind::Identifier(Identifier { name: name.to_string(), span: Span::default() })
+    }
+
+    #[test]
+    fn whole_words() {
+        assert_eq!(kinds("index iffy in_ for2 fn"), vec![ident("index"), ident("iffy"), ident("in_"), ident("for2"), TokenKind::Keyword(Keyword::Fn)]);
+        assert_eq!(kinds("if(a)"), vec![
+            TokenKind::Keyword(Keyword::If),
+            TokenKind::Control(Control::GroupStart),
+            ident("a"),
+            TokenKind::Control(Control::GroupEnd),
+        ]);
+        assert_eq!(kinds("i32 i320 u8x"), vec![TokenKind::Keyword(Keyword::I32), ident("i320"), ident("u8x")]);
+    }
+
+    #[test]
+    fn reserved_words() {
+        for (word, key) in &[("return", Keyword::Return), ("str", Keyword::Str), ("true", Keyword::True)] {
+            match Identifier::lex(word) {
+                Err(nom::Err::Error(PlumaError::ReservedWord(found))) => assert_eq!(found, *key),
+                res => panic!("{} {:?}", word, res),
+            }
+            match parse_str::<Identifier>(word) {
+                Err(PlumaError::ReservedWord(found)) => assert_eq!(found, *key),
+                res => panic!("{} {:?}", word, res),
+            }
+        }
+        assert_eq!(parse_str::<Identifier>("returns").map(|x| x.name().to_string()).ok(), Some("returns".to_string()));
+    }
+
+    #[test]
+    fn spans() {
+        let file = FileId(2);
+        let spans = Token::tokenize_file(file, "var ab = 12\n  f()").unwrap().into_iter()
+            .map(|tk| (tk.span.start, tk.span.end))
+            .collect::<Vec<_>>();
+        assert_eq!(spans, vec![(0, 3), (4, 6), (7, 8), (9, 11), (11, 12), (14, 15), (15, 16), (16, 17)]);
+        assert!(Token::tokenize_file(file, "a b").unwrap().iter().all(|tk| tk.span.file == file));
+        match Token::tokenize_str("a $ b") {
+            Err(PlumaError::NotToken(span)) => assert_eq!((span.start, span.end), (2, 2)),
+            res => panic!("{:?}", res),
+        }
+        match Token::tokenize_str("f(\"ab") {
+            Err(PlumaError::InvalidToken(span, err)) => match *err {
+                PlumaError::UnterminatedLiteral => assert_eq!(span.start, 2),
+                err => panic!("{:?}", err),
+            },
+            res => panic!("{:?}", res),
+        }
+    }
+
+    fn controls(src: &str) -> Vec<Control> {
+        kinds(src).into_iter()
+            .map(|kind| match kind {
+                TokenKind::Control(ctl) => ctl,
+                kind => panic!("{} {:?}", src, kind),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn longest_controls() {
+        assert_eq!(controls("-> => == != <= && || <<"), vec![
+            Control::Arrow, Control::FatArrow, Control::EqualEqual, Control::NotEqual,
+            Control::LessEqual, Control::AndAnd, Control::OrOr, Control::ShiftLeft,
+        ]);
+        assert_eq!(controls("+= -= *= /= %= .. ::"), vec![
+            Control::PlusEqual, Control::MinusEqual, Control::MulEqual, Control::SlashEqual,
+            Control::ModEqual, Control::DotDot, Control::Path,
+        ]);
+        assert_eq!(controls("...=<<<"), vec![Control::DotDot, Control::Dot, Control::Equal, Control::ShiftLeft, Control::GenericStart]);
+        assert_eq!(controls("&|!"), vec![Control::And, Control::Or, Control::Not]);
+    }
+
+    #[test]
+    fn glued_greater() {
+        let any = |src: &str| {
+            let tokens = Token::tokenize_str(src).unwrap();
+            Control::any_matcher()(&tokens).map(|(left, ctl)| (ctl, left.len())).ok()
+        };
+        assert_eq!(controls(">>="), vec![Control::GenericEnd, Control::GenericEnd, Control::Equal]);
+        assert_eq!(any(">>"), Some((Control::ShiftRight, 0)));
+        assert_eq!(any(">= 1"), Some((Control::GreaterEqual, 1)));
+        assert_eq!(any("> >"), Some((Control::GenericEnd, 1)));
+        assert_eq!(any("> ="), Some((Control::GenericEnd, 1)));
+        let tokens = Token::tokenize_str(">>").unwrap();
+        assert_eq!(Control::GenericEnd.matcher()(&tokens).map(|(left, _)| left.len()).ok(), Some(1));
+    }
+
+    #[test]
+    fn unicode_identifiers() {
+        assert_eq!(kinds("名前 _x café δ1"), vec![ident("名前"), ident("_x"), ident("café"), ident("δ1")]);
+        // the decomposed `e` and accent is the same name as the composed `é`
+        match kinds("cafe\u{301}").as_slice() {
+            [TokenKind::Identifier(x)] => assert_eq!(x.name(), "caf\u{e9}"),
+            kinds => panic!("{:?}", kinds),
+        }
+        match Token::tokenize_str("a €") {
+            Err(PlumaError::NotToken(span)) => assert_eq!(span.start, 2),
+            res => panic!("{:?}", res),
+        }
+    }
+
+    #[test]
+    fn blank_runs() {
+        assert_eq!(kinds("fn main()  {\n\t}"), kinds("fn main() {\n}"));
+        assert_eq!(newlines("a\n\n\n  \n\tb\n"), 2);
+        assert_eq!(newlines("\n\n"), 0);
+    }
+
+    #[test]
+    fn crlf() {
+        assert_eq!(kinds("var a = 1\r\nb()\r\n"), kinds("var a = 1\nb()\n"));
+        assert_eq!(kinds("a // x\r\nb"), kinds("a // x\nb"));
+    }
+
+    #[test]
+    fn terminating_tokens() {
+        for src in &["a\n", "1\n", "\"a\"\n", "'a'\n", "f()\n", "x[0]\n", "{}\n", "i32\n", "true\n", "return\n", "break\n", "continue\n"] {
+            assert_eq!(newlines(src), 1, "{:?}", src);
+        }
+        for src in &["a +\n", "a =\n", "a,\n", "{\n", "fn\n", "var\n", "if\n"] {
+            assert_eq!(newlines(src), 0, "{:?}", src);
+        }
+    }
+
+    #[test]
+    fn joined_in_brackets() {
+        assert_eq!(newlines("f(a,\n b\n)"), 0);
+        assert_eq!(newlines("[1,\n 2\n]"), 0);
+        assert_eq!(newlines("{\n a\n}"), 1);
+        // a block inside parentheses still keeps its lines
+        assert_eq!(newlines("f({\n a\n})"), 1);
+    }
+
+    #[test]
+    fn generics_end() {
+        assert_eq!(newlines("a >\n b"), 0);
+        assert_eq!(newlines("a <\n b"), 0);
+        assert_eq!(newlines("a > b\n"), 1);
+        assert_eq!(newlines("var b Box<i32>\n"), 1);
+        assert_eq!(newlines("var m Map<str, List<i32>>\n"), 1);
+        assert_eq!(newlines("var f Box<fn(i32) -> i32>\n"), 1);
+    }
+
+    #[test]
+    fn comments() {
+        assert_eq!(newlines("a // x\nb"), 1);
+        assert_eq!(newlines("a /* x\n y */ b"), 1);
+        assert_eq!(newlines("a /* x */\nb"), 1);
+        assert_eq!(newlines("a + // x\nb"), 0);
+    }
+
+    #[test]
+    fn comment_tokens() {
+        let comment = |x: &str| TokenKind::Comment(x.to_string());
+        assert_eq!(kinds("a // x"), vec![ident("a"), comment(" x")]);
+        assert_eq!(kinds("/* a /* b */ c */ d"), vec![comment(" a /* b */ c "), ident("d")]);
+        assert_eq!(kinds("/// doc\n//// plain"), vec![TokenKind::DocComment(" doc".to_string()), comment("// plain")]);
+        match Token::tokenize_str("a /* b /* c */") {
+            Err(PlumaError::InvalidToken(span, err)) => match *err {
+                PlumaError::UnterminatedComment => assert_eq!(span.start, 2),
+                err => panic!("{:?}", err),
+            },
+            res => panic!("{:?}", res),
+        }
+    }
+
+    #[test]
+    fn whole_input() {
+        assert!(parse_str::<Type>("i32\n").is_ok());
+        match parse_str::<Type>("i32\nfoo") {
+            Err(PlumaError::Left(Token { kind: TokenKind::Identifier(ident), .. })) => assert_eq!(ident.name(), "foo"),
+            Err(err) => panic!("{:?}", err),
+            Ok(tp) => panic!("{:?}", tp),
+        }
+        assert!(parse_str::<SourceFile>("fn main()  {\r\n\r\n    var a = 1 +\r\n        2\r\n}\r\n").is_ok());
+    }
+}